@@ -0,0 +1,116 @@
+// Frame time benchmark for the rasteriser. Run with `cargo bench`.
+// Uses the models in ./models when they exist, otherwise spheres with roughly as many triangles
+// as them are generated so the numbers are still comparable between changes.
+use gfxprogramming::Rasteriser;
+use gfxprogramming::SampleCount;
+use gfxprogramming::SimdLevel;
+use std::fmt::Write as _;
+use std::path::Path;
+use std::time::Instant;
+
+const WIDTH: usize = 1000;
+const HEIGHT: usize = 1000;
+// Every Rasteriser's turntable spins its models around the origin by a degree each frame, so this
+// is kept low enough that the spheres never swing round behind the camera
+const FRAMES: usize = 5;
+
+// Writes a sphere made of about `triangles` triangles, sat in front of the camera
+fn write_sphere(path: &Path, triangles: usize) {
+    let rings = ((triangles / 2) as f32).sqrt().max(3.) as usize;
+    let segments = rings * 2;
+    let mut obj = String::new();
+    for i in 0..=rings {
+        let theta = std::f32::consts::PI * i as f32 / rings as f32;
+        for j in 0..segments {
+            let phi = 2. * std::f32::consts::PI * j as f32 / segments as f32;
+            let (x, y, z) = (
+                theta.sin() * phi.cos(),
+                theta.cos(),
+                theta.sin() * phi.sin(),
+            );
+            writeln!(obj, "v {} {} {}", x * 2.5, y * 2.5, z * 2.5 - 4.).unwrap();
+        }
+    }
+    for i in 0..rings {
+        for j in 0..segments {
+            let a = i * segments + j + 1;
+            let b = i * segments + (j + 1) % segments + 1;
+            let (c, d) = (a + segments, b + segments);
+            writeln!(obj, "f {} {} {}", a, c, b).unwrap();
+            writeln!(obj, "f {} {} {}", b, c, d).unwrap();
+        }
+    }
+    std::fs::write(path, obj).unwrap();
+}
+
+fn bench(
+    name: &str,
+    obj_path: &str,
+    sample_count: SampleCount,
+    threads: usize,
+    simd_level: SimdLevel,
+) {
+    let mut r = Rasteriser::new(WIDTH, HEIGHT);
+    r.set_sample_count(sample_count);
+    r.set_threads(threads);
+    r.set_simd_level(simd_level);
+    r.load_obj(obj_path);
+    // warm up
+    r.render_frame();
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        r.render_frame();
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{:<40} {:>3}x {:>3} threads {:<6} {:>8.2} ms/frame",
+        name,
+        sample_count.samples(),
+        r.threads(),
+        format!("{:?}", r.simd_level()),
+        elapsed.as_secs_f64() * 1000. / FRAMES as f64
+    );
+}
+
+// Single threaded without and with SIMD, then across every core
+fn bench_all(name: &str, obj_path: &str) {
+    let cores = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+    let simd_level = SimdLevel::detect();
+    for sample_count in [SampleCount::X1, SampleCount::X4] {
+        // the SIMD path is only used for single sampled rendering
+        if sample_count == SampleCount::X1 && simd_level != SimdLevel::Scalar {
+            bench(name, obj_path, sample_count, 1, SimdLevel::Scalar);
+        }
+        bench(name, obj_path, sample_count, 1, simd_level);
+        if cores > 1 {
+            bench(name, obj_path, sample_count, cores, simd_level);
+        }
+    }
+}
+
+fn main() {
+    let models = [
+        "./models/african_head.obj",
+        "./models/teapot.obj",
+        "./models/Sora_KH1/Sora_KH1.obj",
+    ];
+    let mut found = false;
+    for model in models {
+        if Path::new(model).exists() {
+            found = true;
+            bench_all(model, model);
+        }
+    }
+    if found {
+        return;
+    }
+
+    // african_head, teapot and a character model respectively
+    let dir = std::env::temp_dir();
+    for triangles in [2_500, 6_300, 25_000] {
+        let path = dir.join(format!("rusteriser_bench_sphere_{}.obj", triangles));
+        write_sphere(&path, triangles);
+        let name = format!("sphere ({} triangles)", triangles);
+        bench_all(&name, path.to_str().unwrap());
+    }
+}
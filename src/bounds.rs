@@ -0,0 +1,171 @@
+use cgmath::point3;
+use cgmath::EuclideanSpace;
+use cgmath::InnerSpace;
+use cgmath::Matrix4;
+use cgmath::MetricSpace;
+use cgmath::Point3;
+use cgmath::Transform;
+use cgmath::Vector4;
+
+// Axis aligned bounding box
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    // Contains nothing, so the union with anything is that thing
+    pub fn empty() -> Aabb {
+        Aabb {
+            min: point3(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+            max: point3(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+        }
+    }
+
+    pub fn from_points<'a>(points: impl IntoIterator<Item = &'a Point3<f32>>) -> Aabb {
+        points
+            .into_iter()
+            .fold(Aabb::empty(), |aabb, &p| aabb.union_point(p))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: point3(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            max: point3(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        }
+    }
+
+    pub fn union_point(&self, p: Point3<f32>) -> Aabb {
+        self.union(&Aabb { min: p, max: p })
+    }
+
+    pub fn center(&self) -> Point3<f32> {
+        self.min.midpoint(self.max)
+    }
+
+    // The box around this one after transforming it, bigger than it needs to be under rotations
+    pub fn transform(&self, m: &Matrix4<f32>) -> Aabb {
+        if self.is_empty() {
+            return *self;
+        }
+        let corners = (0..8).map(|i| {
+            let pick = |bit: usize, min: f32, max: f32| if i & bit == 0 { min } else { max };
+            m.transform_point(point3(
+                pick(1, self.min.x, self.max.x),
+                pick(2, self.min.y, self.max.y),
+                pick(4, self.min.z, self.max.z),
+            ))
+        });
+        corners.fold(Aabb::empty(), |aabb, p| aabb.union_point(p))
+    }
+
+    // 0, 1 or 2 for x, y or z
+    pub fn longest_axis(&self) -> usize {
+        let size = self.max - self.min;
+        if size.x >= size.y && size.x >= size.z {
+            0
+        } else if size.y >= size.z {
+            1
+        } else {
+            2
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Point3<f32>,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    // Centred on the points' bounding box, which isn't the smallest sphere but is never far off
+    pub fn from_points<'a>(
+        points: impl IntoIterator<Item = &'a Point3<f32>> + Clone,
+    ) -> BoundingSphere {
+        let center = Aabb::from_points(points.clone()).center();
+        let radius = points
+            .into_iter()
+            .map(|&p| center.distance2(p))
+            .fold(0., f32::max)
+            .sqrt();
+        BoundingSphere { center, radius }
+    }
+}
+
+// Whether a bounding volume is outside, inside or on the edge of a frustum
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Containment {
+    Outside,
+    Inside,
+    Intersecting,
+}
+
+// The six planes of a view frustum, a point p is inside a plane when dot(plane, (p, 1)) >= 0
+#[derive(Clone, Copy, Debug)]
+pub struct Frustum {
+    pub planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    // Frustum of a clip space matrix in whichever space the matrix maps from, so passing
+    // projection * model_view gives it in model space and bounds can be tested without
+    // transforming them. Clip space is -w..w on every axis like cgmath::perspective produces.
+    pub fn from_matrix(m: Matrix4<f32>) -> Frustum {
+        let row = |i: usize| Vector4::new(m.x[i], m.y[i], m.z[i], m.w[i]);
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        let planes = [w + x, w - x, w + y, w - y, w + z, w - z].map(|plane| {
+            // normalised so the distances to the planes are real distances for sphere tests
+            plane / plane.truncate().magnitude()
+        });
+        Frustum { planes }
+    }
+
+    pub fn test_sphere(&self, sphere: &BoundingSphere) -> Containment {
+        let mut containment = Containment::Inside;
+        for plane in &self.planes {
+            let distance = plane.truncate().dot(sphere.center.to_vec()) + plane.w;
+            if distance < -sphere.radius {
+                return Containment::Outside;
+            }
+            if distance < sphere.radius {
+                containment = Containment::Intersecting;
+            }
+        }
+        containment
+    }
+
+    pub fn test_aabb(&self, aabb: &Aabb) -> Containment {
+        let mut containment = Containment::Inside;
+        for plane in &self.planes {
+            // the corners furthest along and furthest against the plane's normal
+            let (mut far, mut near) = (aabb.max, aabb.min);
+            for axis in 0..3 {
+                if plane[axis] < 0. {
+                    std::mem::swap(&mut far[axis], &mut near[axis]);
+                }
+            }
+            let distance = |p: Point3<f32>| plane.truncate().dot(p.to_vec()) + plane.w;
+            if distance(far) < 0. {
+                return Containment::Outside;
+            }
+            if distance(near) < 0. {
+                containment = Containment::Intersecting;
+            }
+        }
+        containment
+    }
+}
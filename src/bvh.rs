@@ -0,0 +1,125 @@
+use crate::Aabb;
+use crate::Containment;
+use crate::Frustum;
+use cgmath::Point3;
+use std::ops::Range;
+
+// Most triangles a leaf holds before it gets split
+const MAX_LEAF_TRIANGLES: usize = 4;
+
+#[derive(Clone, Debug)]
+pub struct BvhNode {
+    pub bounds: Aabb,
+    // range of Bvh::triangles below this node
+    pub start: u32,
+    pub end: u32,
+    // index of the first of the two children, 0 for leaves as the root is never a child
+    pub children: u32,
+}
+
+impl BvhNode {
+    pub fn is_leaf(&self) -> bool {
+        self.children == 0
+    }
+}
+
+// Bounding volume hierarchy over a list of triangles, built by splitting the triangles in half
+// along the longest axis of their centres until they're small enough
+#[derive(Clone, Debug, Default)]
+pub struct Bvh {
+    // the root is nodes[0]
+    pub nodes: Vec<BvhNode>,
+    // triangle indices, ordered so every node's triangles are next to each other
+    pub triangles: Vec<u32>,
+}
+
+impl Bvh {
+    // Builds a tree over triangles[range]
+    pub fn new(triangles: &[[Point3<f32>; 3]], range: Range<usize>) -> Bvh {
+        let first = range.start;
+        let triangles = &triangles[range];
+        let mut bvh = Bvh {
+            nodes: Vec::with_capacity(2 * triangles.len().div_ceil(MAX_LEAF_TRIANGLES)),
+            triangles: (first as u32..(first + triangles.len()) as u32).collect(),
+        };
+        if triangles.is_empty() {
+            return bvh;
+        }
+        // indexed by triangle index - first
+        let bounds: Vec<Aabb> = triangles.iter().map(Aabb::from_points).collect();
+        let centers: Vec<Point3<f32>> = bounds.iter().map(|b| b.center()).collect();
+
+        bvh.nodes.push(BvhNode {
+            bounds: Aabb::empty(),
+            start: 0,
+            end: triangles.len() as u32,
+            children: 0,
+        });
+        let mut stack = vec![0];
+        while let Some(node) = stack.pop() {
+            let (start, end) = (bvh.nodes[node].start as usize, bvh.nodes[node].end as usize);
+            let indices = &mut bvh.triangles[start..end];
+            bvh.nodes[node].bounds = indices
+                .iter()
+                .fold(Aabb::empty(), |b, &i| b.union(&bounds[i as usize - first]));
+            if indices.len() <= MAX_LEAF_TRIANGLES {
+                continue;
+            }
+
+            let axis = Aabb::from_points(indices.iter().map(|&i| &centers[i as usize - first]))
+                .longest_axis();
+            let mid = indices.len() / 2;
+            indices.select_nth_unstable_by(mid, |&a, &b| {
+                let a = centers[a as usize - first][axis];
+                let b = centers[b as usize - first][axis];
+                a.total_cmp(&b)
+            });
+
+            let children = bvh.nodes.len();
+            bvh.nodes[node].children = children as u32;
+            for (start, end) in [(start, start + mid), (start + mid, end)] {
+                bvh.nodes.push(BvhNode {
+                    bounds: Aabb::empty(),
+                    start: start as u32,
+                    end: end as u32,
+                    children: 0,
+                });
+            }
+            stack.push(children);
+            stack.push(children + 1);
+        }
+        bvh
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        self.nodes.first().map_or(Aabb::empty(), |root| root.bounds)
+    }
+
+    // Adds the indices of the triangles that might be inside the frustum to visible, in no
+    // particular order. Triangles in leaves on the frustum's edge are tested one by one, so
+    // triangles has to be the same list the tree was built from.
+    pub fn cull(&self, frustum: &Frustum, triangles: &[[Point3<f32>; 3]], visible: &mut Vec<u32>) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut stack = vec![0];
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+            let node_triangles = &self.triangles[node.start as usize..node.end as usize];
+            match frustum.test_aabb(&node.bounds) {
+                Containment::Outside => {}
+                Containment::Inside => visible.extend_from_slice(node_triangles),
+                Containment::Intersecting if node.is_leaf() => {
+                    visible.extend(node_triangles.iter().filter(|&&i| {
+                        let bounds = Aabb::from_points(&triangles[i as usize]);
+                        frustum.test_aabb(&bounds) != Containment::Outside
+                    }));
+                }
+                Containment::Intersecting => {
+                    stack.push(node.children as usize);
+                    stack.push(node.children as usize + 1);
+                }
+            }
+        }
+    }
+}
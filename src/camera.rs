@@ -0,0 +1,68 @@
+use crate::Aabb;
+use cgmath::perspective;
+use cgmath::point3;
+use cgmath::vec3;
+use cgmath::Angle;
+use cgmath::Deg;
+use cgmath::InnerSpace;
+use cgmath::Matrix4;
+use cgmath::Point3;
+use cgmath::Vector3;
+
+// Perspective camera, the default one sits at the origin looking down -z
+#[derive(Clone, Copy, Debug)]
+pub struct Camera {
+    pub position: Point3<f32>,
+    pub target: Point3<f32>,
+    pub up: Vector3<f32>,
+    // vertical field of view
+    pub fov: Deg<f32>,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Camera {
+        Camera {
+            position: point3(0., 0., 0.),
+            target: point3(0., 0., -1.),
+            up: vec3(0., 1., 0.),
+            fov: Deg(90.),
+            near: 0.1,
+            far: 100.,
+        }
+    }
+}
+
+impl Camera {
+    // World space to view space
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        Matrix4::look_at_rh(self.position, self.target, self.up)
+    }
+
+    // View space to clip space
+    pub fn projection_matrix(&self, aspect: f32) -> Matrix4<f32> {
+        perspective(self.fov, aspect, self.near, self.far)
+    }
+
+    // This camera moved along its view direction until all of bounds fits in the view, looking
+    // at its centre, with near and far planes that fit around it
+    pub fn framing(&self, bounds: &Aabb, aspect: f32) -> Camera {
+        let radius = (bounds.max - bounds.min).magnitude() / 2.;
+        if bounds.is_empty() || radius == 0. {
+            return *self;
+        }
+        // the narrower of the vertical and horizontal fields of view
+        let tan = Deg::tan(self.fov / 2.) * aspect.min(1.);
+        let distance = radius * (1. + tan * tan).sqrt() / tan;
+        let direction = (self.target - self.position).normalize();
+        let target = bounds.center();
+        Camera {
+            position: target - direction * distance,
+            target,
+            near: (distance - radius).max(distance / 1000.) / 2.,
+            far: (distance + radius) * 2.,
+            ..*self
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use crate::Camera;
+use crate::Event;
+use crate::Key;
+use crate::MouseButton;
+use crate::Rasteriser;
+use cgmath::Deg;
+use cgmath::InnerSpace;
+use cgmath::Matrix3;
+use cgmath::Rad;
+use cgmath::Vector3;
+use std::collections::HashSet;
+
+// Radians the camera turns per pixel the mouse is dragged
+const TURN_SPEED: f32 = 0.005;
+// How much closer each step of the scroll wheel takes the camera to its target
+const ZOOM_STEP: f32 = 0.9;
+// Shift multiplies the flying speed by this
+const FAST: f32 = 4.;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CameraMode {
+    // dragging with the left button swings the camera around its target
+    Orbit,
+    // dragging with the left button turns the camera where it stands
+    Fly,
+}
+
+// Moves a rasteriser's camera around from a frontend's input:
+//
+//     left drag     orbit around the target, or look around in fly mode
+//     middle drag   pan
+//     scroll        zoom towards or away from the target
+//     W A S D       fly forwards, left, backwards and right
+//     Q E           fly down and up, Shift to go faster
+//     Tab           switch between orbit and fly mode
+//     F or Home     back to the starting view, framing the whole scene
+pub struct CameraController {
+    pub mode: CameraMode,
+    // units per second when flying
+    pub speed: f32,
+    // the camera the controller started with, which reset() frames the scene from
+    home: Camera,
+    keys_down: HashSet<Key>,
+    buttons_down: HashSet<MouseButton>,
+    mouse_position: Option<(f32, f32)>,
+}
+
+impl CameraController {
+    pub fn new(rasteriser: &Rasteriser) -> CameraController {
+        let bounds = rasteriser.scene.bounds();
+        let size = match bounds.is_empty() {
+            true => 1.,
+            false => (bounds.max - bounds.min).magnitude(),
+        };
+        CameraController {
+            mode: CameraMode::Orbit,
+            // crosses the scene in about two seconds
+            speed: size.max(f32::EPSILON) / 2.,
+            home: rasteriser.camera,
+            keys_down: HashSet::new(),
+            buttons_down: HashSet::new(),
+            mouse_position: None,
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &Event, rasteriser: &mut Rasteriser) {
+        let (_, height) = rasteriser.size();
+        let camera = &mut rasteriser.camera;
+        match *event {
+            Event::KeyDown(key) => {
+                self.keys_down.insert(key);
+                match key {
+                    Key::Tab => {
+                        self.mode = match self.mode {
+                            CameraMode::Orbit => CameraMode::Fly,
+                            CameraMode::Fly => CameraMode::Orbit,
+                        }
+                    }
+                    Key::Char('f') | Key::Home => self.reset(rasteriser),
+                    _ => {}
+                }
+            }
+            Event::KeyUp(key) => {
+                self.keys_down.remove(&key);
+            }
+            Event::MouseDown(button) => {
+                self.buttons_down.insert(button);
+            }
+            Event::MouseUp(button) => {
+                self.buttons_down.remove(&button);
+            }
+            Event::MouseMove { x, y } => {
+                if let Some((last_x, last_y)) = self.mouse_position {
+                    let (dx, dy) = (x - last_x, y - last_y);
+                    if self.buttons_down.contains(&MouseButton::Left) {
+                        match self.mode {
+                            CameraMode::Orbit => orbit(camera, dx, dy),
+                            CameraMode::Fly => look(camera, dx, dy),
+                        }
+                    } else if self.buttons_down.contains(&MouseButton::Middle) {
+                        pan(camera, dx, dy, height);
+                    }
+                }
+                self.mouse_position = Some((x, y));
+            }
+            Event::Scroll { y, .. } => {
+                let offset = camera.position - camera.target;
+                let distance = offset.magnitude() * ZOOM_STEP.powf(y);
+                // stops short of the target, so the view direction stays defined
+                camera.position = camera.target + offset.normalize() * distance.max(camera.near);
+            }
+            Event::Close => {}
+        }
+    }
+
+    // Flies the camera with the keys held down, dt being the seconds since the last update
+    pub fn update(&mut self, dt: f32, rasteriser: &mut Rasteriser) {
+        let held = |key: char| self.keys_down.contains(&Key::Char(key)) as i32 as f32;
+        let (forward, right, up) = camera_axes(&rasteriser.camera);
+        let movement = forward * (held('w') - held('s'))
+            + right * (held('d') - held('a'))
+            + up * (held('e') - held('q'));
+        if movement == Vector3::new(0., 0., 0.) {
+            return;
+        }
+        let fast = match self.keys_down.contains(&Key::Shift) {
+            true => FAST,
+            false => 1.,
+        };
+        let step = movement.normalize() * self.speed * fast * dt;
+        let camera = &mut rasteriser.camera;
+        camera.position += step;
+        camera.target += step;
+    }
+
+    // The starting camera's direction and field of view, moved to take in everything in the
+    // scene
+    pub fn reset(&self, rasteriser: &mut Rasteriser) {
+        let (width, height) = rasteriser.size();
+        rasteriser.camera = self
+            .home
+            .framing(&rasteriser.scene.bounds(), width as f32 / height as f32);
+    }
+}
+
+// Unit vectors pointing where the camera looks, to its right and to its top
+fn camera_axes(camera: &Camera) -> (Vector3<f32>, Vector3<f32>, Vector3<f32>) {
+    let forward = (camera.target - camera.position).normalize();
+    let right = forward.cross(camera.up).normalize();
+    (forward, right, right.cross(forward))
+}
+
+// Turns offset around up by yaw and then towards up by pitch, stopping just short of pointing
+// along up so the view doesn't flip over
+fn turn(offset: Vector3<f32>, up: Vector3<f32>, yaw: f32, pitch: f32) -> Vector3<f32> {
+    let up = up.normalize();
+    let offset = Matrix3::from_axis_angle(up, Rad(yaw)) * offset;
+    let limit = Rad::from(Deg(1.)).0;
+    let from_up = offset.angle(up).0;
+    let pitch = pitch.clamp(from_up - std::f32::consts::PI + limit, from_up - limit);
+    let axis = offset.cross(up).normalize();
+    Matrix3::from_axis_angle(axis, Rad(pitch)) * offset
+}
+
+fn orbit(camera: &mut Camera, dx: f32, dy: f32) {
+    let offset = camera.position - camera.target;
+    camera.position = camera.target + turn(offset, camera.up, -dx * TURN_SPEED, dy * TURN_SPEED);
+}
+
+fn look(camera: &mut Camera, dx: f32, dy: f32) {
+    let direction = camera.target - camera.position;
+    camera.target =
+        camera.position + turn(direction, camera.up, -dx * TURN_SPEED, -dy * TURN_SPEED);
+}
+
+// Moves the camera and its target so whatever is at the target follows the mouse
+fn pan(camera: &mut Camera, dx: f32, dy: f32, height: usize) {
+    let (_, right, up) = camera_axes(camera);
+    let distance = (camera.target - camera.position).magnitude();
+    // world units per pixel at the target's distance
+    let scale = 2. * distance * Rad::from(camera.fov / 2.).0.tan() / height as f32;
+    let step = (right * -dx + up * dy) * scale;
+    camera.position += step;
+    camera.target += step;
+}
@@ -0,0 +1,377 @@
+use crate::load_scene_file;
+use crate::Camera;
+use crate::Color;
+use crate::Light;
+use crate::Rasteriser;
+use crate::RenderStatsHistory;
+use crate::TriangleShading;
+use cgmath::point3;
+use cgmath::vec3;
+use cgmath::Deg;
+use cgmath::EuclideanSpace;
+use cgmath::InnerSpace;
+use cgmath::Matrix4;
+use cgmath::Point3;
+use cgmath::SquareMatrix;
+use std::path::Path;
+
+pub const USAGE: &str = "\
+Usage: gfxprogramming [OPTIONS] [PATH]
+
+Renders PATH, a scene file (.toml) or a model (.obj, .gltf, .glb, .dae, .fbx, .stl or .ply), in
+a window. Without PATH it renders scenes/default.toml. Models are centred, spun on a turntable and
+looked at from the front, the options below override the scene file's settings.
+
+Output:
+  --png FILE                 render to FILE instead of opening a window
+  --frames N                 with --png, render N frames to FILE_0000.png, FILE_0001.png...
+  --hud                      with --png, draw the frame time, counts and settings over the frames
+                             like the window does
+  --stats FILE               save the triangle and fragment counts and time taken by each stage
+                             of every frame to FILE as CSV, up to the last 3600 frames for the
+                             window
+
+Rendering:
+  --size WIDTHxHEIGHT        e.g. 800x600, defaults to 1000x1000
+  --shading MODE             points, wireframe, flat or gouraud
+
+Camera:
+  --camera-position X,Y,Z
+  --camera-target X,Y,Z
+  --fov DEGREES              vertical field of view
+  --near DISTANCE
+  --far DISTANCE
+
+Lights:
+  --light X,Y,Z[:R,G,B[:INTENSITY]]
+                             a directional light travelling along X,Y,Z, e.g.
+                             --light 0,-1,-1:1,0.9,0.8:0.8. Can be given more than once, and
+                             replaces the scene's lights. Without any, a light shines from the
+                             camera.
+
+Turntable:
+  --turntable-angle DEGREES  angle to start at
+  --turntable-speed DEGREES  added to the angle after each frame, 0 holds the scene still
+
+  -h, --help                 print this
+";
+
+// What the command line asked for, see USAGE. None leaves the setting to the scene file, or the
+// default for a model.
+#[derive(Default)]
+pub struct Options {
+    pub path: Option<String>,
+    pub png: Option<String>,
+    pub frames: Option<usize>,
+    pub size: Option<(usize, usize)>,
+    pub shading: Option<TriangleShading>,
+    pub camera_position: Option<Point3<f32>>,
+    pub camera_target: Option<Point3<f32>>,
+    pub fov: Option<Deg<f32>>,
+    pub near: Option<f32>,
+    pub far: Option<f32>,
+    pub lights: Vec<Light>,
+    pub turntable_angle: Option<Deg<f32>>,
+    pub turntable_speed: Option<Deg<f32>>,
+    pub hud: bool,
+    pub stats: Option<String>,
+    pub help: bool,
+}
+
+impl Options {
+    // Takes the arguments without the program name. Values can follow their option as the next
+    // argument or after an =, e.g. --fov 60 or --fov=60
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with('-') || arg == "-" {
+                if let Some(path) = &options.path {
+                    return Err(format!(
+                        "only one scene or model can be rendered, got {} and {}",
+                        path, arg
+                    ));
+                }
+                options.path = Some(arg);
+                continue;
+            }
+            let (name, inline_value) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (arg.as_str(), None),
+            };
+            if name == "-h" || name == "--help" {
+                options.help = true;
+                continue;
+            }
+            if name == "--hud" {
+                options.hud = true;
+                continue;
+            }
+            if !OPTIONS.contains(&name) {
+                return Err(format!("unknown option {}", name));
+            }
+            let Some(value) = inline_value.or_else(|| args.next()) else {
+                return Err(format!("{} needs a value", name));
+            };
+            let value = value.as_str();
+            match name {
+                "--png" => options.png = Some(value.to_string()),
+                "--stats" => options.stats = Some(value.to_string()),
+                "--frames" => match value.parse() {
+                    Ok(0) | Err(_) => {
+                        return Err(format!(
+                            "--frames has to be a whole number above 0, not {}",
+                            value
+                        ))
+                    }
+                    Ok(frames) => options.frames = Some(frames),
+                },
+                "--size" => options.size = Some(parse_size(value)?),
+                "--shading" => {
+                    options.shading = Some(match value {
+                        "points" => TriangleShading::Points,
+                        "wireframe" => TriangleShading::Wireframe,
+                        "flat" => TriangleShading::Flat,
+                        "gouraud" => TriangleShading::Gouraud,
+                        _ => {
+                            return Err(format!(
+                                "--shading has to be points, wireframe, flat or gouraud, not {}",
+                                value
+                            ))
+                        }
+                    })
+                }
+                "--camera-position" => {
+                    let [x, y, z] = parse_numbers(name, value)?;
+                    options.camera_position = Some(point3(x, y, z));
+                }
+                "--camera-target" => {
+                    let [x, y, z] = parse_numbers(name, value)?;
+                    options.camera_target = Some(point3(x, y, z));
+                }
+                "--fov" => {
+                    let [fov] = parse_numbers(name, value)?;
+                    if !(fov > 0. && fov < 180.) {
+                        return Err("--fov has to be between 0 and 180 degrees".to_string());
+                    }
+                    options.fov = Some(Deg(fov));
+                }
+                "--near" => {
+                    let [near] = parse_numbers(name, value)?;
+                    if near <= 0. {
+                        return Err("--near has to be greater than 0".to_string());
+                    }
+                    options.near = Some(near);
+                }
+                "--far" => options.far = Some(parse_numbers::<1>(name, value)?[0]),
+                "--light" => options.lights.push(parse_light(value)?),
+                "--turntable-angle" => {
+                    options.turntable_angle = Some(Deg(parse_numbers::<1>(name, value)?[0]))
+                }
+                "--turntable-speed" => {
+                    options.turntable_speed = Some(Deg(parse_numbers::<1>(name, value)?[0]))
+                }
+                _ => unreachable!(),
+            }
+        }
+        if options.png.is_none() {
+            if options.frames.is_some() {
+                return Err("--frames only applies when rendering to a file with --png".to_string());
+            }
+            if options.hud {
+                return Err("--hud only applies to --png, the window always has it".to_string());
+            }
+        }
+        if let (Some(near), Some(far)) = (options.near, options.far) {
+            if far <= near {
+                return Err("--far has to be greater than --near".to_string());
+            }
+        }
+        Ok(options)
+    }
+
+    // Loads the scene file or model and applies the options on top of it
+    pub fn rasteriser(&self) -> Result<Rasteriser, String> {
+        let path = self.path.as_deref().unwrap_or("./scenes/default.toml");
+        let is_scene = Path::new(path)
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("toml"));
+        let mut r = if is_scene {
+            let mut r = load_scene_file(path).map_err(|e| e.to_string())?;
+            if let Some((width, height)) = self.size {
+                r.set_size(width, height);
+            }
+            r
+        } else {
+            let (width, height) = self.size.unwrap_or((1000, 1000));
+            let mut r = Rasteriser::new(width, height);
+            let node = r.load_model(path).map_err(|e| e.to_string())?;
+            // centred on the turntable's axis, so it spins in place
+            r.scene.update_world_matrices();
+            let bounds = r.scene.bounds();
+            if bounds.is_empty() {
+                return Err(format!("{}: has nothing to draw", path));
+            }
+            let center = bounds.center();
+            let root = &mut r.scene.nodes[node];
+            root.transform = Matrix4::from_translation(-center.to_vec()) * root.transform;
+            r.scene.update_world_matrices();
+            let camera = Camera {
+                fov: self.fov.unwrap_or(Deg(60.)),
+                ..Camera::default()
+            };
+            r.camera = camera.framing(&r.scene.bounds(), width as f32 / height as f32);
+            r
+        };
+
+        if let Some(shading) = self.shading {
+            r.shading = shading;
+        }
+
+        let camera = &mut r.camera;
+        if let Some(position) = self.camera_position {
+            camera.position = position;
+        }
+        if let Some(target) = self.camera_target {
+            camera.target = target;
+        }
+        if camera.position == camera.target {
+            return Err("the camera's position and target can't be the same".to_string());
+        }
+        if camera
+            .up
+            .cross(camera.target - camera.position)
+            .magnitude2()
+            == 0.
+        {
+            return Err("the camera can't look straight along its up direction".to_string());
+        }
+        if let Some(fov) = self.fov {
+            camera.fov = fov;
+        }
+        if let Some(near) = self.near {
+            camera.near = near;
+        }
+        if let Some(far) = self.far {
+            camera.far = far;
+        }
+        if camera.far <= camera.near {
+            return Err(format!(
+                "the camera's far plane ({}) has to be further than its near plane ({})",
+                camera.far, camera.near
+            ));
+        }
+
+        if !self.lights.is_empty() {
+            for node in &mut r.scene.nodes {
+                node.light = None;
+            }
+            for &light in &self.lights {
+                let node = r.scene.add_node("light", None, Matrix4::identity());
+                r.scene.nodes[node].light = Some(light);
+            }
+        }
+
+        if let Some(angle) = self.turntable_angle {
+            r.turntable.angle = angle;
+        }
+        if let Some(speed) = self.turntable_speed {
+            r.turntable.speed = speed;
+        }
+
+        if self.stats.is_some() {
+            let frames = match self.png {
+                Some(_) => self.frames.unwrap_or(1),
+                None => WINDOW_STATS_FRAMES,
+            };
+            r.stats_history = Some(RenderStatsHistory::new(frames));
+        }
+        Ok(r)
+    }
+}
+
+// How many frames --stats keeps when rendering in a window, a minute's worth at 60 fps
+const WINDOW_STATS_FRAMES: usize = 3600;
+
+// Everything apart from --help and --hud takes a value
+const OPTIONS: &[&str] = &[
+    "--png",
+    "--frames",
+    "--stats",
+    "--size",
+    "--shading",
+    "--camera-position",
+    "--camera-target",
+    "--fov",
+    "--near",
+    "--far",
+    "--light",
+    "--turntable-angle",
+    "--turntable-speed",
+];
+
+fn parse_size(value: &str) -> Result<(usize, usize), String> {
+    let size = value
+        .split_once('x')
+        .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)));
+    match size {
+        Some((width, height)) if width > 0 && height > 0 => Ok((width, height)),
+        _ => Err(format!(
+            "--size has to be WIDTHxHEIGHT, e.g. 800x600, not {}",
+            value
+        )),
+    }
+}
+
+// N comma separated numbers
+fn parse_numbers<const N: usize>(name: &str, value: &str) -> Result<[f32; N], String> {
+    let expected = || match N {
+        1 => format!("{} has to be a number, not {}", name, value),
+        _ => format!(
+            "{} has to be {} numbers separated by commas, not {}",
+            name, N, value
+        ),
+    };
+    let numbers = value
+        .split(',')
+        .map(|number| number.trim().parse::<f32>())
+        .collect::<Result<Vec<f32>, _>>()
+        .map_err(|_| expected())?;
+    numbers
+        .try_into()
+        .ok()
+        .filter(|numbers: &[f32; N]| numbers.iter().all(|n| n.is_finite()))
+        .ok_or_else(expected)
+}
+
+// X,Y,Z[:R,G,B[:INTENSITY]]
+fn parse_light(value: &str) -> Result<Light, String> {
+    let mut parts = value.split(':');
+    let mut light = Light::default();
+    let [x, y, z] = parse_numbers("--light's direction", parts.next().unwrap_or(""))?;
+    if x == 0. && y == 0. && z == 0. {
+        return Err("--light's direction can't be zero".to_string());
+    }
+    light.direction = vec3(x, y, z).normalize();
+    if let Some(color) = parts.next() {
+        let [r, g, b] = parse_numbers("--light's color", color)?;
+        if ![r, g, b].iter().all(|c| (0. ..=1.).contains(c)) {
+            return Err("--light's color components have to be between 0 and 1".to_string());
+        }
+        light.color = Color::new(r, g, b);
+    }
+    if let Some(intensity) = parts.next() {
+        let [intensity] = parse_numbers("--light's intensity", intensity)?;
+        if intensity < 0. {
+            return Err("--light's intensity can't be negative".to_string());
+        }
+        light.intensity = intensity;
+    }
+    if parts.next().is_some() {
+        return Err(format!(
+            "--light has to be X,Y,Z[:R,G,B[:INTENSITY]], not {}",
+            value
+        ));
+    }
+    Ok(light)
+}
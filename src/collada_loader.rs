@@ -0,0 +1,765 @@
+use crate::decode_image;
+use crate::percent_decode;
+use crate::Camera;
+use crate::Color;
+use crate::Image;
+use crate::Light;
+use crate::LoadError;
+use crate::MeshRef;
+use crate::NodeId;
+use crate::ObjData;
+use crate::Sampler;
+use crate::Scene;
+use crate::Submesh;
+use crate::TextureData;
+use crate::TextureFilter;
+use crate::WrapMode;
+use cgmath::point3;
+use cgmath::vec3;
+use cgmath::Deg;
+use cgmath::InnerSpace;
+use cgmath::Matrix;
+use cgmath::Matrix4;
+use cgmath::Rad;
+use cgmath::SquareMatrix;
+use roxmltree::Document;
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+
+type XmlNode<'a> = roxmltree::Node<'a, 'a>;
+
+// Loads a Collada (.dae) model's visual scene, with a node for every <node>, converted to metres
+// and y up.
+//
+// Like glTF models, each <triangles>, <polylist> or <polygons> of a geometry becomes a mesh with
+// a single submesh, tinted by its material's diffuse color or textured with its diffuse texture.
+// Cameras and directional lights get attached to their nodes, animations, controllers and
+// everything else are ignored.
+pub fn load_collada(
+    scene: &mut Scene,
+    path: &str,
+    parent: Option<NodeId>,
+) -> Result<NodeId, LoadError> {
+    let path = Path::new(path);
+    let text = std::fs::read_to_string(path).map_err(|error| LoadError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    let document = Document::parse(&text).map_err(|e| LoadError::invalid(path, e.to_string()))?;
+    let mut loader = ColladaLoader {
+        path,
+        document: &document,
+        ids: document
+            .descendants()
+            .filter_map(|node| node.attribute("id").map(|id| (id, node)))
+            .collect(),
+        sources: HashMap::new(),
+        images: HashMap::new(),
+        meshes: HashMap::new(),
+    };
+    let root = document.root_element();
+    if root.tag_name().name() != "COLLADA" {
+        return Err(LoadError::invalid(path, "not a Collada file"));
+    }
+
+    let visual_scene = match child(root, "scene").and_then(|s| child(s, "instance_visual_scene")) {
+        Some(instance) => loader.url(instance)?,
+        None => child(root, "library_visual_scenes")
+            .and_then(|library| child(library, "visual_scene"))
+            .ok_or_else(|| LoadError::invalid(path, "there's no visual scene to load"))?,
+    };
+    let name = path
+        .file_stem()
+        .map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+    let root_node = scene.add_node(&name, parent, loader.asset_transform(root)?);
+    for node in children(visual_scene, "node") {
+        loader.add_node(scene, node, root_node)?;
+    }
+    Ok(root_node)
+}
+
+// An accessor into a <source>'s float array
+struct Source {
+    values: Vec<f32>,
+    stride: usize,
+    offset: usize,
+}
+
+impl Source {
+    fn get(&self, i: usize, component: usize) -> Option<f32> {
+        self.values
+            .get(self.offset + i * self.stride + component)
+            .copied()
+    }
+}
+
+#[derive(Clone)]
+struct Material {
+    color: Color,
+    texture: Option<(Image, Sampler)>,
+}
+
+struct ColladaLoader<'a> {
+    path: &'a Path,
+    document: &'a Document<'a>,
+    // every element with an id, for resolving "#id" urls
+    ids: HashMap<&'a str, XmlNode<'a>>,
+    sources: HashMap<&'a str, Rc<Source>>,
+    // by file path
+    images: HashMap<String, Image>,
+    // the scene mesh each primitive became with each material, so geometries used by several
+    // nodes are only loaded once
+    meshes: HashMap<(&'a str, usize, Option<&'a str>), Option<usize>>,
+}
+
+impl<'a> ColladaLoader<'a> {
+    // An error pointing at the line of the element it's about
+    fn error(&self, node: XmlNode, message: impl std::fmt::Display) -> LoadError {
+        let pos = self.document.text_pos_at(node.range().start);
+        LoadError::invalid(self.path, format!("line {}: {}", pos.row, message))
+    }
+
+    // The element the node's url attribute points at
+    fn url(&self, node: XmlNode<'a>) -> Result<XmlNode<'a>, LoadError> {
+        let url = node
+            .attribute("url")
+            .or_else(|| node.attribute("source"))
+            .or_else(|| node.attribute("target"))
+            .ok_or_else(|| self.error(node, "missing url"))?;
+        self.id(node, url)
+    }
+
+    fn id(&self, node: XmlNode<'a>, url: &str) -> Result<XmlNode<'a>, LoadError> {
+        let id = url
+            .strip_prefix('#')
+            .ok_or_else(|| self.error(node, format!("can't follow \"{}\" to another file", url)))?;
+        self.ids
+            .get(id)
+            .copied()
+            .ok_or_else(|| self.error(node, format!("nothing has the id \"{}\"", id)))
+    }
+
+    // Scales the model to metres and turns it so y is up
+    fn asset_transform(&self, root: XmlNode) -> Result<Matrix4<f32>, LoadError> {
+        let Some(asset) = child(root, "asset") else {
+            return Ok(Matrix4::identity());
+        };
+        let scale = match child(asset, "unit").and_then(|unit| unit.attribute("meter")) {
+            Some(meter) => meter
+                .parse()
+                .map_err(|_| self.error(asset, format!("bad unit \"{}\"", meter)))?,
+            None => 1.,
+        };
+        let rotation = match child(asset, "up_axis").and_then(|axis| axis.text()) {
+            Some("X_UP") => Matrix4::from_angle_z(Deg(90.)),
+            Some("Z_UP") => Matrix4::from_angle_x(Deg(-90.)),
+            _ => Matrix4::identity(),
+        };
+        Ok(rotation * Matrix4::from_scale(scale))
+    }
+
+    fn add_node(
+        &mut self,
+        scene: &mut Scene,
+        node: XmlNode<'a>,
+        parent: NodeId,
+    ) -> Result<(), LoadError> {
+        let name = node
+            .attribute("name")
+            .or_else(|| node.attribute("id"))
+            .map_or_else(|| format!("node {}", scene.nodes.len()), str::to_string);
+        let id = scene.add_node(&name, Some(parent), self.node_transform(node)?);
+
+        let mut meshes = Vec::new();
+        for instance in children(node, "instance_geometry") {
+            meshes.extend(self.load_geometry(scene, instance)?);
+        }
+        let mesh_ref = |mesh| MeshRef {
+            mesh,
+            submeshes: vec![0],
+        };
+        if let [mesh] = meshes[..] {
+            scene.nodes[id].mesh = Some(mesh_ref(mesh));
+        } else {
+            for (i, &mesh) in meshes.iter().enumerate() {
+                let child =
+                    scene.add_node(&format!("{} {}", name, i), Some(id), Matrix4::identity());
+                scene.nodes[child].mesh = Some(mesh_ref(mesh));
+            }
+        }
+
+        if let Some(instance) = child(node, "instance_camera") {
+            scene.nodes[id].camera = self.camera(self.url(instance)?)?;
+        }
+        if let Some(instance) = child(node, "instance_light") {
+            scene.nodes[id].light = self.light(self.url(instance)?)?;
+        }
+
+        for child in children(node, "node") {
+            self.add_node(scene, child, id)?;
+        }
+        // nodes from <library_nodes>
+        for instance in children(node, "instance_node") {
+            let child = self.url(instance)?;
+            self.add_node(scene, child, id)?;
+        }
+        Ok(())
+    }
+
+    // The node's transform elements multiplied together in order
+    fn node_transform(&self, node: XmlNode) -> Result<Matrix4<f32>, LoadError> {
+        let mut transform = Matrix4::identity();
+        for element in node.children().filter(|c| c.is_element()) {
+            let floats = || floats(element).map_err(|e| self.error(element, e));
+            let count = |n| {
+                let values = floats()?;
+                if values.len() != n {
+                    return Err(self.error(element, format!("expected {} numbers", n)));
+                }
+                Ok(values)
+            };
+            transform = transform
+                * match element.tag_name().name() {
+                    "matrix" => {
+                        let m = count(16)?;
+                        // row major
+                        Matrix4::new(
+                            m[0], m[1], m[2], m[3], m[4], m[5], m[6], m[7], m[8], m[9], m[10],
+                            m[11], m[12], m[13], m[14], m[15],
+                        )
+                        .transpose()
+                    }
+                    "translate" => {
+                        let t = count(3)?;
+                        Matrix4::from_translation(vec3(t[0], t[1], t[2]))
+                    }
+                    "rotate" => {
+                        let r = count(4)?;
+                        let axis = vec3(r[0], r[1], r[2]);
+                        if axis.magnitude2() == 0. {
+                            Matrix4::identity()
+                        } else {
+                            Matrix4::from_axis_angle(axis.normalize(), Deg(r[3]))
+                        }
+                    }
+                    "scale" => {
+                        let s = count(3)?;
+                        Matrix4::from_nonuniform_scale(s[0], s[1], s[2])
+                    }
+                    "lookat" => {
+                        let l = count(9)?;
+                        Matrix4::look_at_rh(
+                            point3(l[0], l[1], l[2]),
+                            point3(l[3], l[4], l[5]),
+                            vec3(l[6], l[7], l[8]),
+                        )
+                        .invert()
+                        .ok_or_else(|| self.error(element, "the lookat can't be inverted"))?
+                    }
+                    "skew" => {
+                        println!("Skipping skew transform in {}", self.path.display());
+                        Matrix4::identity()
+                    }
+                    _ => Matrix4::identity(),
+                };
+        }
+        Ok(transform)
+    }
+
+    // Adds a mesh for each primitive of the instanced geometry, returning their indices
+    fn load_geometry(
+        &mut self,
+        scene: &mut Scene,
+        instance: XmlNode<'a>,
+    ) -> Result<Vec<usize>, LoadError> {
+        let geometry = self.url(instance)?;
+        let Some(mesh) = child(geometry, "mesh") else {
+            println!(
+                "Skipping geometry that isn't a mesh in {}",
+                self.path.display()
+            );
+            return Ok(Vec::new());
+        };
+        let name = geometry
+            .attribute("name")
+            .or_else(|| geometry.attribute("id"))
+            .unwrap_or("mesh");
+        // material symbols used by the primitives, to the materials this instance binds them to
+        let bindings: HashMap<&str, &str> = instance
+            .descendants()
+            .filter(|node| node.has_tag_name("instance_material"))
+            .filter_map(|node| Some((node.attribute("symbol")?, node.attribute("target")?)))
+            .collect();
+
+        let mut meshes = Vec::new();
+        let primitives = mesh.children().filter(|node| {
+            matches!(
+                node.tag_name().name(),
+                "triangles" | "polylist" | "polygons"
+            )
+        });
+        for (i, primitive) in primitives.enumerate() {
+            let material = match primitive.attribute("material") {
+                Some(symbol) => Some(match bindings.get(symbol) {
+                    Some(target) => self.id(instance, target)?,
+                    // some exporters skip the binding and use the material's id
+                    None => self.id(primitive, &format!("#{}", symbol))?,
+                }),
+                None => None,
+            };
+            let key = (
+                geometry.attribute("id").unwrap_or(""),
+                i,
+                material.and_then(|m| m.attribute("id")),
+            );
+            let index = match self.meshes.get(&key) {
+                Some(&index) => index,
+                None => {
+                    let material = match material {
+                        Some(material) => self.material(material)?,
+                        None => Material {
+                            color: Color::new(1., 1., 1.),
+                            texture: None,
+                        },
+                    };
+                    let index = self.load_primitive(name, primitive, &material)?.map(|obj| {
+                        scene.meshes.push(obj);
+                        scene.meshes.len() - 1
+                    });
+                    self.meshes.insert(key, index);
+                    index
+                }
+            };
+            meshes.extend(index);
+        }
+        Ok(meshes)
+    }
+
+    fn load_primitive(
+        &mut self,
+        name: &str,
+        primitive: XmlNode<'a>,
+        material: &Material,
+    ) -> Result<Option<ObjData>, LoadError> {
+        // (semantic, source, offset into each vertex's indices, set)
+        let mut inputs = Vec::new();
+        for input in children(primitive, "input") {
+            let semantic = input.attribute("semantic").unwrap_or("");
+            let offset: usize = input
+                .attribute("offset")
+                .unwrap_or("0")
+                .parse()
+                .map_err(|_| self.error(input, "bad offset"))?;
+            let set: usize = input.attribute("set").unwrap_or("0").parse().unwrap_or(0);
+            if semantic == "VERTEX" {
+                // the inputs of <vertices> all share its offset
+                let vertices = self.url(input)?;
+                for input in children(vertices, "input") {
+                    let semantic = input.attribute("semantic").unwrap_or("");
+                    inputs.push((semantic, self.url(input)?, offset, 0));
+                }
+            } else {
+                inputs.push((semantic, self.url(input)?, offset, set));
+            }
+        }
+        let stride = inputs.iter().map(|input| input.2 + 1).max().unwrap_or(1);
+        // the lowest set of each semantic
+        let mut find = |semantic: &str| -> Result<Option<(Rc<Source>, usize)>, LoadError> {
+            let input = inputs
+                .iter()
+                .filter(|input| input.0 == semantic)
+                .min_by_key(|input| input.3);
+            match input {
+                Some(&(_, source, offset, _)) => Ok(Some((self.source(source)?, offset))),
+                None => Ok(None),
+            }
+        };
+        let positions = find("POSITION")?;
+        let normals = find("NORMAL")?;
+        let tex_coords = find("TEXCOORD")?;
+        let colors = find("COLOR")?;
+        let positions = positions.ok_or_else(|| self.error(primitive, "no positions"))?;
+
+        // the index of the first of each polygon's vertices' indices in p, and how many vertices
+        // it has
+        let mut p = Vec::new();
+        let mut polygons = Vec::new();
+        match primitive.tag_name().name() {
+            "triangles" => {
+                p = child_indices(self, primitive, "p")?;
+                polygons.extend((0..p.len() / (stride * 3)).map(|i| (i * 3 * stride, 3)));
+            }
+            "polylist" => {
+                p = child_indices(self, primitive, "p")?;
+                let mut start = 0;
+                for count in child_indices(self, primitive, "vcount")? {
+                    polygons.push((start, count));
+                    start += count * stride;
+                }
+            }
+            // a <p> per polygon, polygons with holes (<ph>) are skipped
+            _ => {
+                for polygon in children(primitive, "p") {
+                    let indices: Vec<usize> =
+                        parse_words(polygon).map_err(|e| self.error(polygon, e))?;
+                    polygons.push((p.len(), indices.len() / stride));
+                    p.extend(indices);
+                }
+            }
+        }
+        if polygons
+            .last()
+            .is_some_and(|&(start, count)| start + count * stride > p.len())
+        {
+            return Err(self.error(primitive, "<p> is too short"));
+        }
+        // fan, like convex polygons in .obj files
+        let triangles: Vec<[usize; 3]> = polygons
+            .iter()
+            .flat_map(|&(start, count)| {
+                (1..count.saturating_sub(1)).map(move |i| [0, i, i + 1].map(|v| start + v * stride))
+            })
+            .collect();
+        if triangles.is_empty() {
+            return Ok(None);
+        }
+
+        let bad_index = || self.error(primitive, "index out of bounds");
+        let attribute = |(source, offset): &(Rc<Source>, usize), vertex: usize, component| {
+            source.get(p[vertex + offset], component)
+        };
+        let mut tri_positions = Vec::with_capacity(triangles.len());
+        for triangle in &triangles {
+            let mut points = [point3(0., 0., 0.); 3];
+            for (point, &vertex) in points.iter_mut().zip(triangle) {
+                let c = |k| attribute(&positions, vertex, k).ok_or_else(bad_index);
+                *point = point3(c(0)?, c(1)?, c(2)?);
+            }
+            tri_positions.push(points);
+        }
+        let tri_normals = match &normals {
+            Some(normals) => {
+                let mut tri_normals = Vec::with_capacity(triangles.len());
+                for triangle in &triangles {
+                    let mut n = [vec3(0., 0., 0.); 3];
+                    for (n, &vertex) in n.iter_mut().zip(triangle) {
+                        let c = |k| attribute(normals, vertex, k).ok_or_else(bad_index);
+                        *n = vec3(c(0)?, c(1)?, c(2)?).normalize();
+                    }
+                    tri_normals.push(n);
+                }
+                Some(tri_normals)
+            }
+            None => None,
+        };
+        let tri_colors = match &colors {
+            Some(colors) => {
+                let mut tri_colors = Vec::with_capacity(triangles.len());
+                for triangle in &triangles {
+                    let mut rgb = [Color::new(0., 0., 0.); 3];
+                    for (rgb, &vertex) in rgb.iter_mut().zip(triangle) {
+                        let c = |k| match attribute(colors, vertex, k) {
+                            Some(c) if c.is_finite() => Ok(c.clamp(0., 1.)),
+                            Some(_) => Err(self.error(primitive, "colors have to be finite")),
+                            None => Err(bad_index()),
+                        };
+                        *rgb = Color::new(c(0)?, c(1)?, c(2)?);
+                    }
+                    tri_colors.push(rgb);
+                }
+                Some(tri_colors)
+            }
+            None => None,
+        };
+        let mut textures = Vec::new();
+        let tri_textures = match (&material.texture, &tex_coords) {
+            (Some(((pixels, width, height), sampler)), Some(tex_coords)) => {
+                let mut tri_textures = Vec::with_capacity(triangles.len());
+                for triangle in &triangles {
+                    let mut points = [point3(0., 0., 0.); 3];
+                    for (point, &vertex) in points.iter_mut().zip(triangle) {
+                        // t goes up the image like in .obj files
+                        let c = |k| attribute(tex_coords, vertex, k).ok_or_else(bad_index);
+                        *point = point3(c(0)?, c(1)?, 0.);
+                    }
+                    tri_textures.push(TextureData {
+                        sampler: *sampler,
+                        ..TextureData::new(pixels.clone(), *width, *height, points)
+                    });
+                }
+                textures.push(pixels.clone());
+                Some(tri_textures)
+            }
+            (Some(_), None) => {
+                println!(
+                    "{} has a texture but no texture coordinates, drawing it untextured",
+                    name
+                );
+                None
+            }
+            _ => None,
+        };
+
+        let submesh = Submesh {
+            color: material.color,
+            ..Submesh::new(name, 0..tri_positions.len(), &tri_positions)
+        };
+        Ok(Some(ObjData {
+            tri_colors,
+            ..ObjData::from_triangles(
+                tri_positions,
+                tri_textures,
+                tri_normals,
+                textures,
+                vec![submesh],
+            )
+        }))
+    }
+
+    fn source(&mut self, source: XmlNode<'a>) -> Result<Rc<Source>, LoadError> {
+        let id = source.attribute("id").unwrap_or("");
+        if let Some(source) = self.sources.get(id) {
+            return Ok(source.clone());
+        }
+        let accessor = child(source, "technique_common")
+            .and_then(|technique| child(technique, "accessor"))
+            .ok_or_else(|| self.error(source, "source has no accessor"))?;
+        let array = self.url(accessor)?;
+        let number = |name, default: usize| {
+            accessor
+                .attribute(name)
+                .map_or(Ok(default), |n| n.parse())
+                .map_err(|_| self.error(accessor, format!("bad {}", name)))
+        };
+        let decoded = Rc::new(Source {
+            values: floats(array).map_err(|e| self.error(array, e))?,
+            stride: number("stride", 1)?,
+            offset: number("offset", 0)?,
+        });
+        self.sources.insert(id, decoded.clone());
+        Ok(decoded)
+    }
+
+    // The diffuse color or texture of a <material>'s effect
+    fn material(&mut self, material: XmlNode<'a>) -> Result<Material, LoadError> {
+        let effect = self.url(
+            child(material, "instance_effect")
+                .ok_or_else(|| self.error(material, "material has no effect"))?,
+        )?;
+        let mut result = Material {
+            color: Color::new(1., 1., 1.),
+            texture: None,
+        };
+        let Some(profile) = child(effect, "profile_COMMON") else {
+            return Ok(result);
+        };
+        let shader = child(profile, "technique").and_then(|technique| {
+            technique.children().find(|node| {
+                matches!(
+                    node.tag_name().name(),
+                    "phong" | "lambert" | "blinn" | "constant"
+                )
+            })
+        });
+        let Some(shader) = shader else {
+            return Ok(result);
+        };
+        // constant materials don't have a diffuse color, just an emissive one
+        let Some(diffuse) = child(shader, "diffuse").or_else(|| child(shader, "emission")) else {
+            return Ok(result);
+        };
+        if let Some(color) = child(diffuse, "color") {
+            let [r, g, b] = self.color(color)?.map(|c| c.clamp(0., 1.));
+            result.color = Color::new(r, g, b);
+        }
+        if let Some(texture) = child(diffuse, "texture") {
+            result.texture = Some(self.texture(profile, texture)?);
+        }
+        Ok(result)
+    }
+
+    // Follows the texture's sampler <newparam> to its image, either through a surface
+    // <newparam> (1.4) or an <instance_image> (1.5)
+    fn texture(
+        &mut self,
+        profile: XmlNode<'a>,
+        texture: XmlNode<'a>,
+    ) -> Result<(Image, Sampler), LoadError> {
+        let name = texture.attribute("texture").unwrap_or("");
+        let param = |sid: &str| {
+            profile
+                .descendants()
+                .find(|node| node.has_tag_name("newparam") && node.attribute("sid") == Some(sid))
+        };
+        let Some(sampler) = param(name).and_then(|param| child(param, "sampler2D")) else {
+            // some exporters point straight at the image
+            let image = self.id(texture, &format!("#{}", name))?;
+            return Ok((self.image(image)?, Sampler::default()));
+        };
+        let image = match child(sampler, "instance_image") {
+            Some(instance) => self.url(instance)?,
+            None => {
+                let surface = child(sampler, "source")
+                    .and_then(|source| source.text())
+                    .and_then(|source| param(source.trim()))
+                    .and_then(|param| child(param, "surface"))
+                    .ok_or_else(|| self.error(sampler, "can't find the sampler's surface"))?;
+                let init_from = child(surface, "init_from")
+                    .and_then(|init_from| init_from.text())
+                    .ok_or_else(|| self.error(surface, "surface has no image"))?;
+                self.id(surface, &format!("#{}", init_from.trim()))?
+            }
+        };
+        let wrap = |name| match child(sampler, name).and_then(|wrap| wrap.text()) {
+            Some("MIRROR") => WrapMode::MirroredRepeat,
+            Some("CLAMP" | "BORDER") => WrapMode::ClampToEdge,
+            _ => WrapMode::Repeat,
+        };
+        let filter = match child(sampler, "magfilter").and_then(|filter| filter.text()) {
+            Some("NEAREST") => TextureFilter::Nearest,
+            _ => TextureFilter::Linear,
+        };
+        let sampler = Sampler {
+            wrap_s: wrap("wrap_s"),
+            wrap_t: wrap("wrap_t"),
+            filter,
+        };
+        Ok((self.image(image)?, sampler))
+    }
+
+    fn image(&mut self, image: XmlNode<'a>) -> Result<Image, LoadError> {
+        // 1.5 puts the file in a <ref>
+        let init_from = child(image, "init_from")
+            .and_then(|init_from| child(init_from, "ref").or(Some(init_from)))
+            .and_then(|init_from| init_from.text())
+            .ok_or_else(|| self.error(image, "image has no file"))?
+            .trim();
+        // file:///abs/path is absolute, which joining onto the model's directory keeps, but some
+        // exporters write file://relative/path too. Windows paths come as file:///C:/abs/path.
+        let file = percent_decode(init_from.strip_prefix("file://").unwrap_or(init_from));
+        let file = match file.as_bytes() {
+            [b'/', drive, b':', ..] if drive.is_ascii_alphabetic() => &file[1..],
+            _ => &file,
+        };
+        let file = self.path.parent().unwrap_or(Path::new("")).join(file);
+        let key = file.to_string_lossy().into_owned();
+        if let Some(decoded) = self.images.get(&key) {
+            return Ok(decoded.clone());
+        }
+        let bytes = std::fs::read(&file).map_err(|error| LoadError::Io {
+            path: file.clone(),
+            error,
+        })?;
+        let decoded = decode_image(&bytes).map_err(|e| LoadError::invalid(&file, e))?;
+        println!("Loaded texture: {:?}", file);
+        self.images.insert(key, decoded.clone());
+        Ok(decoded)
+    }
+
+    fn camera(&self, camera: XmlNode) -> Result<Option<Camera>, LoadError> {
+        let optics = child(camera, "optics").and_then(|optics| child(optics, "technique_common"));
+        let Some(perspective) = optics.and_then(|optics| child(optics, "perspective")) else {
+            println!("Skipping orthographic camera in {}", self.path.display());
+            return Ok(None);
+        };
+        let value = |name| -> Result<Option<f32>, LoadError> {
+            match child(perspective, name).and_then(|node| node.text()) {
+                Some(text) => text
+                    .trim()
+                    .parse()
+                    .map(Some)
+                    .map_err(|_| self.error(perspective, format!("bad {}", name))),
+                None => Ok(None),
+            }
+        };
+        let default = Camera::default();
+        // in degrees, either field of view can be given along with the aspect ratio
+        let fov = match (value("yfov")?, value("xfov")?, value("aspect_ratio")?) {
+            (Some(yfov), _, _) => Deg(yfov),
+            (None, Some(xfov), Some(aspect)) => {
+                Rad(2. * ((Rad::from(Deg(xfov)).0 / 2.).tan() / aspect).atan()).into()
+            }
+            (None, Some(xfov), None) => Deg(xfov),
+            (None, None, _) => default.fov,
+        };
+        // looks down the node's -z like Camera::default()
+        Ok(Some(Camera {
+            fov,
+            near: value("znear")?.unwrap_or(default.near),
+            far: value("zfar")?.unwrap_or(default.far),
+            ..default
+        }))
+    }
+
+    fn light(&self, light: XmlNode) -> Result<Option<Light>, LoadError> {
+        let directional =
+            child(light, "technique_common").and_then(|technique| child(technique, "directional"));
+        let Some(directional) = directional else {
+            println!(
+                "Skipping non directional light in {}, only directional lights are supported",
+                self.path.display()
+            );
+            return Ok(None);
+        };
+        let mut result = Light::default();
+        if let Some(color) = child(directional, "color") {
+            let [r, g, b] = self.color(color)?.map(|c| c.max(0.));
+            // colors brighter than white are the light's intensity
+            let intensity = r.max(g).max(b).max(1.);
+            result.color = Color::new(r / intensity, g / intensity, b / intensity);
+            result.intensity = intensity;
+        }
+        Ok(Some(result))
+    }
+
+    // The first 3 numbers of a <color>, unclamped
+    fn color(&self, color: XmlNode) -> Result<[f32; 3], LoadError> {
+        let c = floats(color).map_err(|e| self.error(color, e))?;
+        match c[..] {
+            [r, g, b, ..] if [r, g, b].iter().all(|c| c.is_finite()) => Ok([r, g, b]),
+            [_, _, _, ..] => Err(self.error(color, "colors have to be finite")),
+            _ => Err(self.error(color, "colors need 3 components")),
+        }
+    }
+}
+
+fn child<'a>(node: XmlNode<'a>, name: &str) -> Option<XmlNode<'a>> {
+    node.children().find(|child| child.has_tag_name(name))
+}
+
+fn children<'a, 'b>(node: XmlNode<'a>, name: &'b str) -> impl Iterator<Item = XmlNode<'a>> + 'b
+where
+    'a: 'b,
+{
+    node.children()
+        .filter(move |child| child.has_tag_name(name))
+}
+
+// The whitespace separated numbers in the element
+fn parse_words<T: std::str::FromStr>(node: XmlNode) -> Result<Vec<T>, String> {
+    node.text()
+        .unwrap_or("")
+        .split_whitespace()
+        .map(|word| {
+            word.parse()
+                .map_err(|_| format!("\"{}\" isn't a number", word))
+        })
+        .collect()
+}
+
+fn floats(node: XmlNode) -> Result<Vec<f32>, String> {
+    parse_words(node)
+}
+
+// Indices in the child element called name, if there is one
+fn child_indices(
+    loader: &ColladaLoader,
+    node: XmlNode,
+    name: &str,
+) -> Result<Vec<usize>, LoadError> {
+    match child(node, name) {
+        Some(child) => parse_words(child).map_err(|e| loader.error(child, e)),
+        None => Ok(Vec::new()),
+    }
+}
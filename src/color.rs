@@ -8,7 +8,7 @@ pub struct Color {
 
 impl Color {
     pub fn new(r: f32, g: f32, b: f32) -> Color {
-        assert!(r >= 0. && r <= 1. && g >= 0. && g <= 1. && b >= 0. && b <= 1.);
+        assert!((0. ..=1.).contains(&r) && (0. ..=1.).contains(&g) && (0. ..=1.).contains(&b));
         Color { r, g, b }
     }
 
@@ -20,14 +20,63 @@ impl Color {
         }
     }
 
+    pub fn new_from_pixel_color(color: u32) -> Color {
+        Color::new_from_rgb((color >> 16) as u8, (color >> 8) as u8, color as u8)
+    }
+
+    pub fn rgb(&self) -> [f32; 3] {
+        [self.r, self.g, self.b]
+    }
+
     pub fn get_pixel_color(&self) -> u32 {
         ((self.r * 255.) as u32) << 16 | ((self.g * 255.) as u32) << 8 | ((self.b * 255.) as u32)
     }
 
     pub fn modify_intensity(&mut self, intensity: f32) {
-        debug_assert!(intensity >= 0. && intensity <= 1.);
+        debug_assert!((0. ..=1.).contains(&intensity));
         self.r *= intensity;
         self.g *= intensity;
         self.b *= intensity;
     }
+
+    // Component wise product, e.g. a surface color lit by a light's color
+    pub fn multiply(&self, other: &Color) -> Color {
+        Color {
+            r: self.r * other.r,
+            g: self.g * other.g,
+            b: self.b * other.b,
+        }
+    }
+
+    // self + other * t, saturating at white
+    pub fn add_scaled(&self, other: &Color, t: f32) -> Color {
+        Color {
+            r: (self.r + other.r * t).min(1.),
+            g: (self.g + other.g * t).min(1.),
+            b: (self.b + other.b * t).min(1.),
+        }
+    }
+
+    // Weighted sum of three colors, e.g. vertex colors with barycentric weights, clamped in case
+    // the weights reach a little outside the triangle
+    pub fn blend(colors: &[Color; 3], weights: [f32; 3]) -> Color {
+        let [w0, w1, w2] = weights;
+        let channel = |c: fn(&Color) -> f32| {
+            (c(&colors[0]) * w0 + c(&colors[1]) * w1 + c(&colors[2]) * w2).clamp(0., 1.)
+        };
+        Color {
+            r: channel(|c| c.r),
+            g: channel(|c| c.g),
+            b: channel(|c| c.b),
+        }
+    }
+
+    // Linear interpolation towards other, t = 0 gives self and t = 1 gives other
+    pub fn mix(&self, other: &Color, t: f32) -> Color {
+        Color {
+            r: self.r + (other.r - self.r) * t,
+            g: self.g + (other.g - self.g) * t,
+            b: self.b + (other.b - self.b) * t,
+        }
+    }
 }
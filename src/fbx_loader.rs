@@ -0,0 +1,788 @@
+use crate::decode_image;
+use crate::fbx_version;
+use crate::parse_fbx;
+use crate::Color;
+use crate::FbxNode;
+use crate::FbxProperty;
+use crate::Image;
+use crate::LoadError;
+use crate::MeshRef;
+use crate::NodeId;
+use crate::ObjData;
+use crate::Sampler;
+use crate::Scene;
+use crate::Submesh;
+use crate::TextureData;
+use crate::TextureFilter;
+use crate::WrapMode;
+use base64::Engine;
+use cgmath::point3;
+use cgmath::vec3;
+use cgmath::Deg;
+use cgmath::InnerSpace;
+use cgmath::Matrix;
+use cgmath::Matrix4;
+use cgmath::SquareMatrix;
+use cgmath::Vector3;
+use cgmath::Vector4;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::path::Path;
+use std::path::PathBuf;
+
+// Loads an FBX 7.x model, binary or ASCII, with a node for every Model object, converted to
+// metres and y up.
+//
+// Geometries are split by material into meshes with a single submesh each, tinted by the
+// material's diffuse color or textured with its diffuse texture, which can be embedded in the
+// file. Cameras, lights, skinning, animations and everything else are ignored.
+pub fn load_fbx(
+    scene: &mut Scene,
+    path: &str,
+    parent: Option<NodeId>,
+) -> Result<NodeId, LoadError> {
+    let path = Path::new(path);
+    let bytes = std::fs::read(path).map_err(|error| LoadError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    let document = parse_fbx(&bytes).map_err(|e| LoadError::invalid(path, e))?;
+    if let Some(version) = fbx_version(&document).filter(|&version| version < 7000) {
+        return Err(LoadError::invalid(
+            path,
+            format!(
+                "FBX {}.{} files aren't supported, only 7.x",
+                version / 1000,
+                version % 1000 / 100
+            ),
+        ));
+    }
+    let top_level = |name| document.iter().find(|node| node.name == name);
+    let objects = top_level("Objects")
+        .ok_or_else(|| LoadError::invalid(path, "there are no objects to load"))?;
+
+    let mut loader = FbxLoader {
+        path,
+        objects: objects
+            .children
+            .iter()
+            .filter_map(|object| Some((object.properties.first()?.as_i64()?, object)))
+            .collect(),
+        children: HashMap::new(),
+        images: HashMap::new(),
+        meshes: HashMap::new(),
+    };
+    // C: "OO", child, parent or C: "OP", child, parent, "property"
+    for connection in top_level("Connections")
+        .into_iter()
+        .flat_map(|connections| connections.children_named("C"))
+    {
+        let [_, child, parent, rest @ ..] = &connection.properties[..] else {
+            continue;
+        };
+        if let (Some(child), Some(parent)) = (child.as_i64(), parent.as_i64()) {
+            let property = rest.first().and_then(FbxProperty::as_str);
+            loader
+                .children
+                .entry(parent)
+                .or_default()
+                .push((child, property));
+        }
+    }
+
+    let name = path
+        .file_stem()
+        .map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+    let settings = top_level("GlobalSettings");
+    let root_node = scene.add_node(&name, parent, global_transform(settings));
+    // the scene's root is object 0
+    let mut added = HashSet::new();
+    let models: Vec<i64> = loader.connected(0, "Model").map(|(id, ..)| id).collect();
+    for id in models {
+        loader.add_model(scene, id, root_node, &mut added)?;
+    }
+    Ok(root_node)
+}
+
+#[derive(Clone)]
+struct Material {
+    color: Color,
+    texture: Option<(Image, Sampler)>,
+}
+
+impl Default for Material {
+    fn default() -> Material {
+        Material {
+            color: Color::new(1., 1., 1.),
+            texture: None,
+        }
+    }
+}
+
+// How a layer element's values map onto a mesh
+#[derive(Clone, Copy)]
+enum Mapping {
+    ByPolygonVertex,
+    ByVertex,
+    ByPolygon,
+    AllSame,
+}
+
+// Normals, texture coordinates, colors or material indices of a geometry
+struct LayerElement {
+    values: Vec<f64>,
+    // IndexToDirect elements index into values, Direct ones don't have these
+    indices: Option<Vec<i64>>,
+    mapping: Mapping,
+    components: usize,
+}
+
+impl LayerElement {
+    fn get(&self, corner: &Corner) -> Option<&[f64]> {
+        let i = match self.mapping {
+            Mapping::ByPolygonVertex => corner.polygon_vertex,
+            Mapping::ByVertex => corner.vertex,
+            Mapping::ByPolygon => corner.polygon,
+            Mapping::AllSame => 0,
+        };
+        let i = match &self.indices {
+            Some(indices) => usize::try_from(*indices.get(i)?).ok()?,
+            None => i,
+        };
+        let start = i.checked_mul(self.components)?;
+        self.values.get(start..start.checked_add(self.components)?)
+    }
+}
+
+// A corner of a polygon, as indices into everything a layer element can be mapped by
+#[derive(Clone, Copy)]
+struct Corner {
+    // into PolygonVertexIndex
+    polygon_vertex: usize,
+    // into Vertices
+    vertex: usize,
+    polygon: usize,
+}
+
+struct FbxLoader<'a> {
+    path: &'a Path,
+    objects: HashMap<i64, &'a FbxNode>,
+    // each object's children, in the order they were connected, with the property they're
+    // connected to if any
+    children: HashMap<i64, Vec<(i64, Option<&'a str>)>>,
+    // by file path or video id
+    images: HashMap<String, Image>,
+    // the scene meshes each geometry became with each list of materials, so geometries used by
+    // several models are only loaded once
+    meshes: HashMap<(i64, Vec<i64>), Vec<usize>>,
+}
+
+impl<'a> FbxLoader<'a> {
+    // An error naming the object it's about
+    fn error(&self, object: &FbxNode, message: impl std::fmt::Display) -> LoadError {
+        LoadError::invalid(
+            self.path,
+            format!("{} \"{}\": {}", object.name, object_name(object), message),
+        )
+    }
+
+    // The children of the object that are class objects, with the properties they're connected to
+    fn connected(
+        &self,
+        parent: i64,
+        class: &'a str,
+    ) -> impl Iterator<Item = (i64, &'a FbxNode, Option<&'a str>)> + '_ {
+        self.children
+            .get(&parent)
+            .into_iter()
+            .flatten()
+            .filter_map(move |&(id, property)| {
+                let object = *self.objects.get(&id)?;
+                (object.name == class).then_some((id, object, property))
+            })
+    }
+
+    fn add_model(
+        &mut self,
+        scene: &mut Scene,
+        id: i64,
+        parent: NodeId,
+        added: &mut HashSet<i64>,
+    ) -> Result<(), LoadError> {
+        // a model connected to several parents, or to itself, only gets added once
+        if !added.insert(id) {
+            return Ok(());
+        }
+        let model = self.objects[&id];
+        let name = object_name(model);
+        let node = scene.add_node(&name, Some(parent), model_transform(model));
+
+        let materials: Vec<i64> = self.connected(id, "Material").map(|(id, ..)| id).collect();
+        let geometries: Vec<_> = self.connected(id, "Geometry").collect();
+        let mut meshes = Vec::new();
+        for (geometry_id, geometry, _) in geometries {
+            if geometry.properties.get(2).and_then(FbxProperty::as_str) != Some("Mesh") {
+                println!(
+                    "Skipping geometry that isn't a mesh in {}",
+                    self.path.display()
+                );
+                continue;
+            }
+            meshes.extend(self.load_geometry(scene, geometry_id, geometry, &materials)?);
+        }
+        // the geometric transform only applies to the model's own meshes, not its children
+        let geometric = geometric_transform(model);
+        let mesh_ref = |mesh| MeshRef {
+            mesh,
+            submeshes: vec![0],
+        };
+        match meshes[..] {
+            [mesh] if geometric == Matrix4::identity() => {
+                scene.nodes[node].mesh = Some(mesh_ref(mesh))
+            }
+            _ => {
+                for (i, &mesh) in meshes.iter().enumerate() {
+                    let child = scene.add_node(&format!("{} {}", name, i), Some(node), geometric);
+                    scene.nodes[child].mesh = Some(mesh_ref(mesh));
+                }
+            }
+        }
+
+        let children: Vec<i64> = self.connected(id, "Model").map(|(id, ..)| id).collect();
+        for child in children {
+            self.add_model(scene, child, node, added)?;
+        }
+        Ok(())
+    }
+
+    // Adds a mesh for each material used by the geometry, returning their indices
+    fn load_geometry(
+        &mut self,
+        scene: &mut Scene,
+        id: i64,
+        geometry: &FbxNode,
+        materials: &[i64],
+    ) -> Result<Vec<usize>, LoadError> {
+        let key = (id, materials.to_vec());
+        if let Some(meshes) = self.meshes.get(&key) {
+            return Ok(meshes.clone());
+        }
+        let name = object_name(geometry);
+        let positions = geometry
+            .child_array("Vertices")
+            .and_then(FbxProperty::floats)
+            .ok_or_else(|| self.error(geometry, "no vertices"))?;
+        let indices = geometry
+            .child_array("PolygonVertexIndex")
+            .and_then(FbxProperty::ints)
+            .ok_or_else(|| self.error(geometry, "no polygons"))?;
+        let normals = self.layer_element(
+            geometry,
+            "LayerElementNormal",
+            "Normals",
+            Some("NormalsIndex"),
+            3,
+        )?;
+        let tex_coords =
+            self.layer_element(geometry, "LayerElementUV", "UV", Some("UVIndex"), 2)?;
+        let colors = self.layer_element(
+            geometry,
+            "LayerElementColor",
+            "Colors",
+            Some("ColorIndex"),
+            4,
+        )?;
+        // the material indices are the values themselves
+        let slots = self.layer_element(geometry, "LayerElementMaterial", "Materials", None, 1)?;
+
+        // each polygon is fanned, like convex polygons in .obj files, into the triangles of its
+        // material
+        let mut triangles: Vec<Vec<[Corner; 3]>> = Vec::new();
+        let mut polygon = Vec::new();
+        let mut polygons = 0;
+        for (polygon_vertex, &index) in indices.iter().enumerate() {
+            // the last vertex of each polygon is stored as -index - 1
+            polygon.push(Corner {
+                polygon_vertex,
+                vertex: if index < 0 { !index } else { index } as usize,
+                polygon: 0,
+            });
+            if index >= 0 {
+                continue;
+            }
+            for corner in &mut polygon {
+                corner.polygon = polygons;
+            }
+            polygons += 1;
+            let slot = match slots.as_ref().and_then(|slots| slots.get(&polygon[0])) {
+                // without any materials everything gets the default one
+                Some(_) if materials.is_empty() => 0,
+                Some(&[slot]) if slot >= 0. && (slot as usize) < materials.len() => slot as usize,
+                Some(slot) => {
+                    return Err(self.error(
+                        geometry,
+                        format!(
+                            "material slot {} but there are {} materials",
+                            slot[0],
+                            materials.len()
+                        ),
+                    ))
+                }
+                None => 0,
+            };
+            if triangles.len() <= slot {
+                triangles.resize_with(slot + 1, Vec::new);
+            }
+            for i in 1..polygon.len().saturating_sub(1) {
+                triangles[slot].push([polygon[0], polygon[i], polygon[i + 1]]);
+            }
+            polygon.clear();
+        }
+
+        let mut meshes = Vec::new();
+        for (slot, triangles) in triangles.iter().enumerate() {
+            if triangles.is_empty() {
+                continue;
+            }
+            let material = match materials.get(slot) {
+                Some(&material) => self.material(material)?,
+                None => Material::default(),
+            };
+            let obj = self.load_mesh(
+                geometry,
+                &name,
+                triangles,
+                &positions,
+                [&normals, &tex_coords, &colors],
+                &material,
+            )?;
+            scene.meshes.push(obj);
+            meshes.push(scene.meshes.len() - 1);
+        }
+        self.meshes.insert(key, meshes.clone());
+        Ok(meshes)
+    }
+
+    // The lowest layer's element called name, None if there isn't one or it's mapped in a way
+    // that isn't supported
+    fn layer_element(
+        &self,
+        geometry: &FbxNode,
+        name: &str,
+        values: &str,
+        index: Option<&str>,
+        components: usize,
+    ) -> Result<Option<LayerElement>, LoadError> {
+        let element = geometry
+            .children_named(name)
+            .min_by_key(|element| element.properties.first().and_then(FbxProperty::as_i64));
+        let Some(element) = element else {
+            return Ok(None);
+        };
+        let text = |name| {
+            element
+                .child(name)
+                .and_then(|child| child.properties.first())
+                .and_then(FbxProperty::as_str)
+                .unwrap_or("")
+        };
+        let mapping = match text("MappingInformationType") {
+            "ByPolygonVertex" => Mapping::ByPolygonVertex,
+            "ByVertex" | "ByVertice" | "ByControlPoint" => Mapping::ByVertex,
+            "ByPolygon" => Mapping::ByPolygon,
+            "AllSame" => Mapping::AllSame,
+            mapping => {
+                println!(
+                    "Skipping {} mapped {} in {}",
+                    name,
+                    mapping,
+                    self.path.display()
+                );
+                return Ok(None);
+            }
+        };
+        let array = |name: &str| {
+            element
+                .child_array(name)
+                .ok_or_else(|| self.error(geometry, format!("{} has no {}", element.name, name)))
+        };
+        let values = array(values)?
+            .floats()
+            .ok_or_else(|| self.error(geometry, format!("{} isn't an array", values)))?
+            .into_owned();
+        let indices = match (text("ReferenceInformationType"), index) {
+            ("IndexToDirect" | "Index", Some(index)) => Some(
+                array(index)?
+                    .ints()
+                    .ok_or_else(|| self.error(geometry, format!("{} isn't an array", index)))?
+                    .into_owned(),
+            ),
+            _ => None,
+        };
+        Ok(Some(LayerElement {
+            values,
+            indices,
+            mapping,
+            components,
+        }))
+    }
+
+    fn load_mesh(
+        &mut self,
+        geometry: &FbxNode,
+        name: &str,
+        triangles: &[[Corner; 3]],
+        positions: &[f64],
+        [normals, tex_coords, colors]: [&Option<LayerElement>; 3],
+        material: &Material,
+    ) -> Result<ObjData, LoadError> {
+        let bad_index = |what: &str| self.error(geometry, format!("{} index out of bounds", what));
+        let mut tri_positions = Vec::with_capacity(triangles.len());
+        for triangle in triangles {
+            let mut points = [point3(0., 0., 0.); 3];
+            for (point, corner) in points.iter_mut().zip(triangle) {
+                let p = corner
+                    .vertex
+                    .checked_mul(3)
+                    .and_then(|start| positions.get(start..start.checked_add(3)?))
+                    .ok_or_else(|| bad_index("vertex"))?;
+                *point = point3(p[0] as f32, p[1] as f32, p[2] as f32);
+            }
+            tri_positions.push(points);
+        }
+        let tri_normals = match normals {
+            Some(normals) => {
+                let mut tri_normals = Vec::with_capacity(triangles.len());
+                for triangle in triangles {
+                    let mut n = [vec3(0., 0., 0.); 3];
+                    for (n, corner) in n.iter_mut().zip(triangle) {
+                        let c = normals.get(corner).ok_or_else(|| bad_index("normal"))?;
+                        *n = vec3(c[0] as f32, c[1] as f32, c[2] as f32).normalize();
+                    }
+                    tri_normals.push(n);
+                }
+                Some(tri_normals)
+            }
+            None => None,
+        };
+        let tri_colors = match colors {
+            Some(colors) => {
+                let mut tri_colors = Vec::with_capacity(triangles.len());
+                for triangle in triangles {
+                    let mut rgb = [Color::new(0., 0., 0.); 3];
+                    for (rgb, corner) in rgb.iter_mut().zip(triangle) {
+                        let c = colors.get(corner).ok_or_else(|| bad_index("color"))?;
+                        if !c[..3].iter().all(|c| c.is_finite()) {
+                            return Err(self.error(geometry, "colors have to be finite"));
+                        }
+                        let c = |k: usize| (c[k] as f32).clamp(0., 1.);
+                        *rgb = Color::new(c(0), c(1), c(2));
+                    }
+                    tri_colors.push(rgb);
+                }
+                Some(tri_colors)
+            }
+            None => None,
+        };
+        let mut textures = Vec::new();
+        let tri_textures = match (&material.texture, tex_coords) {
+            (Some(((pixels, width, height), sampler)), Some(tex_coords)) => {
+                let mut tri_textures = Vec::with_capacity(triangles.len());
+                for triangle in triangles {
+                    let mut points = [point3(0., 0., 0.); 3];
+                    for (point, corner) in points.iter_mut().zip(triangle) {
+                        // v goes up the image like in .obj files
+                        let c = tex_coords
+                            .get(corner)
+                            .ok_or_else(|| bad_index("texture coordinate"))?;
+                        *point = point3(c[0] as f32, c[1] as f32, 0.);
+                    }
+                    tri_textures.push(TextureData {
+                        sampler: *sampler,
+                        ..TextureData::new(pixels.clone(), *width, *height, points)
+                    });
+                }
+                textures.push(pixels.clone());
+                Some(tri_textures)
+            }
+            (Some(_), None) => {
+                println!(
+                    "{} has a texture but no texture coordinates, drawing it untextured",
+                    name
+                );
+                None
+            }
+            _ => None,
+        };
+
+        let submesh = Submesh {
+            color: material.color,
+            ..Submesh::new(name, 0..tri_positions.len(), &tri_positions)
+        };
+        Ok(ObjData {
+            tri_colors,
+            ..ObjData::from_triangles(
+                tri_positions,
+                tri_textures,
+                tri_normals,
+                textures,
+                vec![submesh],
+            )
+        })
+    }
+
+    // The material's diffuse color and the texture connected to it
+    fn material(&mut self, id: i64) -> Result<Material, LoadError> {
+        let material = self.objects[&id];
+        let mut result = Material::default();
+        // older exporters only write Diffuse
+        let color = material
+            .property70("DiffuseColor")
+            .or_else(|| material.property70("Diffuse"));
+        if let Some(color) = color {
+            let c: Vec<f64> = color.iter().filter_map(FbxProperty::as_f64).collect();
+            if c.len() < 3 {
+                return Err(self.error(material, "colors need 3 components"));
+            }
+            if !c[..3].iter().all(|c| c.is_finite()) {
+                return Err(self.error(material, "colors have to be finite"));
+            }
+            let c = |k: usize| c[k].clamp(0., 1.) as f32;
+            result.color = Color::new(c(0), c(1), c(2));
+        }
+        let texture = self
+            .connected(id, "Texture")
+            .find(|&(.., property)| matches!(property, Some("DiffuseColor" | "Diffuse")));
+        if let Some((texture_id, texture, _)) = texture {
+            result.texture = self.texture(texture_id, texture)?;
+        }
+        Ok(result)
+    }
+
+    // The texture's image, embedded in a connected Video or from its file, and how it's sampled
+    fn texture(
+        &mut self,
+        id: i64,
+        texture: &FbxNode,
+    ) -> Result<Option<(Image, Sampler)>, LoadError> {
+        let embedded = self
+            .connected(id, "Video")
+            .find(|(_, video, _)| video.child("Content").is_some());
+        let image = match embedded {
+            Some((video_id, video, _)) => self.embedded_image(video_id, video)?,
+            None => self.image_file(texture)?,
+        };
+        let Some(image) = image else {
+            return Ok(None);
+        };
+        // 0 is repeat, 1 is clamp
+        let wrap = |name| match texture.property70(name).and_then(|v| v.first()?.as_i64()) {
+            Some(1) => WrapMode::ClampToEdge,
+            _ => WrapMode::Repeat,
+        };
+        let sampler = Sampler {
+            wrap_s: wrap("WrapModeU"),
+            wrap_t: wrap("WrapModeV"),
+            filter: TextureFilter::Linear,
+        };
+        Ok(Some((image, sampler)))
+    }
+
+    fn embedded_image(&mut self, id: i64, video: &FbxNode) -> Result<Option<Image>, LoadError> {
+        let key = id.to_string();
+        if let Some(decoded) = self.images.get(&key) {
+            return Ok(Some(decoded.clone()));
+        }
+        let content = video.child("Content").unwrap();
+        // raw bytes in binary files, base64 strings in ASCII ones
+        let bytes = match &content.properties[..] {
+            [FbxProperty::Bytes(bytes)] => bytes.clone(),
+            strings => {
+                let base64: String = strings.iter().filter_map(FbxProperty::as_str).collect();
+                base64::engine::general_purpose::STANDARD
+                    .decode(base64)
+                    .map_err(|e| self.error(video, format!("bad embedded image: {}", e)))?
+            }
+        };
+        let decoded = decode_image(&bytes).map_err(|e| self.error(video, e))?;
+        println!("Loaded embedded texture: {}", object_name(video));
+        self.images.insert(key, decoded.clone());
+        Ok(Some(decoded))
+    }
+
+    // Textures point at files on the machine that exported them, so besides the path relative to
+    // the model, the absolute path and a file with the same name next to the model are tried.
+    // Textures that can't be found are left off rather than failing the whole model.
+    fn image_file(&mut self, texture: &FbxNode) -> Result<Option<Image>, LoadError> {
+        let string = |name| {
+            texture
+                .child(name)
+                .and_then(|child| child.properties.first())
+                .and_then(FbxProperty::as_str)
+                .filter(|file| !file.is_empty())
+        };
+        let absolute = string("FileName");
+        let Some(relative) = string("RelativeFilename").or(absolute) else {
+            println!(
+                "{} has no file, drawing it untextured",
+                object_name(texture)
+            );
+            return Ok(None);
+        };
+        let directory = self.path.parent().unwrap_or(Path::new(""));
+        // exported on Windows, possibly
+        let path = |file: &str| PathBuf::from(file.replace('\\', "/"));
+        let mut candidates = vec![directory.join(path(relative))];
+        candidates.extend(absolute.map(path));
+        candidates.extend(path(relative).file_name().map(|file| directory.join(file)));
+        let Some(file) = candidates.iter().find(|file| file.is_file()) else {
+            println!(
+                "Can't find texture {:?}, drawing it untextured",
+                candidates[0]
+            );
+            return Ok(None);
+        };
+        let key = file.to_string_lossy().into_owned();
+        if let Some(decoded) = self.images.get(&key) {
+            return Ok(Some(decoded.clone()));
+        }
+        let bytes = std::fs::read(file).map_err(|error| LoadError::Io {
+            path: file.clone(),
+            error,
+        })?;
+        let decoded = decode_image(&bytes).map_err(|e| LoadError::invalid(file, e))?;
+        println!("Loaded texture: {:?}", file);
+        self.images.insert(key, decoded.clone());
+        Ok(Some(decoded))
+    }
+}
+
+// Object names are "Name\0\x01Class" in binary files and "Class::Name" in ASCII ones
+fn object_name(object: &FbxNode) -> String {
+    let name = object
+        .properties
+        .get(1)
+        .and_then(FbxProperty::as_str)
+        .unwrap_or("");
+    let name = match name.split_once("\0\x01") {
+        Some((name, _)) => name,
+        None => name.split_once("::").map_or(name, |(_, name)| name),
+    };
+    name.to_string()
+}
+
+// The three numbers of a Properties70 vector, or default if it isn't there
+fn vector(object: &FbxNode, name: &str, default: f32) -> Vector3<f32> {
+    match object.property70(name) {
+        Some([x, y, z, ..]) => {
+            let c = |c: &FbxProperty| c.as_f64().map_or(default, |c| c as f32);
+            vec3(c(x), c(y), c(z))
+        }
+        _ => vec3(default, default, default),
+    }
+}
+
+fn int(object: &FbxNode, name: &str) -> Option<i64> {
+    object.property70(name)?.first()?.as_i64()
+}
+
+// Euler angles in degrees, applied in the given order of axes
+fn euler(angles: Vector3<f32>, order: [usize; 3]) -> Matrix4<f32> {
+    order.iter().fold(Matrix4::identity(), |rotation, &axis| {
+        let angle = Deg(angles[axis]);
+        let axis_rotation = match axis {
+            0 => Matrix4::from_angle_x(angle),
+            1 => Matrix4::from_angle_y(angle),
+            _ => Matrix4::from_angle_z(angle),
+        };
+        axis_rotation * rotation
+    })
+}
+
+// The order of the axes for each RotationOrder, XYZ being x first
+const ROTATION_ORDERS: [[usize; 3]; 6] = [
+    [0, 1, 2],
+    [0, 2, 1],
+    [1, 2, 0],
+    [1, 0, 2],
+    [2, 0, 1],
+    [2, 1, 0],
+];
+
+// The model's transform relative to its parent, which FBX builds out of
+//     T * Roff * Rp * Rpre * R * Rpost^-1 * Rp^-1 * Soff * Sp * S * Sp^-1
+// where the pivots and offsets are usually zero
+fn model_transform(model: &FbxNode) -> Matrix4<f32> {
+    let translation = |name| Matrix4::from_translation(vector(model, name, 0.));
+    let order = int(model, "RotationOrder")
+        .and_then(|order| ROTATION_ORDERS.get(order as usize))
+        .unwrap_or(&ROTATION_ORDERS[0]);
+    let rotation = euler(vector(model, "Lcl Rotation", 0.), *order);
+    // pre and post rotations only count when the rotation is active, and are always XYZ
+    let (pre_rotation, post_rotation) = if int(model, "RotationActive") == Some(1) {
+        (
+            euler(vector(model, "PreRotation", 0.), ROTATION_ORDERS[0]),
+            euler(vector(model, "PostRotation", 0.), ROTATION_ORDERS[0]),
+        )
+    } else {
+        (Matrix4::identity(), Matrix4::identity())
+    };
+    let s = vector(model, "Lcl Scaling", 1.);
+    let pivot = vector(model, "RotationPivot", 0.);
+    let scaling_pivot = vector(model, "ScalingPivot", 0.);
+    translation("Lcl Translation")
+        * translation("RotationOffset")
+        * Matrix4::from_translation(pivot)
+        * pre_rotation
+        * rotation
+        * post_rotation.transpose()
+        * Matrix4::from_translation(-pivot)
+        * translation("ScalingOffset")
+        * Matrix4::from_translation(scaling_pivot)
+        * Matrix4::from_nonuniform_scale(s.x, s.y, s.z)
+        * Matrix4::from_translation(-scaling_pivot)
+}
+
+// The offset of the model's own meshes from the model
+fn geometric_transform(model: &FbxNode) -> Matrix4<f32> {
+    let s = vector(model, "GeometricScaling", 1.);
+    Matrix4::from_translation(vector(model, "GeometricTranslation", 0.))
+        * euler(vector(model, "GeometricRotation", 0.), ROTATION_ORDERS[0])
+        * Matrix4::from_nonuniform_scale(s.x, s.y, s.z)
+}
+
+// Scales the model to metres (FBX defaults to centimetres) and turns it so y is up
+fn global_transform(settings: Option<&FbxNode>) -> Matrix4<f32> {
+    let Some(settings) = settings else {
+        return Matrix4::from_scale(0.01);
+    };
+    let unit = settings
+        .property70("UnitScaleFactor")
+        .and_then(|unit| unit.first()?.as_f64())
+        .unwrap_or(1.);
+    // each of the file's axes and which way it points
+    let axis = |name: &str, default| {
+        let axis = int(settings, &format!("{}Axis", name)).unwrap_or(default);
+        let sign = int(settings, &format!("{}AxisSign", name)).unwrap_or(1);
+        let mut row = Vector4::<f32>::new(0., 0., 0., 0.);
+        if (0..3).contains(&axis) {
+            row[axis as usize] = if sign < 0 { -1. } else { 1. };
+        }
+        row
+    };
+    // rows taking the file's right, up and front axes to x, y and z
+    let rotation = Matrix4::from_cols(
+        axis("Coord", 0),
+        axis("Up", 1),
+        axis("Front", 2),
+        Vector4::unit_w(),
+    )
+    .transpose();
+    // a file with a missing or repeated axis is left the way it is
+    let rotation = if rotation.determinant().abs() == 1. {
+        rotation
+    } else {
+        Matrix4::identity()
+    };
+    rotation * Matrix4::from_scale(unit as f32 / 100.)
+}
@@ -0,0 +1,508 @@
+use std::borrow::Cow;
+use std::io::Read;
+
+// FBX files, binary or ASCII, are a tree of nodes that each have a name, a list of properties and
+// child nodes. What the nodes mean is up to fbx_loader.rs.
+#[derive(Clone, Debug)]
+pub struct FbxNode {
+    pub name: String,
+    pub properties: Vec<FbxProperty>,
+    pub children: Vec<FbxNode>,
+}
+
+// Binary files have more types than this (bools, 16/32 bit ints, 32 bit floats), they get widened
+#[derive(Clone, Debug)]
+pub enum FbxProperty {
+    Int(i64),
+    Float(f64),
+    String(String),
+    Bytes(Vec<u8>),
+    IntArray(Vec<i64>),
+    FloatArray(Vec<f64>),
+}
+
+impl FbxProperty {
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            FbxProperty::Int(i) => Some(i),
+            FbxProperty::Float(f) => Some(f as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            FbxProperty::Int(i) => Some(i as f64),
+            FbxProperty::Float(f) => Some(f),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            FbxProperty::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    // Either kind of array, as floats
+    pub fn floats(&self) -> Option<Cow<'_, [f64]>> {
+        match self {
+            FbxProperty::FloatArray(a) => Some(Cow::Borrowed(a)),
+            FbxProperty::IntArray(a) => Some(Cow::Owned(a.iter().map(|&i| i as f64).collect())),
+            _ => None,
+        }
+    }
+
+    // Either kind of array, as ints
+    pub fn ints(&self) -> Option<Cow<'_, [i64]>> {
+        match self {
+            FbxProperty::IntArray(a) => Some(Cow::Borrowed(a)),
+            FbxProperty::FloatArray(a) => Some(Cow::Owned(a.iter().map(|&f| f as i64).collect())),
+            _ => None,
+        }
+    }
+}
+
+impl FbxNode {
+    pub fn child(&self, name: &str) -> Option<&FbxNode> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    pub fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a FbxNode> {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    // The values of a property in the node's Properties70, which are listed as
+    //     P: "name", "type", "label", "flags", values...
+    pub fn property70(&self, name: &str) -> Option<&[FbxProperty]> {
+        self.child("Properties70")?
+            .children_named("P")
+            .find(|p| p.properties.first().and_then(FbxProperty::as_str) == Some(name))
+            .map(|p| p.properties.get(4..).unwrap_or(&[]))
+    }
+
+    // The array in the first property of the child called name, e.g. Vertices: *12 { a: ... }
+    pub fn child_array(&self, name: &str) -> Option<&FbxProperty> {
+        self.child(name)?.properties.first()
+    }
+}
+
+const BINARY_MAGIC: &[u8] = b"Kaydara FBX Binary  \0";
+
+// The top level nodes of a binary or ASCII file
+pub fn parse_fbx(bytes: &[u8]) -> Result<Vec<FbxNode>, String> {
+    if bytes.starts_with(BINARY_MAGIC) {
+        parse_binary(bytes)
+    } else {
+        let text = std::str::from_utf8(bytes).map_err(|_| "not an FBX file".to_string())?;
+        parse_ascii(text)
+    }
+}
+
+// The file version, e.g. 7400 for 7.4
+pub fn fbx_version(nodes: &[FbxNode]) -> Option<i64> {
+    nodes
+        .iter()
+        .find(|node| node.name == "FBXHeaderExtension")?
+        .child("FBXVersion")?
+        .properties
+        .first()?
+        .as_i64()
+}
+
+fn parse_binary(bytes: &[u8]) -> Result<Vec<FbxNode>, String> {
+    // the magic, two unknown bytes and the version
+    let version = bytes
+        .get(23..27)
+        .map(|v| u32::from_le_bytes(v.try_into().unwrap()))
+        .ok_or("file is truncated")?;
+    let mut reader = BinaryReader {
+        bytes,
+        pos: 27,
+        // node record headers went from 32 to 64 bit offsets in 7.5
+        wide: version >= 7500,
+    };
+    let mut nodes = Vec::new();
+    while let Some(node) = reader.node()? {
+        nodes.push(node);
+    }
+    Ok(nodes)
+}
+
+struct BinaryReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    wide: bool,
+}
+
+impl BinaryReader<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8], String> {
+        let bytes = self
+            .bytes
+            .get(self.pos..self.pos + len)
+            .ok_or("file is truncated")?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn offset(&mut self) -> Result<usize, String> {
+        if self.wide {
+            Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()) as usize)
+        } else {
+            Ok(self.u32()? as usize)
+        }
+    }
+
+    // None at the end of a list of nodes, which is marked by a record of zeros or the end of the
+    // file
+    fn node(&mut self) -> Result<Option<FbxNode>, String> {
+        if self.pos >= self.bytes.len() {
+            return Ok(None);
+        }
+        let end = self.offset()?;
+        let property_count = self.offset()?;
+        let _property_list_len = self.offset()?;
+        let name_len = self.u8()? as usize;
+        if end == 0 {
+            return Ok(None);
+        }
+        if end > self.bytes.len() {
+            return Err("file is truncated".to_string());
+        }
+        if end < self.pos {
+            return Err(format!("node at byte {} has a bad end offset", self.pos));
+        }
+        let name = String::from_utf8_lossy(self.take(name_len)?).into_owned();
+        let mut properties = Vec::with_capacity(property_count.min(1024));
+        for _ in 0..property_count {
+            properties.push(self.property()?);
+        }
+        let mut children = Vec::new();
+        while self.pos < end {
+            match self.node()? {
+                Some(child) => children.push(child),
+                None => break,
+            }
+        }
+        self.pos = end;
+        Ok(Some(FbxNode {
+            name,
+            properties,
+            children,
+        }))
+    }
+
+    fn property(&mut self) -> Result<FbxProperty, String> {
+        let kind = self.u8()?;
+        Ok(match kind {
+            b'C' => FbxProperty::Int(self.u8()? as i64),
+            b'Y' => FbxProperty::Int(i16::from_le_bytes(self.take(2)?.try_into().unwrap()) as i64),
+            b'I' => FbxProperty::Int(self.u32()? as i32 as i64),
+            b'L' => FbxProperty::Int(i64::from_le_bytes(self.take(8)?.try_into().unwrap())),
+            b'F' => {
+                FbxProperty::Float(f32::from_le_bytes(self.take(4)?.try_into().unwrap()) as f64)
+            }
+            b'D' => FbxProperty::Float(f64::from_le_bytes(self.take(8)?.try_into().unwrap())),
+            b'S' => {
+                let len = self.u32()? as usize;
+                FbxProperty::String(String::from_utf8_lossy(self.take(len)?).into_owned())
+            }
+            b'R' => {
+                let len = self.u32()? as usize;
+                FbxProperty::Bytes(self.take(len)?.to_vec())
+            }
+            b'b' | b'i' | b'l' | b'f' | b'd' => {
+                let len = self.u32()? as usize;
+                let encoding = self.u32()?;
+                let compressed_len = self.u32()? as usize;
+                let data = self.take(compressed_len)?;
+                let size = match kind {
+                    b'b' => 1,
+                    b'i' | b'f' => 4,
+                    _ => 8,
+                };
+                let data = match encoding {
+                    0 => Cow::Borrowed(data),
+                    // zlib
+                    1 => {
+                        let mut decompressed = Vec::with_capacity(len * size);
+                        flate2::read::ZlibDecoder::new(data)
+                            .read_to_end(&mut decompressed)
+                            .map_err(|e| format!("can't decompress array: {}", e))?;
+                        Cow::Owned(decompressed)
+                    }
+                    _ => return Err(format!("unknown array encoding {}", encoding)),
+                };
+                if data.len() < len * size {
+                    return Err("array is shorter than its length".to_string());
+                }
+                let elements = data.chunks_exact(size).take(len);
+                match kind {
+                    b'b' => FbxProperty::IntArray(elements.map(|b| b[0] as i64).collect()),
+                    b'i' => FbxProperty::IntArray(
+                        elements
+                            .map(|b| i32::from_le_bytes(b.try_into().unwrap()) as i64)
+                            .collect(),
+                    ),
+                    b'l' => FbxProperty::IntArray(
+                        elements
+                            .map(|b| i64::from_le_bytes(b.try_into().unwrap()))
+                            .collect(),
+                    ),
+                    b'f' => FbxProperty::FloatArray(
+                        elements
+                            .map(|b| f32::from_le_bytes(b.try_into().unwrap()) as f64)
+                            .collect(),
+                    ),
+                    _ => FbxProperty::FloatArray(
+                        elements
+                            .map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+                            .collect(),
+                    ),
+                }
+            }
+            _ => {
+                return Err(format!(
+                    "unknown property type '{}' at byte {}",
+                    kind as char,
+                    self.pos - 1
+                ))
+            }
+        })
+    }
+}
+
+// ASCII files look like
+//     ; comment
+//     Objects:  {
+//         Geometry: 1234, "Geometry::Cube", "Mesh" {
+//             Vertices: *6 {
+//                 a: 0,0,0,1,0,0
+//             }
+//             GeometryVersion: 124
+//         }
+//     }
+// with arrays turned into array properties like in binary files
+fn parse_ascii(text: &str) -> Result<Vec<FbxNode>, String> {
+    let mut tokens = Tokens {
+        text,
+        pos: 0,
+        peeked: None,
+    };
+    let nodes = ascii_nodes(&mut tokens)?;
+    match tokens.next()? {
+        None => Ok(nodes),
+        Some(token) => Err(tokens.error(&format!("unexpected {:?}", token))),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token<'a> {
+    // names of nodes and bare values like T or Y
+    Word(&'a str),
+    Number(&'a str),
+    String(&'a str),
+    Colon,
+    Comma,
+    Star,
+    Open,
+    Close,
+}
+
+struct Tokens<'a> {
+    text: &'a str,
+    pos: usize,
+    peeked: Option<(usize, Option<Token<'a>>)>,
+}
+
+impl<'a> Tokens<'a> {
+    fn error(&self, message: &str) -> String {
+        let line = self.text[..self.pos.min(self.text.len())]
+            .matches('\n')
+            .count()
+            + 1;
+        format!("line {}: {}", line, message)
+    }
+
+    fn peek(&mut self) -> Result<Option<Token<'a>>, String> {
+        if self.peeked.is_none() {
+            let start = self.pos;
+            let token = self.read()?;
+            self.peeked = Some((std::mem::replace(&mut self.pos, start), token));
+        }
+        Ok(self.peeked.as_ref().unwrap().1.clone())
+    }
+
+    fn next(&mut self) -> Result<Option<Token<'a>>, String> {
+        match self.peeked.take() {
+            Some((end, token)) => {
+                self.pos = end;
+                Ok(token)
+            }
+            None => self.read(),
+        }
+    }
+
+    // Whether the next token is a word followed by a colon
+    fn word_is_name(&mut self) -> Result<bool, String> {
+        let (pos, peeked) = (self.pos, self.peeked.clone());
+        let is_name =
+            matches!(self.next()?, Some(Token::Word(_))) && self.peek()? == Some(Token::Colon);
+        self.pos = pos;
+        self.peeked = peeked;
+        Ok(is_name)
+    }
+
+    fn read(&mut self) -> Result<Option<Token<'a>>, String> {
+        let bytes = self.text.as_bytes();
+        loop {
+            while self.pos < bytes.len() && bytes[self.pos].is_ascii_whitespace() {
+                self.pos += 1;
+            }
+            if bytes.get(self.pos) == Some(&b';') {
+                while self.pos < bytes.len() && bytes[self.pos] != b'\n' {
+                    self.pos += 1;
+                }
+            } else {
+                break;
+            }
+        }
+        let Some(&c) = bytes.get(self.pos) else {
+            return Ok(None);
+        };
+        let start = self.pos;
+        self.pos += 1;
+        let token = match c {
+            b':' => Token::Colon,
+            b',' => Token::Comma,
+            b'*' => Token::Star,
+            b'{' => Token::Open,
+            b'}' => Token::Close,
+            b'"' => {
+                let len = self.text[self.pos..]
+                    .find('"')
+                    .ok_or_else(|| self.error("string doesn't end"))?;
+                self.pos += len + 1;
+                Token::String(&self.text[start + 1..self.pos - 1])
+            }
+            _ => {
+                let is_word = |c: u8| c.is_ascii_alphanumeric() || b"_-+.|".contains(&c);
+                if !is_word(c) {
+                    return Err(self.error(&format!("unexpected '{}'", c as char)));
+                }
+                while self.pos < bytes.len() && is_word(bytes[self.pos]) {
+                    self.pos += 1;
+                }
+                let word = &self.text[start..self.pos];
+                if c.is_ascii_digit() || c == b'-' || c == b'+' || c == b'.' {
+                    Token::Number(word)
+                } else {
+                    Token::Word(word)
+                }
+            }
+        };
+        Ok(Some(token))
+    }
+}
+
+// Nodes until a closing brace or the end of the file
+fn ascii_nodes(tokens: &mut Tokens) -> Result<Vec<FbxNode>, String> {
+    let mut nodes = Vec::new();
+    while let Some(Token::Word(name)) = tokens.peek()? {
+        tokens.next()?;
+        if tokens.next()? != Some(Token::Colon) {
+            return Err(tokens.error(&format!("expected ':' after {}", name)));
+        }
+        let mut node = FbxNode {
+            name: name.to_string(),
+            properties: Vec::new(),
+            children: Vec::new(),
+        };
+        // properties are separated by commas, the last one is followed by the next node's name,
+        // a closing brace or the node's children. Embedded files start with an empty one, as in
+        //     Content: , "base64..."
+        if tokens.peek()? == Some(Token::Comma) {
+            tokens.next()?;
+        }
+        // arrays are written as *count { a: values }
+        let mut array = false;
+        loop {
+            match tokens.peek()? {
+                Some(Token::Number(_) | Token::String(_)) => {
+                    node.properties.push(ascii_property(tokens)?)
+                }
+                Some(Token::Star) if node.properties.is_empty() => {
+                    tokens.next()?;
+                    match tokens.next()? {
+                        Some(Token::Number(count)) if count.parse::<u64>().is_ok() => array = true,
+                        Some(Token::Number(_)) => return Err(tokens.error("bad array length")),
+                        _ => return Err(tokens.error("expected an array length after *")),
+                    }
+                    break;
+                }
+                // a bare value like T, unless it's the next node's name
+                Some(Token::Word(word))
+                    if !node.properties.is_empty() || !tokens.word_is_name()? =>
+                {
+                    tokens.next()?;
+                    node.properties.push(FbxProperty::String(word.to_string()));
+                }
+                _ => break,
+            }
+            if tokens.peek()? == Some(Token::Comma) {
+                tokens.next()?;
+            } else {
+                break;
+            }
+        }
+        if tokens.peek()? == Some(Token::Open) {
+            tokens.next()?;
+            node.children = ascii_nodes(tokens)?;
+            if tokens.next()? != Some(Token::Close) {
+                return Err(tokens.error(&format!("{} isn't closed", node.name)));
+            }
+            if array {
+                match &node.children[..] {
+                    [values] if values.name == "a" => {
+                        node.properties = vec![to_array(&values.properties)];
+                        node.children.clear();
+                    }
+                    _ => return Err(tokens.error(&format!("{} has no a: values", node.name))),
+                }
+            }
+        } else if array {
+            return Err(tokens.error(&format!("{} has no values after its length", node.name)));
+        }
+        nodes.push(node);
+    }
+    Ok(nodes)
+}
+
+fn to_array(values: &[FbxProperty]) -> FbxProperty {
+    if values.iter().all(|v| matches!(v, FbxProperty::Int(_))) {
+        FbxProperty::IntArray(values.iter().filter_map(FbxProperty::as_i64).collect())
+    } else {
+        FbxProperty::FloatArray(values.iter().filter_map(FbxProperty::as_f64).collect())
+    }
+}
+
+fn ascii_property(tokens: &mut Tokens) -> Result<FbxProperty, String> {
+    match tokens.next()? {
+        Some(Token::Number(number)) => number
+            .parse()
+            .map(FbxProperty::Int)
+            .or_else(|_| number.parse().map(FbxProperty::Float))
+            .map_err(|_| tokens.error(&format!("bad number {}", number))),
+        Some(Token::String(string)) => Ok(FbxProperty::String(string.to_string())),
+        token => Err(tokens.error(&format!("unexpected {:?}", token))),
+    }
+}
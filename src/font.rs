@@ -0,0 +1,513 @@
+use crate::LoadError;
+use std::collections::HashMap;
+use std::path::Path;
+
+// One character's bitmap and where it goes relative to the pen
+#[derive(Clone, Debug)]
+pub struct Glyph {
+    pub width: usize,
+    pub height: usize,
+    // width x height, top row first, true where the glyph is drawn
+    pub pixels: Vec<bool>,
+    // from the pen position to the bitmap's left edge
+    pub x_offset: i32,
+    // from the top of the line to the bitmap's top edge
+    pub y_offset: i32,
+    // how far the pen moves right afterwards
+    pub advance: usize,
+}
+
+// A font of fixed bitmaps, either the built in 5x7 one or loaded from a BDF or PSF file
+#[derive(Clone, Debug)]
+pub struct BitmapFont {
+    // distance between the tops of consecutive lines
+    pub line_height: usize,
+    pub glyphs: HashMap<char, Glyph>,
+}
+
+impl BitmapFont {
+    // 5x7 pixel ASCII characters in 6x9 cells
+    pub fn builtin() -> BitmapFont {
+        let glyphs = BUILTIN_GLYPHS
+            .iter()
+            .enumerate()
+            .map(|(i, rows)| {
+                let pixels = rows
+                    .iter()
+                    .flat_map(|row| (0..5).rev().map(move |bit| row & (1 << bit) != 0))
+                    .collect();
+                let glyph = Glyph {
+                    width: 5,
+                    height: 7,
+                    pixels,
+                    x_offset: 0,
+                    y_offset: 1,
+                    advance: 6,
+                };
+                (char::from(b' ' + i as u8), glyph)
+            })
+            .collect();
+        BitmapFont {
+            line_height: 9,
+            glyphs,
+        }
+    }
+
+    // Loads a .bdf or .psf (version 1 or 2) font
+    pub fn load(path: &str) -> Result<BitmapFont, LoadError> {
+        let bytes = std::fs::read(path).map_err(|error| LoadError::Io {
+            path: path.into(),
+            error,
+        })?;
+        let extension = Path::new(path)
+            .extension()
+            .map_or(String::new(), |e| e.to_string_lossy().to_lowercase());
+        match extension.as_str() {
+            "bdf" => parse_bdf(&String::from_utf8_lossy(&bytes)),
+            "psf" | "psfu" => parse_psf(&bytes),
+            _ => Err("only .bdf and .psf fonts are supported".to_string()),
+        }
+        .map_err(|e| LoadError::invalid(path, e))
+    }
+
+    // Characters the font doesn't have are drawn as '?', or left out if it hasn't got that either
+    pub fn glyph(&self, c: char) -> Option<&Glyph> {
+        self.glyphs.get(&c).or_else(|| self.glyphs.get(&'?'))
+    }
+
+    // Width of the widest line and the height of all of them, in unscaled pixels
+    pub fn measure(&self, text: &str) -> (usize, usize) {
+        let width = text
+            .lines()
+            .map(|line| self.line_width(line))
+            .max()
+            .unwrap_or(0);
+        (width, text.lines().count().max(1) * self.line_height)
+    }
+
+    pub fn line_width(&self, line: &str) -> usize {
+        line.chars()
+            .filter_map(|c| self.glyph(c))
+            .map(|glyph| glyph.advance)
+            .sum()
+    }
+}
+
+// Glyph Bitmap Distribution Format, the text format X11 bitmap fonts come in
+fn parse_bdf(text: &str) -> Result<BitmapFont, String> {
+    let mut glyphs = HashMap::new();
+    let mut ascent = None;
+    let mut descent = None;
+    // height and y offset from FONTBOUNDINGBOX, for fonts without FONT_ASCENT/FONT_DESCENT
+    let mut bounding_box = None;
+    let mut lines = text.lines().enumerate();
+    while let Some((i, line)) = lines.next() {
+        let error = |message: &str| format!("line {}: {}", i + 1, message);
+        let mut words = line.split_whitespace();
+        let numbers = |words: std::str::SplitWhitespace| {
+            words
+                .map(|word| word.parse::<i32>())
+                .collect::<Result<Vec<i32>, _>>()
+                .map_err(|_| error("expected numbers"))
+        };
+        match words.next() {
+            Some("FONT_ASCENT") => ascent = numbers(words)?.first().copied(),
+            Some("FONT_DESCENT") => descent = numbers(words)?.first().copied(),
+            Some("FONTBOUNDINGBOX") => match numbers(words)?[..] {
+                [_, height, _, y_offset] => bounding_box = Some((height, y_offset)),
+                _ => return Err(error("FONTBOUNDINGBOX needs 4 numbers")),
+            },
+            Some("STARTCHAR") => {
+                let (c, glyph) = parse_bdf_char(&mut lines)?;
+                // characters without a unicode encoding are -1
+                if let Some(c) = c {
+                    glyphs.insert(c, glyph);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let (ascent, descent) = match (ascent, descent, bounding_box) {
+        (Some(ascent), Some(descent), _) => (ascent, descent),
+        (_, _, Some((height, y_offset))) => (height + y_offset, -y_offset),
+        _ => return Err("no FONT_ASCENT, FONT_DESCENT or FONTBOUNDINGBOX".to_string()),
+    };
+    if glyphs.is_empty() {
+        return Err("there are no characters".to_string());
+    }
+    // glyph offsets were relative to the baseline until now
+    for glyph in glyphs.values_mut() {
+        glyph.y_offset += ascent;
+    }
+    Ok(BitmapFont {
+        line_height: (ascent + descent).max(1) as usize,
+        glyphs,
+    })
+}
+
+// Reads from just after STARTCHAR up to ENDCHAR. The glyph's y_offset is relative to the baseline.
+fn parse_bdf_char<'a>(
+    lines: &mut impl Iterator<Item = (usize, &'a str)>,
+) -> Result<(Option<char>, Glyph), String> {
+    let mut encoding = None;
+    let mut advance = None;
+    let mut bbx = None;
+    while let Some((i, line)) = lines.next() {
+        let error = |message: &str| format!("line {}: {}", i + 1, message);
+        let mut words = line.split_whitespace();
+        let keyword = words.next();
+        let numbers = words
+            .map(|word| word.parse::<i32>())
+            .collect::<Result<Vec<i32>, _>>();
+        match keyword {
+            Some("ENCODING") => {
+                let number = numbers.ok().and_then(|n| n.first().copied());
+                encoding = Some(number.ok_or_else(|| error("ENCODING needs a number"))?);
+            }
+            Some("DWIDTH") => {
+                advance = numbers.ok().and_then(|n| n.first().copied());
+            }
+            Some("BBX") => match numbers.as_deref() {
+                // rows are read into a u128
+                Ok(&[width, height, x_offset, y_offset])
+                    if (0..=128).contains(&width) && height >= 0 =>
+                {
+                    bbx = Some((width as usize, height as usize, x_offset, y_offset))
+                }
+                _ => {
+                    return Err(error(
+                        "BBX needs a width (up to 128), height and x and y offsets",
+                    ))
+                }
+            },
+            Some("BITMAP") => {
+                let (width, height, x_offset, y_offset) =
+                    bbx.ok_or_else(|| error("BITMAP before BBX"))?;
+                let mut pixels = Vec::with_capacity(width * height);
+                for _ in 0..height {
+                    let (i, row) = lines
+                        .next()
+                        .ok_or_else(|| "the file ends in the middle of a character".to_string())?;
+                    let row = u128::from_str_radix(row.trim(), 16)
+                        .map_err(|_| format!("line {}: bitmap rows have to be hex", i + 1))?;
+                    // rows are padded to whole bytes, leftmost pixel in the top bit
+                    let bits = width.div_ceil(8) * 8;
+                    pixels.extend((0..width).map(|x| row & (1 << (bits - 1 - x)) != 0));
+                }
+                let encoding = encoding.ok_or_else(|| error("BITMAP before ENCODING"))?;
+                let glyph = Glyph {
+                    width,
+                    height,
+                    pixels,
+                    x_offset,
+                    // from the baseline up to the bitmap's bottom edge, turned into down to its top
+                    y_offset: -(y_offset + height as i32),
+                    advance: advance.unwrap_or(width as i32).max(0) as usize,
+                };
+                let c = u32::try_from(encoding).ok().and_then(char::from_u32);
+                return Ok((c, glyph));
+            }
+            Some("ENDCHAR") => return Err(error("ENDCHAR without a BITMAP")),
+            _ => {}
+        }
+    }
+    Err("the file ends in the middle of a character".to_string())
+}
+
+// PC Screen Font, the Linux console's format. Every glyph is the same size.
+fn parse_psf(bytes: &[u8]) -> Result<BitmapFont, String> {
+    let truncated = || "file is truncated".to_string();
+    let u32_at = |offset: usize| {
+        bytes
+            .get(offset..offset + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+            .ok_or_else(truncated)
+    };
+    // (glyph count, width, height, bytes per glyph, where glyphs start, has a unicode table)
+    let (count, width, height, glyph_size, start, has_table) = match bytes {
+        [0x36, 0x04, mode, size, ..] => {
+            let count = if mode & 0x01 != 0 { 512 } else { 256 };
+            let has_table = mode & 0x06 != 0;
+            (count, 8, *size as usize, *size as usize, 4, has_table)
+        }
+        [0x72, 0xb5, 0x4a, 0x86, ..] => {
+            let header_size = u32_at(8)?;
+            let has_table = u32_at(12)? & 0x01 != 0;
+            let (count, glyph_size, height, width) =
+                (u32_at(16)?, u32_at(20)?, u32_at(24)?, u32_at(28)?);
+            if glyph_size < width.div_ceil(8) * height {
+                return Err("glyphs are smaller than their width and height".to_string());
+            }
+            (count, width, height, glyph_size, header_size, has_table)
+        }
+        _ => return Err("not a PSF font".to_string()),
+    };
+    let table_start = start + count * glyph_size;
+    if bytes.len() < table_start {
+        return Err(truncated());
+    }
+
+    let row_size = width.div_ceil(8);
+    let glyph = |index: usize| {
+        let data = &bytes[start + index * glyph_size..];
+        Glyph {
+            width,
+            height,
+            pixels: (0..height)
+                .flat_map(|y| {
+                    (0..width).map(move |x| data[y * row_size + x / 8] & (0x80 >> (x % 8)) != 0)
+                })
+                .collect(),
+            x_offset: 0,
+            y_offset: 0,
+            advance: width,
+        }
+    };
+
+    let mut glyphs = HashMap::new();
+    if has_table {
+        for (index, chars) in psf_unicode_table(&bytes[table_start..], bytes[0] == 0x36)
+            .into_iter()
+            .take(count)
+            .enumerate()
+        {
+            for c in chars {
+                glyphs.entry(c).or_insert_with(|| glyph(index));
+            }
+        }
+    } else {
+        // without a table the glyphs are in the order of their code points
+        for index in 0..count.min(256) {
+            glyphs.insert(char::from(index as u8), glyph(index));
+        }
+    }
+    Ok(BitmapFont {
+        line_height: height,
+        glyphs,
+    })
+}
+
+// The characters each glyph draws. Sequences of combining characters are skipped, as only single
+// characters get looked up.
+fn psf_unicode_table(table: &[u8], version_1: bool) -> Vec<Vec<char>> {
+    let mut glyphs = Vec::new();
+    let mut chars = Vec::new();
+    let mut in_sequence = false;
+    if version_1 {
+        // UCS-2, 0xffff ends a glyph's entry and 0xfffe starts a sequence
+        for pair in table.chunks_exact(2) {
+            match u16::from_le_bytes([pair[0], pair[1]]) {
+                0xffff => {
+                    glyphs.push(std::mem::take(&mut chars));
+                    in_sequence = false;
+                }
+                0xfffe => in_sequence = true,
+                c if !in_sequence => chars.extend(char::from_u32(c as u32)),
+                _ => {}
+            }
+        }
+    } else {
+        // UTF-8, 0xff ends a glyph's entry and 0xfe starts a sequence
+        for entry in table.split(|&b| b == 0xff) {
+            let single = entry.split(|&b| b == 0xfe).next().unwrap_or(&[]);
+            glyphs.push(String::from_utf8_lossy(single).chars().collect());
+        }
+    }
+    glyphs
+}
+
+// ' ' to '~', one u8 per row with the leftmost pixel in bit 4
+#[rustfmt::skip]
+const BUILTIN_GLYPHS: [[u8; 7]; 95] = [
+    // ' '
+    [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000],
+    // '!'
+    [0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00000, 0b00100],
+    // '"'
+    [0b01010, 0b01010, 0b01010, 0b00000, 0b00000, 0b00000, 0b00000],
+    // '#'
+    [0b01010, 0b01010, 0b11111, 0b01010, 0b11111, 0b01010, 0b01010],
+    // '$'
+    [0b00100, 0b01111, 0b10100, 0b01110, 0b00101, 0b11110, 0b00100],
+    // '%'
+    [0b11000, 0b11001, 0b00010, 0b00100, 0b01000, 0b10011, 0b00011],
+    // '&'
+    [0b01100, 0b10010, 0b10100, 0b01000, 0b10101, 0b10010, 0b01101],
+    // '\''
+    [0b00100, 0b00100, 0b01000, 0b00000, 0b00000, 0b00000, 0b00000],
+    // '('
+    [0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010],
+    // ')'
+    [0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000],
+    // '*'
+    [0b00000, 0b00100, 0b10101, 0b01110, 0b10101, 0b00100, 0b00000],
+    // '+'
+    [0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000],
+    // ','
+    [0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b00100, 0b01000],
+    // '-'
+    [0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000],
+    // '.'
+    [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100],
+    // '/'
+    [0b00000, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b00000],
+    // '0'
+    [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110],
+    // '1'
+    [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110],
+    // '2'
+    [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111],
+    // '3'
+    [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110],
+    // '4'
+    [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010],
+    // '5'
+    [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110],
+    // '6'
+    [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110],
+    // '7'
+    [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000],
+    // '8'
+    [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110],
+    // '9'
+    [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100],
+    // ':'
+    [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000],
+    // ';'
+    [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b00100, 0b01000],
+    // '<'
+    [0b00010, 0b00100, 0b01000, 0b10000, 0b01000, 0b00100, 0b00010],
+    // '='
+    [0b00000, 0b00000, 0b11111, 0b00000, 0b11111, 0b00000, 0b00000],
+    // '>'
+    [0b01000, 0b00100, 0b00010, 0b00001, 0b00010, 0b00100, 0b01000],
+    // '?'
+    [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100],
+    // '@'
+    [0b01110, 0b10001, 0b00001, 0b01101, 0b10101, 0b10101, 0b01110],
+    // 'A'
+    [0b01110, 0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001],
+    // 'B'
+    [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110],
+    // 'C'
+    [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110],
+    // 'D'
+    [0b11100, 0b10010, 0b10001, 0b10001, 0b10001, 0b10010, 0b11100],
+    // 'E'
+    [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111],
+    // 'F'
+    [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000],
+    // 'G'
+    [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111],
+    // 'H'
+    [0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001],
+    // 'I'
+    [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110],
+    // 'J'
+    [0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100],
+    // 'K'
+    [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001],
+    // 'L'
+    [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111],
+    // 'M'
+    [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001],
+    // 'N'
+    [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001],
+    // 'O'
+    [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110],
+    // 'P'
+    [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000],
+    // 'Q'
+    [0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101],
+    // 'R'
+    [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001],
+    // 'S'
+    [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110],
+    // 'T'
+    [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100],
+    // 'U'
+    [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110],
+    // 'V'
+    [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100],
+    // 'W'
+    [0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010],
+    // 'X'
+    [0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001],
+    // 'Y'
+    [0b10001, 0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100],
+    // 'Z'
+    [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111],
+    // '['
+    [0b01110, 0b01000, 0b01000, 0b01000, 0b01000, 0b01000, 0b01110],
+    // '\\'
+    [0b00000, 0b10000, 0b01000, 0b00100, 0b00010, 0b00001, 0b00000],
+    // ']'
+    [0b01110, 0b00010, 0b00010, 0b00010, 0b00010, 0b00010, 0b01110],
+    // '^'
+    [0b00100, 0b01010, 0b10001, 0b00000, 0b00000, 0b00000, 0b00000],
+    // '_'
+    [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111],
+    // '`'
+    [0b01000, 0b00100, 0b00010, 0b00000, 0b00000, 0b00000, 0b00000],
+    // 'a'
+    [0b00000, 0b00000, 0b01110, 0b00001, 0b01111, 0b10001, 0b01111],
+    // 'b'
+    [0b10000, 0b10000, 0b10110, 0b11001, 0b10001, 0b10001, 0b11110],
+    // 'c'
+    [0b00000, 0b00000, 0b01110, 0b10000, 0b10000, 0b10001, 0b01110],
+    // 'd'
+    [0b00001, 0b00001, 0b01101, 0b10011, 0b10001, 0b10001, 0b01111],
+    // 'e'
+    [0b00000, 0b00000, 0b01110, 0b10001, 0b11111, 0b10000, 0b01110],
+    // 'f'
+    [0b00110, 0b01001, 0b01000, 0b11100, 0b01000, 0b01000, 0b01000],
+    // 'g'
+    [0b00000, 0b01111, 0b10001, 0b10001, 0b01111, 0b00001, 0b01110],
+    // 'h'
+    [0b10000, 0b10000, 0b10110, 0b11001, 0b10001, 0b10001, 0b10001],
+    // 'i'
+    [0b00100, 0b00000, 0b01100, 0b00100, 0b00100, 0b00100, 0b01110],
+    // 'j'
+    [0b00010, 0b00000, 0b00110, 0b00010, 0b00010, 0b10010, 0b01100],
+    // 'k'
+    [0b10000, 0b10000, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010],
+    // 'l'
+    [0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110],
+    // 'm'
+    [0b00000, 0b00000, 0b11010, 0b10101, 0b10101, 0b10001, 0b10001],
+    // 'n'
+    [0b00000, 0b00000, 0b10110, 0b11001, 0b10001, 0b10001, 0b10001],
+    // 'o'
+    [0b00000, 0b00000, 0b01110, 0b10001, 0b10001, 0b10001, 0b01110],
+    // 'p'
+    [0b00000, 0b00000, 0b11110, 0b10001, 0b11110, 0b10000, 0b10000],
+    // 'q'
+    [0b00000, 0b00000, 0b01101, 0b10011, 0b01111, 0b00001, 0b00001],
+    // 'r'
+    [0b00000, 0b00000, 0b10110, 0b11001, 0b10000, 0b10000, 0b10000],
+    // 's'
+    [0b00000, 0b00000, 0b01110, 0b10000, 0b01110, 0b00001, 0b11110],
+    // 't'
+    [0b01000, 0b01000, 0b11100, 0b01000, 0b01000, 0b01001, 0b00110],
+    // 'u'
+    [0b00000, 0b00000, 0b10001, 0b10001, 0b10001, 0b10011, 0b01101],
+    // 'v'
+    [0b00000, 0b00000, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100],
+    // 'w'
+    [0b00000, 0b00000, 0b10001, 0b10001, 0b10101, 0b10101, 0b01010],
+    // 'x'
+    [0b00000, 0b00000, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001],
+    // 'y'
+    [0b00000, 0b00000, 0b10001, 0b10001, 0b01111, 0b00001, 0b01110],
+    // 'z'
+    [0b00000, 0b00000, 0b11111, 0b00010, 0b00100, 0b01000, 0b11111],
+    // '{'
+    [0b00010, 0b00100, 0b00100, 0b01000, 0b00100, 0b00100, 0b00010],
+    // '|'
+    [0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100],
+    // '}'
+    [0b01000, 0b00100, 0b00100, 0b00010, 0b00100, 0b00100, 0b01000],
+    // '~'
+    [0b00000, 0b00000, 0b01000, 0b10101, 0b00010, 0b00000, 0b00000],
+];
@@ -0,0 +1,151 @@
+use crate::CameraController;
+use crate::Hud;
+use crate::Rasteriser;
+use std::time::{Duration, Instant};
+
+// Somewhere to show frames and get input from, like a window. The rasteriser itself knows
+// nothing about them, so it can be used as a library without any windowing.
+pub trait Frontend {
+    // Shows a frame of 0xRRGGBB pixels, top row first like Rasteriser::buffer
+    fn present(&mut self, buffer: &[u32], width: usize, height: usize) -> std::io::Result<()>;
+
+    // Everything that happened since the last call, oldest first
+    fn poll_events(&mut self) -> Vec<Event>;
+
+    // Time since the frontend was created, which doesn't have to be wall clock time, e.g. when
+    // rendering frames to files
+    fn time(&self) -> Duration;
+
+    // Whether there's someone to send key and mouse events, which there isn't e.g. when rendering
+    // frames to files
+    fn interactive(&self) -> bool {
+        true
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    // the window was closed, or there is nothing more to render
+    Close,
+    KeyDown(Key),
+    KeyUp(Key),
+    // in pixels from the top left of the frame
+    MouseMove { x: f32, y: f32 },
+    MouseDown(MouseButton),
+    MouseUp(MouseButton),
+    // positive y scrolls up/away from the user
+    Scroll { x: f32, y: f32 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Key {
+    // a to z and 0 to 9, always lower case
+    Char(char),
+    // function keys, F(1) to F(12)
+    F(u8),
+    Escape,
+    Enter,
+    Space,
+    Tab,
+    Backspace,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Minus,
+    Equal,
+    // either side's
+    Shift,
+    Control,
+    Alt,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+}
+
+// Renders and presents frames until the frontend closes or Escape is pressed, with the camera
+// moved around by a CameraController and the hud drawn over them. H shows and hides the hud.
+pub fn run(
+    frontend: &mut impl Frontend,
+    rasteriser: &mut Rasteriser,
+    hud: &mut Hud,
+) -> std::io::Result<()> {
+    let mut controller = CameraController::new(rasteriser);
+    let mut last_time = frontend.time();
+    let mut last_frame = None;
+    loop {
+        for event in frontend.poll_events() {
+            if let Event::Close | Event::KeyDown(Key::Escape) = event {
+                return Ok(());
+            }
+            if let Event::KeyDown(key) = event {
+                toggle_render_setting(key, rasteriser);
+                if key == Key::Char('h') {
+                    hud.visible = !hud.visible;
+                }
+            }
+            controller.handle_event(&event, rasteriser);
+        }
+        let time = frontend.time();
+        controller.update((time - last_time).as_secs_f32(), rasteriser);
+        last_time = time;
+
+        let start = Instant::now();
+        rasteriser.render_frame();
+        // wall clock time even when the frontend's time() isn't, so the hud shows the real rate
+        let frame_time = last_frame.map_or(Duration::ZERO, |last_frame: Instant| {
+            start.duration_since(last_frame)
+        });
+        let camera_mode = frontend.interactive().then_some(controller.mode);
+        hud.draw(rasteriser, start.elapsed(), frame_time, camera_mode);
+        last_frame = Some(start);
+
+        let (width, height) = rasteriser.size();
+        frontend.present(&rasteriser.buffer, width, height)?;
+    }
+}
+
+// Render settings bound to keys, saying what they changed to:
+//
+//     M   cycle through points, wireframe, flat and gouraud shading
+//     O   wireframe overlay on and off
+//     T   textures on and off
+//     L   lighting on and off
+//     V   cycle through the debug views
+pub fn toggle_render_setting(key: Key, rasteriser: &mut Rasteriser) {
+    let on_off = |on: bool| if on { "on" } else { "off" };
+    match key {
+        Key::Char('m') => {
+            rasteriser.shading = rasteriser.shading.next();
+            println!("Shading: {:?}", rasteriser.shading);
+        }
+        Key::Char('o') => {
+            rasteriser.wireframe_overlay = !rasteriser.wireframe_overlay;
+            println!(
+                "Wireframe overlay: {}",
+                on_off(rasteriser.wireframe_overlay)
+            );
+        }
+        Key::Char('t') => {
+            rasteriser.textures = !rasteriser.textures;
+            println!("Textures: {}", on_off(rasteriser.textures));
+        }
+        Key::Char('l') => {
+            rasteriser.lighting = !rasteriser.lighting;
+            println!("Lighting: {}", on_off(rasteriser.lighting));
+        }
+        Key::Char('v') => {
+            rasteriser.debug_view = rasteriser.debug_view.next();
+            println!("Debug view: {:?}", rasteriser.debug_view);
+        }
+        _ => {}
+    }
+}
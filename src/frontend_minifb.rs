@@ -1,38 +1,122 @@
 extern crate minifb;
 
-use crate::Rasteriser;
-use minifb::{Key, Window, WindowOptions};
+use crate::Event;
+use crate::Frontend;
+use crate::Key;
+use crate::MouseButton;
+use minifb::{KeyRepeat, MouseMode, Window, WindowOptions};
+use std::time::{Duration, Instant};
 
-pub struct Frontend {
+// A window to render into, behind the minifb feature
+pub struct MinifbFrontend {
     window: Window,
-    width: usize,
-    height: usize,
-    rasteriser: Rasteriser,
+    start: Instant,
+    // last polled mouse state, to turn into events
+    mouse_position: Option<(f32, f32)>,
+    mouse_down: [bool; 3],
 }
 
-impl Frontend {
-    pub fn new(width: usize, height: usize, rasteriser: Rasteriser) -> Frontend {
+const MOUSE_BUTTONS: [(minifb::MouseButton, MouseButton); 3] = [
+    (minifb::MouseButton::Left, MouseButton::Left),
+    (minifb::MouseButton::Middle, MouseButton::Middle),
+    (minifb::MouseButton::Right, MouseButton::Right),
+];
+
+impl MinifbFrontend {
+    pub fn new(width: usize, height: usize) -> std::io::Result<MinifbFrontend> {
         let mut window = Window::new("GFX Programming", width, height, WindowOptions::default())
-            .unwrap_or_else(|e| {
-                panic!("{}", e);
-            });
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
         // Limit to max ~60 fps update rate
         window.limit_update_rate(Some(std::time::Duration::from_micros(16600)));
-        Frontend {
+        Ok(MinifbFrontend {
             window,
-            width,
-            height,
-            rasteriser,
-        }
+            start: Instant::now(),
+            mouse_position: None,
+            mouse_down: [false; 3],
+        })
+    }
+}
+
+impl Frontend for MinifbFrontend {
+    fn present(&mut self, buffer: &[u32], width: usize, height: usize) -> std::io::Result<()> {
+        self.window
+            .update_with_buffer(buffer, width, height)
+            .map_err(|e| std::io::Error::other(e.to_string()))
     }
 
-    pub fn run(&mut self) {
-        while self.window.is_open() && !self.window.is_key_down(Key::Escape) {
-            self.rasteriser.render_frame();
-            // We unwrap here as we want this code to exit if it fails. Real applications may want to handle this in a different way
-            self.window
-                .update_with_buffer(&self.rasteriser.buffer, self.width, self.height)
-                .unwrap();
+    // minifb only picks up input when presenting, so this sees what happened up to the last frame
+    fn poll_events(&mut self) -> Vec<Event> {
+        if !self.window.is_open() {
+            return vec![Event::Close];
+        }
+        let mut events = Vec::new();
+        for key in self.window.get_keys_pressed(KeyRepeat::No) {
+            events.extend(convert_key(key).map(Event::KeyDown));
+        }
+        for key in self.window.get_keys_released() {
+            events.extend(convert_key(key).map(Event::KeyUp));
+        }
+
+        let position = self.window.get_mouse_pos(MouseMode::Discard);
+        if position != self.mouse_position {
+            if let Some((x, y)) = position {
+                events.push(Event::MouseMove { x, y });
+            }
+            self.mouse_position = position;
         }
+        for (i, (minifb_button, button)) in MOUSE_BUTTONS.into_iter().enumerate() {
+            let down = self.window.get_mouse_down(minifb_button);
+            if down != self.mouse_down[i] {
+                events.push(match down {
+                    true => Event::MouseDown(button),
+                    false => Event::MouseUp(button),
+                });
+                self.mouse_down[i] = down;
+            }
+        }
+        if let Some((x, y)) = self.window.get_scroll_wheel() {
+            events.push(Event::Scroll { x, y });
+        }
+        events
     }
+
+    fn time(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+// None for keys nothing uses yet
+fn convert_key(key: minifb::Key) -> Option<Key> {
+    use minifb::Key as K;
+    // digits, letters and function keys are numbered in order
+    Some(match key {
+        _ if (K::Key0 as u8..=K::Key9 as u8).contains(&(key as u8)) => {
+            Key::Char((b'0' + key as u8 - K::Key0 as u8) as char)
+        }
+        _ if (K::A as u8..=K::Z as u8).contains(&(key as u8)) => {
+            Key::Char((b'a' + key as u8 - K::A as u8) as char)
+        }
+        _ if (K::F1 as u8..=K::F12 as u8).contains(&(key as u8)) => {
+            Key::F(key as u8 - K::F1 as u8 + 1)
+        }
+        K::Escape => Key::Escape,
+        K::Enter | K::NumPadEnter => Key::Enter,
+        K::Space => Key::Space,
+        K::Tab => Key::Tab,
+        K::Backspace => Key::Backspace,
+        K::Up => Key::Up,
+        K::Down => Key::Down,
+        K::Left => Key::Left,
+        K::Right => Key::Right,
+        K::Home => Key::Home,
+        K::End => Key::End,
+        K::PageUp => Key::PageUp,
+        K::PageDown => Key::PageDown,
+        K::Minus | K::NumPadMinus => Key::Minus,
+        K::Equal | K::NumPadPlus => Key::Equal,
+        K::LeftShift | K::RightShift => Key::Shift,
+        K::LeftCtrl | K::RightCtrl => Key::Control,
+        K::LeftAlt | K::RightAlt => Key::Alt,
+        _ => return None,
+    })
 }
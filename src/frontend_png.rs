@@ -0,0 +1,86 @@
+use crate::write_png;
+use crate::Event;
+use crate::Frontend;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+// Frames are this far apart as far as time() goes, whatever time they take to render, so the
+// turntable and camera move the same amount each frame
+const FRAME_TIME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+// Renders without a window, for machines without a display like servers and CI, writing each
+// frame to a png and closing after the last one
+pub struct PngFrontend {
+    frames: usize,
+    path: PathBuf,
+    // frames written so far
+    frame: usize,
+}
+
+impl PngFrontend {
+    pub fn new(frames: usize, path: impl Into<PathBuf>) -> PngFrontend {
+        PngFrontend {
+            frames,
+            path: path.into(),
+            frame: 0,
+        }
+    }
+
+    // The path itself for a single frame, otherwise numbered like out_0000.png, out_0001.png...
+    fn frame_path(&self, frame: usize) -> PathBuf {
+        if self.frames == 1 {
+            return self.path.clone();
+        }
+        let stem = self
+            .path
+            .file_stem()
+            .map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+        let extension = self
+            .path
+            .extension()
+            .map_or(String::from("png"), |e| e.to_string_lossy().into_owned());
+        self.path
+            .with_file_name(format!("{}_{:04}.{}", stem, frame, extension))
+    }
+}
+
+impl Frontend for PngFrontend {
+    fn present(&mut self, buffer: &[u32], width: usize, height: usize) -> std::io::Result<()> {
+        let path = self.frame_path(self.frame);
+        save_buffer(&path, buffer, width, height)
+            .map_err(|e| std::io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        println!("Wrote {}", path.display());
+        self.frame += 1;
+        Ok(())
+    }
+
+    fn poll_events(&mut self) -> Vec<Event> {
+        match self.frame >= self.frames {
+            true => vec![Event::Close],
+            false => Vec::new(),
+        }
+    }
+
+    fn time(&self) -> Duration {
+        FRAME_TIME * self.frame as u32
+    }
+
+    fn interactive(&self) -> bool {
+        false
+    }
+}
+
+// Writes a buffer of 0xRRGGBB pixels, top row first like Rasteriser::buffer, to a png
+pub fn save_buffer(
+    path: &Path,
+    buffer: &[u32],
+    width: usize,
+    height: usize,
+) -> std::io::Result<()> {
+    let rgb: Vec<u8> = buffer[..width * height]
+        .iter()
+        .flat_map(|&pixel| [(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8])
+        .collect();
+    write_png(path, width, height, &rgb)
+}
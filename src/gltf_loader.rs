@@ -0,0 +1,428 @@
+use crate::decode_image;
+use crate::Camera;
+use crate::Color;
+use crate::Image;
+use crate::Light;
+use crate::LoadError;
+use crate::MeshRef;
+use crate::NodeId;
+use crate::ObjData;
+use crate::Sampler;
+use crate::Scene;
+use crate::Submesh;
+use crate::TextureData;
+use crate::TextureFilter;
+use crate::WrapMode;
+use base64::Engine;
+use cgmath::point3;
+use cgmath::vec3;
+use cgmath::InnerSpace;
+use cgmath::Matrix4;
+use cgmath::Rad;
+use cgmath::SquareMatrix;
+use gltf::khr_lights_punctual::Kind;
+use gltf::mesh::Mode;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::path::Path;
+use std::path::PathBuf;
+
+// Loads a glTF 2.0 model, either a .gltf with its buffers in data URIs or files next to it, or a
+// .glb with them in the binary chunk.
+//
+// The model's default scene ends up under a node named after the file, with a node for every
+// glTF node. Each primitive becomes a mesh with a single submesh, tinted by the material's base
+// color and textured with its base color texture. Cameras and directional lights
+// (KHR_lights_punctual) get attached to their nodes, everything else (other material
+// properties, skins, animations, morph targets) is ignored.
+pub fn load_gltf(
+    scene: &mut Scene,
+    path: &str,
+    parent: Option<NodeId>,
+) -> Result<NodeId, LoadError> {
+    let path = Path::new(path);
+    let bytes = std::fs::read(path).map_err(|error| LoadError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    let gltf =
+        gltf::Gltf::from_slice(&bytes).map_err(|e| LoadError::invalid(path, e.to_string()))?;
+    let mut loader = GltfLoader {
+        path,
+        buffers: Vec::new(),
+        images: HashMap::new(),
+        meshes: HashMap::new(),
+        ancestors: HashSet::new(),
+    };
+    for buffer in gltf.buffers() {
+        let data = match buffer.source() {
+            gltf::buffer::Source::Bin => gltf.blob.clone().ok_or_else(|| {
+                LoadError::invalid(path, "there's no binary chunk for the buffer")
+            })?,
+            gltf::buffer::Source::Uri(uri) => loader.read_uri(uri)?,
+        };
+        if data.len() < buffer.length() {
+            return Err(LoadError::invalid(
+                path,
+                format!(
+                    "buffer {} is {} bytes long, not {}",
+                    buffer.index(),
+                    data.len(),
+                    buffer.length()
+                ),
+            ));
+        }
+        loader.buffers.push(data);
+    }
+
+    let gltf_scene = gltf
+        .default_scene()
+        .or_else(|| gltf.scenes().next())
+        .ok_or_else(|| LoadError::invalid(path, "there's no scene to load"))?;
+    let name = path
+        .file_stem()
+        .map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+    let root = scene.add_node(&name, parent, Matrix4::identity());
+    for node in gltf_scene.nodes() {
+        loader.add_node(scene, &node, root)?;
+    }
+    Ok(root)
+}
+
+struct GltfLoader<'a> {
+    path: &'a Path,
+    buffers: Vec<Vec<u8>>,
+    // decoded when a material first uses them, by glTF image index
+    images: HashMap<usize, Image>,
+    // the scene meshes each glTF mesh's primitives became, so meshes used by several nodes are
+    // only loaded once
+    meshes: HashMap<usize, Vec<usize>>,
+    // glTF indices of the nodes above the one being added, to catch children arrays that loop
+    ancestors: HashSet<usize>,
+}
+
+impl GltfLoader<'_> {
+    fn add_node(
+        &mut self,
+        scene: &mut Scene,
+        node: &gltf::Node,
+        parent: NodeId,
+    ) -> Result<(), LoadError> {
+        if !self.ancestors.insert(node.index()) {
+            return Err(LoadError::invalid(
+                self.path,
+                format!("node {} is its own ancestor", node.index()),
+            ));
+        }
+        let name = match node.name() {
+            Some(name) => name.to_string(),
+            None => format!("node {}", node.index()),
+        };
+        let transform = Matrix4::from(node.transform().matrix());
+        let id = scene.add_node(&name, Some(parent), transform);
+
+        if let Some(mesh) = node.mesh() {
+            let meshes = self.load_mesh(scene, &mesh)?;
+            let mesh_ref = |mesh| MeshRef {
+                mesh,
+                submeshes: vec![0],
+            };
+            if let [mesh] = meshes[..] {
+                scene.nodes[id].mesh = Some(mesh_ref(mesh));
+            } else {
+                for (i, &mesh) in meshes.iter().enumerate() {
+                    let child =
+                        scene.add_node(&format!("{} {}", name, i), Some(id), Matrix4::identity());
+                    scene.nodes[child].mesh = Some(mesh_ref(mesh));
+                }
+            }
+        }
+
+        if let Some(camera) = node.camera() {
+            match camera.projection() {
+                // looks down the node's -z like Camera::default()
+                gltf::camera::Projection::Perspective(perspective) => {
+                    let near = perspective.znear();
+                    scene.nodes[id].camera = Some(Camera {
+                        fov: Rad(perspective.yfov()).into(),
+                        near,
+                        // infinite projections aren't supported, so pick something far away
+                        far: perspective.zfar().unwrap_or(near * 1e4),
+                        ..Camera::default()
+                    })
+                }
+                gltf::camera::Projection::Orthographic(_) => {
+                    println!("Skipping orthographic camera in {}", self.path.display())
+                }
+            }
+        }
+
+        if let Some(light) = node.light() {
+            match light.kind() {
+                // also points down the node's -z
+                Kind::Directional => {
+                    let what = format!("light of node {}", node.index());
+                    scene.nodes[id].light = Some(Light {
+                        color: self.color(light.color(), &what)?,
+                        intensity: light.intensity(),
+                        ..Light::default()
+                    })
+                }
+                _ => println!(
+                    "Skipping point or spot light in {}, only directional lights are supported",
+                    self.path.display()
+                ),
+            }
+        }
+
+        for child in node.children() {
+            self.add_node(scene, &child, id)?;
+        }
+        self.ancestors.remove(&node.index());
+        Ok(())
+    }
+
+    // Adds a mesh for each of the glTF mesh's primitives to the scene, returning their indices
+    fn load_mesh(&mut self, scene: &mut Scene, mesh: &gltf::Mesh) -> Result<Vec<usize>, LoadError> {
+        if let Some(meshes) = self.meshes.get(&mesh.index()) {
+            return Ok(meshes.clone());
+        }
+        let name = match mesh.name() {
+            Some(name) => name.to_string(),
+            None => format!("mesh {}", mesh.index()),
+        };
+        let mut meshes = Vec::new();
+        for primitive in mesh.primitives() {
+            if let Some(obj) = self.load_primitive(&name, &primitive)? {
+                meshes.push(scene.meshes.len());
+                scene.meshes.push(obj);
+            }
+        }
+        self.meshes.insert(mesh.index(), meshes.clone());
+        Ok(meshes)
+    }
+
+    fn load_primitive(
+        &mut self,
+        name: &str,
+        primitive: &gltf::Primitive,
+    ) -> Result<Option<ObjData>, LoadError> {
+        let reader = primitive.reader(|buffer| Some(&self.buffers[buffer.index()]));
+        let positions: Vec<[f32; 3]> = reader
+            .read_positions()
+            .ok_or_else(|| {
+                LoadError::invalid(
+                    self.path,
+                    format!("a primitive of {} has no positions", name),
+                )
+            })?
+            .collect();
+        let indices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..positions.len() as u32).collect(),
+        };
+        if let Some(&i) = indices.iter().find(|&&i| i as usize >= positions.len()) {
+            return Err(LoadError::invalid(
+                self.path,
+                format!("index {} in {} is out of bounds", i, name),
+            ));
+        }
+        let triangles: Vec<[usize; 3]> = match primitive.mode() {
+            Mode::Triangles => indices
+                .chunks_exact(3)
+                .map(|t| [t[0], t[1], t[2]].map(|i| i as usize))
+                .collect(),
+            // every other triangle is flipped to keep them all wound the same way
+            Mode::TriangleStrip => (0..indices.len().saturating_sub(2))
+                .map(|i| [i, i + 1 + i % 2, i + 2 - i % 2].map(|i| indices[i] as usize))
+                .collect(),
+            Mode::TriangleFan => (1..indices.len().saturating_sub(1))
+                .map(|i| [i, i + 1, 0].map(|i| indices[i] as usize))
+                .collect(),
+            mode => {
+                println!("Skipping {:?} primitive in {}", mode, name);
+                return Ok(None);
+            }
+        };
+        if triangles.is_empty() {
+            return Ok(None);
+        }
+
+        let tri_positions: Vec<_> = triangles
+            .iter()
+            .map(|t| t.map(|i| point3(positions[i][0], positions[i][1], positions[i][2])))
+            .collect();
+
+        // the other attributes are indexed the same way as the positions
+        let check_count = |attribute: &str, count: usize| {
+            if count == positions.len() {
+                return Ok(());
+            }
+            Err(LoadError::invalid(
+                self.path,
+                format!(
+                    "{} has {} {} but {} positions",
+                    name,
+                    count,
+                    attribute,
+                    positions.len()
+                ),
+            ))
+        };
+        let tri_normals = match reader.read_normals() {
+            Some(normals) => {
+                let normals: Vec<_> = normals
+                    .map(|n| vec3(n[0], n[1], n[2]).normalize())
+                    .collect();
+                check_count("normals", normals.len())?;
+                Some(triangles.iter().map(|t| t.map(|i| normals[i])).collect())
+            }
+            None => None,
+        };
+
+        let material = primitive.material().pbr_metallic_roughness();
+        let [r, g, b, _] = material.base_color_factor();
+        let color = self.color([r, g, b], &format!("base color of {}", name))?;
+        let mut textures = Vec::new();
+        let mut tri_textures = None;
+        if let Some(info) = material.base_color_texture() {
+            match reader.read_tex_coords(info.tex_coord()) {
+                Some(tex_coords) => {
+                    let tex_coords: Vec<[f32; 2]> = tex_coords.into_f32().collect();
+                    check_count("texture coordinates", tex_coords.len())?;
+                    let texture = info.texture();
+                    let (pixels, width, height) = self.image(&texture.source())?;
+                    let sampler = sampler(&texture.sampler());
+                    // glTF's v goes down the image, textures are stored bottom row first
+                    let point = |i: usize| point3(tex_coords[i][0], 1. - tex_coords[i][1], 0.);
+                    tri_textures = Some(
+                        triangles
+                            .iter()
+                            .map(|t| TextureData {
+                                sampler,
+                                ..TextureData::new(pixels.clone(), width, height, t.map(point))
+                            })
+                            .collect(),
+                    );
+                    textures.push(pixels);
+                }
+                None => println!(
+                    "{} has a texture but no TEXCOORD_{}, drawing it untextured",
+                    name,
+                    info.tex_coord()
+                ),
+            }
+        }
+
+        let submesh = Submesh {
+            color,
+            ..Submesh::new(name, 0..tri_positions.len(), &tri_positions)
+        };
+        Ok(Some(ObjData::from_triangles(
+            tri_positions,
+            tri_textures,
+            tri_normals,
+            textures,
+            vec![submesh],
+        )))
+    }
+
+    fn image(&mut self, image: &gltf::Image) -> Result<Image, LoadError> {
+        if let Some(decoded) = self.images.get(&image.index()) {
+            return Ok(decoded.clone());
+        }
+        let bytes = match image.source() {
+            gltf::image::Source::View { view, .. } => {
+                let buffer = &self.buffers[view.buffer().index()];
+                view.offset()
+                    .checked_add(view.length())
+                    .and_then(|end| buffer.get(view.offset()..end))
+                    .ok_or_else(|| {
+                        LoadError::invalid(
+                            self.path,
+                            format!("image {} is past the end of its buffer", image.index()),
+                        )
+                    })?
+                    .to_vec()
+            }
+            gltf::image::Source::Uri { uri, .. } => self.read_uri(uri)?,
+        };
+        let decoded = decode_image(&bytes).map_err(|e| {
+            LoadError::invalid(self.path, format!("image {}: {}", image.index(), e))
+        })?;
+        println!("Loaded texture: image {} of {:?}", image.index(), self.path);
+        self.images.insert(image.index(), decoded.clone());
+        Ok(decoded)
+    }
+
+    // Clamped between 0 and 1, as long as it's a number to begin with
+    fn color(&self, [r, g, b]: [f32; 3], what: &str) -> Result<Color, LoadError> {
+        if ![r, g, b].iter().all(|c| c.is_finite()) {
+            return Err(LoadError::invalid(
+                self.path,
+                format!("the {} isn't finite", what),
+            ));
+        }
+        let [r, g, b] = [r, g, b].map(|c| c.clamp(0., 1.));
+        Ok(Color::new(r, g, b))
+    }
+
+    // Contents of a data URI, or of a file relative to the model
+    fn read_uri(&self, uri: &str) -> Result<Vec<u8>, LoadError> {
+        if let Some(data) = uri.strip_prefix("data:") {
+            let (_, base64) = data.split_once(";base64,").ok_or_else(|| {
+                LoadError::invalid(self.path, "only base64 data URIs are supported")
+            })?;
+            return base64::engine::general_purpose::STANDARD
+                .decode(base64)
+                .map_err(|e| LoadError::invalid(self.path, format!("bad data URI: {}", e)));
+        }
+        let path: PathBuf = self
+            .path
+            .parent()
+            .unwrap_or(Path::new(""))
+            .join(percent_decode(uri));
+        std::fs::read(&path).map_err(|error| LoadError::Io { path, error })
+    }
+}
+
+fn sampler(sampler: &gltf::texture::Sampler) -> Sampler {
+    let wrap = |mode| match mode {
+        gltf::texture::WrappingMode::Repeat => WrapMode::Repeat,
+        gltf::texture::WrappingMode::MirroredRepeat => WrapMode::MirroredRepeat,
+        gltf::texture::WrappingMode::ClampToEdge => WrapMode::ClampToEdge,
+    };
+    Sampler {
+        wrap_s: wrap(sampler.wrap_s()),
+        wrap_t: wrap(sampler.wrap_t()),
+        // without mipmaps the minification filter doesn't matter
+        filter: match sampler.mag_filter() {
+            Some(gltf::texture::MagFilter::Nearest) => TextureFilter::Nearest,
+            _ => TextureFilter::Linear,
+        },
+    }
+}
+
+// URIs of files can have %XX escapes in them, e.g. %20 for spaces
+pub fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escape {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,48 @@
+use crate::CompareFunc;
+
+// Hierarchical Z: a coarse buffer with the range of depths stored in each BLOCK_SIZE x BLOCK_SIZE
+// block of pixels, so blocks (and whole triangles) that can't pass the depth test get skipped
+// without looking at any of their samples.
+
+// Bounds on every depth stored in a block. These are allowed to be looser than the real range,
+// writes only mark the block as dirty and it gets recalculated the next time it's tested.
+#[derive(Clone, Copy, Debug)]
+pub struct DepthBounds {
+    pub min: f32,
+    pub max: f32,
+    pub dirty: bool,
+}
+
+impl DepthBounds {
+    pub fn new(depth: f32) -> DepthBounds {
+        DepthBounds {
+            min: depth,
+            max: depth,
+            dirty: false,
+        }
+    }
+
+    // Whether every fragment with a depth in z_min..=z_max is certain to fail the depth test
+    // against every depth in the block
+    pub fn rejects(&self, func: CompareFunc, z_min: f32, z_max: f32) -> bool {
+        match func {
+            CompareFunc::Never => true,
+            CompareFunc::Less => z_min >= self.max,
+            CompareFunc::LessEqual => z_min > self.max,
+            CompareFunc::Greater => z_max <= self.min,
+            CompareFunc::GreaterEqual => z_max < self.min,
+            CompareFunc::Equal => z_max < self.min || z_min > self.max,
+            CompareFunc::NotEqual | CompareFunc::Always => false,
+        }
+    }
+}
+
+// Range of depths the fragments of a triangle with the given vertex depths can have. The
+// interpolated depth is a weighted sum with f32 weights that don't quite add up to 1, so it can
+// land a few ulps outside of the vertex depths, and the range is widened to make up for it.
+pub fn depth_range(z: [f32; 3]) -> (f32, f32) {
+    let min = z[0].min(z[1]).min(z[2]);
+    let max = z[0].max(z[1]).max(z[2]);
+    let margin = min.abs().max(max.abs()) * 16. * f32::EPSILON;
+    (min - margin, max + margin)
+}
@@ -0,0 +1,94 @@
+use crate::BitmapFont;
+use crate::CameraMode;
+use crate::Color;
+use crate::Rasteriser;
+use crate::TextStyle;
+use std::time::Duration;
+
+// How much of each new frame time goes into the averages, lower is steadier but slower to follow
+const SMOOTHING: f32 = 0.1;
+
+// Text in the top left corner of the frame about how it was drawn and with which settings
+pub struct Hud {
+    pub visible: bool,
+    pub font: BitmapFont,
+    pub style: TextStyle,
+    // moving averages in seconds, None before the first frame
+    render_time: Option<f32>,
+    frame_time: Option<f32>,
+}
+
+impl Hud {
+    pub fn new(visible: bool) -> Hud {
+        Hud {
+            visible,
+            font: BitmapFont::builtin(),
+            style: TextStyle {
+                background: Some(Color::new(0., 0., 0.)),
+                ..TextStyle::default()
+            },
+            render_time: None,
+            frame_time: None,
+        }
+    }
+
+    // Draws over the rasteriser's finished frame. render_time is how long the frame took to draw
+    // and frame_time how long since the last one was shown, which is zero for the first frame.
+    // camera_mode is left off when there's no input to change it.
+    pub fn draw(
+        &mut self,
+        rasteriser: &mut Rasteriser,
+        render_time: Duration,
+        frame_time: Duration,
+        camera_mode: Option<CameraMode>,
+    ) {
+        let average = |average: Option<f32>, time: Duration| {
+            let time = time.as_secs_f32();
+            Some(average.map_or(time, |average| average + (time - average) * SMOOTHING))
+        };
+        self.render_time = average(self.render_time, render_time);
+        if !frame_time.is_zero() {
+            self.frame_time = average(self.frame_time, frame_time);
+        }
+        if !self.visible {
+            return;
+        }
+
+        let on_off = |on: bool| if on { "on" } else { "off" };
+        let stats = rasteriser.stats();
+        let mut timing = format!("{:.1} ms to render", self.render_time.unwrap() * 1000.);
+        if let Some(frame_time) = self.frame_time {
+            timing += &format!(", {:.1} fps", 1. / frame_time);
+        }
+        let ms = |time: Duration| time.as_secs_f32() * 1000.;
+        let text = [
+            timing,
+            format!(
+                "vertex {:.1}, setup {:.1}, raster {:.1}, post {:.1} ms",
+                ms(stats.vertex_time),
+                ms(stats.setup_time),
+                ms(stats.raster_time),
+                ms(stats.post_time)
+            ),
+            format!(
+                "{} of {} triangles, {} pixels shaded",
+                stats.triangles_rasterised, stats.triangles_submitted, stats.fragments_shaded
+            ),
+            format!(
+                "{:?} shading, overlay {}, textures {}, lighting {}",
+                rasteriser.shading,
+                on_off(rasteriser.wireframe_overlay),
+                on_off(rasteriser.textures),
+                on_off(rasteriser.lighting)
+            ),
+            match camera_mode {
+                Some(mode) => format!("{:?} view, {:?} camera", rasteriser.debug_view, mode),
+                None => format!("{:?} view", rasteriser.debug_view),
+            },
+        ]
+        .join("\n");
+        // clear of the background's padding
+        let margin = 2 * self.style.scale as i32;
+        rasteriser.draw_text(&self.font, &text, margin, margin, &self.style);
+    }
+}
@@ -1,36 +1,122 @@
+mod bounds;
+mod bvh;
+mod camera;
+mod camera_controller;
+mod cli;
+mod collada_loader;
 mod color;
+mod fbx_loader;
+mod fbx_parser;
+mod font;
+mod frontend;
+#[cfg(feature = "minifb")]
 mod frontend_minifb;
+mod frontend_png;
+mod gltf_loader;
+mod hiz;
+mod hud;
+mod line;
+mod load_error;
 mod mtl_parser;
+mod multisample;
+mod obj_export;
 mod obj_parser;
+mod ply_loader;
+mod post_process;
 mod rasteriser;
-use color::*;
-use frontend_minifb::Frontend;
-use mtl_parser::*;
-use obj_parser::*;
-use rasteriser::*;
+mod render_state;
+mod render_stats;
+mod scene;
+mod scene_file;
+mod simd;
+mod stl_loader;
+mod text;
+mod texture;
+mod tile;
+mod triangle_setup;
+pub use bounds::*;
+pub use bvh::*;
+pub use camera::*;
+pub use camera_controller::*;
+pub use cli::*;
+pub use collada_loader::*;
+pub use color::*;
+pub use fbx_loader::*;
+pub use fbx_parser::*;
+pub use font::*;
+pub use frontend::*;
+#[cfg(feature = "minifb")]
+pub use frontend_minifb::*;
+pub use frontend_png::*;
+pub use gltf_loader::*;
+pub use hiz::*;
+pub use hud::*;
+pub use line::*;
+pub use load_error::*;
+pub use mtl_parser::*;
+pub use multisample::*;
+pub use obj_export::*;
+pub use obj_parser::*;
+pub use ply_loader::*;
+pub use post_process::*;
+pub use rasteriser::*;
+pub use render_state::*;
+pub use render_stats::*;
+pub use scene::*;
+pub use scene_file::*;
+pub use simd::*;
+pub use stl_loader::*;
+pub use text::*;
+pub use texture::*;
+pub use tile::*;
+pub use triangle_setup::*;
 
-// TODO: camera
-// TODO: multithreading and optimisations so that performance isn't ass
 // TODO: wrap up matrices in neat package
-// TODO: make it possible to specify model multiplcation matrixces
 // TODO: egui?
 // TODO: better obj file handling
-// TODO: look into implementing other file format parsers (FBX? Collada?)
 // TODO: writing own matrix library?
 pub fn main() {
-    const WIDTH: usize = 1000;
-    const HEIGHT: usize = 1000;
-    let mut r = Rasteriser::new(WIDTH, HEIGHT);
-    //r.load_obj("./models/african_head.obj"); // manually supply a texture
-    //r.load_obj("./models/teapot.obj");
-    //r.load_obj("./models/Ansem_and_Guardian/Ansem_and_Guardian.obj");
-    //r.load_obj("./models/Ansem_WoC/Ansem_WoC.obj");
-    //r.load_obj("./models/Tear_5/Tear.obj");
-    r.load_obj("./models/Sora_KH1/Sora_KH1.obj"); // zbuffer?
+    let exit = |message: &str| -> ! {
+        eprintln!("{}", message);
+        std::process::exit(1);
+    };
+    let options = Options::parse(std::env::args().skip(1))
+        .unwrap_or_else(|e| exit(&format!("{}\nSee --help for the options", e)));
+    if options.help {
+        print!("{}", USAGE);
+        return;
+    }
 
-    //r.load_obj("./models/destiny_islands/skybox/skybox.obj"); // texture index errors
-    //r.load_obj("./models/destiny_islands/level/di00_01.obj"); // same here
-    //r.load_obj("./models/tekken_temple/temple.obj"); // 8 slashes for faces??
-    let mut frontend = Frontend::new(WIDTH, HEIGHT, r);
-    frontend.run();
+    let mut r = options.rasteriser().unwrap_or_else(|e| exit(&e));
+    let result = match &options.png {
+        Some(png) => run(
+            &mut PngFrontend::new(options.frames.unwrap_or(1), png),
+            &mut r,
+            &mut Hud::new(options.hud),
+        ),
+        None => open_window(&mut r),
+    };
+    result.unwrap_or_else(|e| exit(&e.to_string()));
+    if let (Some(path), Some(history)) = (&options.stats, &r.stats_history) {
+        history
+            .save_csv(path)
+            .unwrap_or_else(|e| exit(&format!("{}: {}", path, e)));
+    }
+}
+
+#[cfg(feature = "minifb")]
+fn open_window(r: &mut Rasteriser) -> std::io::Result<()> {
+    let (width, height) = r.size();
+    run(
+        &mut MinifbFrontend::new(width, height)?,
+        r,
+        &mut Hud::new(true),
+    )
+}
+
+#[cfg(not(feature = "minifb"))]
+fn open_window(_: &mut Rasteriser) -> std::io::Result<()> {
+    Err(std::io::Error::other(
+        "built without the minifb feature, so can only render to a file with --png",
+    ))
 }
@@ -0,0 +1,533 @@
+use crate::Color;
+use cgmath::point3;
+use cgmath::Point3;
+
+// Everything in here works in screen/raster space: x and y are in pixels (origin bottom left) and
+// z is whatever depth value the caller wants interpolated along the line.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineJoin {
+    // falls back to a bevel once the miter gets longer than miter_limit * width
+    Miter,
+    Bevel,
+    Round,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineCap {
+    Butt,
+    Square,
+    Round,
+}
+
+#[derive(Clone)]
+pub struct LineStyle {
+    pub color: Color,
+    // in pixels, anything <= 1 is drawn as a single pixel wide line
+    pub width: f32,
+    pub anti_aliased: bool,
+    pub join: LineJoin,
+    pub cap: LineCap,
+    pub miter_limit: f32,
+    // alternating on/off lengths in pixels, starting with on. None draws a solid line
+    pub dash: Option<Vec<f32>>,
+    // only applies to 3D lines, 2D lines are always drawn on top
+    pub depth_test: bool,
+    // pulls the line towards the camera before depth testing, so lines drawn over the surface they
+    // came from don't z-fight with it
+    pub depth_bias: f32,
+}
+
+impl Default for LineStyle {
+    fn default() -> LineStyle {
+        LineStyle {
+            color: Color::new(1., 1., 1.),
+            width: 1.,
+            anti_aliased: false,
+            join: LineJoin::Miter,
+            cap: LineCap::Butt,
+            miter_limit: 4.,
+            dash: None,
+            depth_test: false,
+            depth_bias: 0.,
+        }
+    }
+}
+
+#[inline(always)]
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+#[inline(always)]
+fn lerp_point(a: Point3<f32>, b: Point3<f32>, t: f32) -> Point3<f32> {
+    point3(lerp(a.x, b.x, t), lerp(a.y, b.y, t), lerp(a.z, b.z, t))
+}
+
+// Cohen-Sutherland, the part of the line from p0 to p1 with x and y inside the inclusive range min
+// to max, z interpolated along with them. None if none of it is inside, or it isn't finite. Ends
+// that get clipped are put exactly on the edge they're clipped to, and the maths is done in f64,
+// so lines reaching far off screen (e.g. from vertices close to the camera plane) are still cut
+// at the right place.
+pub fn clip_line(
+    p0: Point3<f32>,
+    p1: Point3<f32>,
+    min: (f32, f32),
+    max: (f32, f32),
+) -> Option<(Point3<f32>, Point3<f32>)> {
+    if ![p0.x, p0.y, p0.z, p1.x, p1.y, p1.z]
+        .iter()
+        .all(|v| v.is_finite())
+    {
+        return None;
+    }
+    const LEFT: u8 = 1;
+    const RIGHT: u8 = 2;
+    const BOTTOM: u8 = 4;
+    const TOP: u8 = 8;
+    let (min_x, min_y, max_x, max_y) = (min.0 as f64, min.1 as f64, max.0 as f64, max.1 as f64);
+    let outcode = |p: [f64; 3]| {
+        let mut code = 0;
+        if p[0] < min_x {
+            code |= LEFT;
+        } else if p[0] > max_x {
+            code |= RIGHT;
+        }
+        if p[1] < min_y {
+            code |= BOTTOM;
+        } else if p[1] > max_y {
+            code |= TOP;
+        }
+        code
+    };
+    let to_f64 = |p: Point3<f32>| [p.x as f64, p.y as f64, p.z as f64];
+    let (mut a, mut b) = (to_f64(p0), to_f64(p1));
+    let (mut code_a, mut code_b) = (outcode(a), outcode(b));
+    while code_a | code_b != 0 {
+        if code_a & code_b != 0 {
+            return None;
+        }
+        // moves an end that's outside onto the edge it's outside of
+        let code = if code_a != 0 { code_a } else { code_b };
+        let (axis, bound) = match code {
+            _ if code & LEFT != 0 => (0, min_x),
+            _ if code & RIGHT != 0 => (0, max_x),
+            _ if code & BOTTOM != 0 => (1, min_y),
+            _ => (1, max_y),
+        };
+        let t = (bound - a[axis]) / (b[axis] - a[axis]);
+        let mut p = [0.; 3];
+        for (k, c) in p.iter_mut().enumerate() {
+            *c = a[k] + (b[k] - a[k]) * t;
+        }
+        p[axis] = bound;
+        if code == code_a {
+            a = p;
+            code_a = outcode(a);
+        } else {
+            b = p;
+            code_b = outcode(b);
+        }
+    }
+    let to_f32 = |p: [f64; 3]| point3(p[0] as f32, p[1] as f32, p[2] as f32);
+    Some((to_f32(a), to_f32(b)))
+}
+
+// Plain Bresenham, calls plot(x, y, t) where t is how far along the line the pixel is
+pub fn bresenham_line(p0: Point3<f32>, p1: Point3<f32>, mut plot: impl FnMut(i32, i32, f32)) {
+    let (mut x0, mut y0) = (p0.x.round() as i32, p0.y.round() as i32);
+    let (x1, y1) = (p1.x.round() as i32, p1.y.round() as i32);
+    let dx = (x1 - x0).abs();
+    let dy = -(y1 - y0).abs();
+    let sx: i32 = if x0 < x1 { 1 } else { -1 };
+    let sy: i32 = if y0 < y1 { 1 } else { -1 };
+    let steps = dx.max(-dy).max(1) as f32;
+    let mut step = 0;
+    let mut err = dx + dy;
+    loop {
+        plot(x0, y0, step as f32 / steps);
+        if x0 == x1 && y0 == y1 {
+            break;
+        }
+        let e2 = err * 2;
+        // why, in the name of all that is holy and good,
+        // does this not work if we substitue e2 with err*2
+        // WTF?
+        if e2 >= dy {
+            err += dy;
+            x0 += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y0 += sy;
+        }
+        step += 1;
+    }
+}
+
+// Xiaolin Wu's line algorithm, calls plot(x, y, coverage, t) for the two pixels straddling the
+// line at every step along the major axis
+pub fn wu_line(p0: Point3<f32>, p1: Point3<f32>, mut plot: impl FnMut(i32, i32, f32, f32)) {
+    let steep = (p1.y - p0.y).abs() > (p1.x - p0.x).abs();
+    // work as if the line is x major, swapping back when plotting
+    let (mut a, mut b) = if steep {
+        (point3(p0.y, p0.x, p0.z), point3(p1.y, p1.x, p1.z))
+    } else {
+        (p0, p1)
+    };
+    let reversed = a.x > b.x;
+    if reversed {
+        std::mem::swap(&mut a, &mut b);
+    }
+    let length = b.x - a.x;
+    let gradient = if length == 0. {
+        1.
+    } else {
+        (b.y - a.y) / length
+    };
+    let mut plot = |x: i32, y: i32, coverage: f32, major: f32| {
+        let t = if length == 0. {
+            0.
+        } else {
+            ((major - a.x) / length).clamp(0., 1.)
+        };
+        let t = if reversed { 1. - t } else { t };
+        if steep {
+            plot(y, x, coverage, t);
+        } else {
+            plot(x, y, coverage, t);
+        }
+    };
+    let fract = |v: f32| v - v.floor();
+
+    // first endpoint
+    let x_end = a.x.round();
+    let y_end = a.y + gradient * (x_end - a.x);
+    let x_gap = 1. - fract(a.x + 0.5);
+    let x_start = x_end as i32;
+    plot(
+        x_start,
+        y_end.floor() as i32,
+        (1. - fract(y_end)) * x_gap,
+        x_end,
+    );
+    plot(
+        x_start,
+        y_end.floor() as i32 + 1,
+        fract(y_end) * x_gap,
+        x_end,
+    );
+    let mut inter_y = y_end + gradient;
+
+    // second endpoint
+    let x_end = b.x.round();
+    let y_end = b.y + gradient * (x_end - b.x);
+    let x_gap = fract(b.x + 0.5);
+    let x_finish = x_end as i32;
+    if x_finish == x_start {
+        return;
+    }
+    plot(
+        x_finish,
+        y_end.floor() as i32,
+        (1. - fract(y_end)) * x_gap,
+        x_end,
+    );
+    plot(
+        x_finish,
+        y_end.floor() as i32 + 1,
+        fract(y_end) * x_gap,
+        x_end,
+    );
+
+    for x in x_start + 1..x_finish {
+        plot(x, inter_y.floor() as i32, 1. - fract(inter_y), x as f32);
+        plot(x, inter_y.floor() as i32 + 1, fract(inter_y), x as f32);
+        inter_y += gradient;
+    }
+}
+
+// Cuts a polyline up into the "on" parts of a dash pattern. The pattern carries on across
+// vertices rather than restarting at each segment.
+pub fn dash_polyline(points: &[Point3<f32>], pattern: &[f32]) -> Vec<Vec<Point3<f32>>> {
+    let mut dashes = Vec::new();
+    let total: f32 = pattern.iter().sum();
+    if pattern.is_empty() || total <= 0. || pattern.iter().any(|l| *l < 0.) {
+        dashes.push(points.to_vec());
+        return dashes;
+    }
+
+    let mut index = 0;
+    let mut remaining = pattern[0];
+    let mut current: Vec<Point3<f32>> = Vec::new();
+    if let Some(first) = points.first() {
+        current.push(*first);
+    }
+    for segment in points.windows(2) {
+        let (a, b) = (segment[0], segment[1]);
+        let length = ((b.x - a.x).powi(2) + (b.y - a.y).powi(2)).sqrt();
+        let mut travelled = 0.;
+        while length - travelled > remaining {
+            travelled += remaining;
+            let p = lerp_point(a, b, travelled / length);
+            // an "on" part finishing ends the dash, an "off" part finishing starts a new one
+            if index % 2 == 1 {
+                current.clear();
+            }
+            current.push(p);
+            if index % 2 == 0 {
+                dashes.push(std::mem::take(&mut current));
+            }
+            index = (index + 1) % pattern.len();
+            remaining = pattern[index];
+        }
+        remaining -= length - travelled;
+        if index % 2 == 0 {
+            current.push(b);
+        }
+    }
+    if index % 2 == 0 && current.len() > 1 {
+        dashes.push(current);
+    }
+    dashes.retain(|d| d.len() > 1);
+    dashes
+}
+
+// A convex piece of a thick line. Pieces are evaluated separately and combined by taking the
+// maximum coverage, so overlapping joins don't get blended twice.
+pub enum StrokePiece {
+    // a rectangle along the segment, optionally extended past its ends for square caps
+    Segment {
+        a: Point3<f32>,
+        b: Point3<f32>,
+        half_width: f32,
+        extend_start: f32,
+        extend_end: f32,
+    },
+    Polygon {
+        points: Vec<Point3<f32>>,
+        depth: f32,
+    },
+    Disc {
+        centre: Point3<f32>,
+        radius: f32,
+    },
+}
+
+impl StrokePiece {
+    // (min x, min y, max x, max y) in pixels, including a pixel of slack for anti-aliasing
+    pub fn bounds(&self) -> (i32, i32, i32, i32) {
+        let (min_x, min_y, max_x, max_y) = match self {
+            StrokePiece::Segment {
+                a,
+                b,
+                half_width,
+                extend_start,
+                extend_end,
+            } => {
+                let r = half_width + extend_start.max(*extend_end);
+                (
+                    a.x.min(b.x) - r,
+                    a.y.min(b.y) - r,
+                    a.x.max(b.x) + r,
+                    a.y.max(b.y) + r,
+                )
+            }
+            StrokePiece::Polygon { points, .. } => points.iter().fold(
+                (f32::MAX, f32::MAX, f32::MIN, f32::MIN),
+                |(min_x, min_y, max_x, max_y), p| {
+                    (
+                        min_x.min(p.x),
+                        min_y.min(p.y),
+                        max_x.max(p.x),
+                        max_y.max(p.y),
+                    )
+                },
+            ),
+            StrokePiece::Disc { centre, radius } => (
+                centre.x - radius,
+                centre.y - radius,
+                centre.x + radius,
+                centre.y + radius,
+            ),
+        };
+        // casts saturate, so pieces far off screen mustn't overflow
+        (
+            (min_x.floor() as i32).saturating_sub(1),
+            (min_y.floor() as i32).saturating_sub(1),
+            (max_x.ceil() as i32).saturating_add(1),
+            (max_y.ceil() as i32).saturating_add(1),
+        )
+    }
+
+    // Signed distance from (x, y) to the edge of the piece (negative inside) and the depth there
+    pub fn distance(&self, x: f32, y: f32) -> (f32, f32) {
+        match self {
+            StrokePiece::Segment {
+                a,
+                b,
+                half_width,
+                extend_start,
+                extend_end,
+            } => {
+                let (dx, dy) = (b.x - a.x, b.y - a.y);
+                let length = (dx * dx + dy * dy).sqrt().max(f32::EPSILON);
+                let (ux, uy) = (dx / length, dy / length);
+                let (px, py) = (x - a.x, y - a.y);
+                let along = px * ux + py * uy;
+                let across = (px * uy - py * ux).abs();
+                let depth = lerp(a.z, b.z, (along / length).clamp(0., 1.));
+                // box distance, relative to the middle of the (extended) segment
+                let centre = (length + extend_end - extend_start) * 0.5;
+                let half_length = (length + extend_start + extend_end) * 0.5;
+                let qx = (along - centre).abs() - half_length;
+                let qy = across - half_width;
+                let outside = (qx.max(0.).powi(2) + qy.max(0.).powi(2)).sqrt();
+                (outside + qx.max(qy).min(0.), depth)
+            }
+            StrokePiece::Polygon { points, depth } => {
+                // winding independent, distances to each edge are flipped to be negative inside
+                let area: f32 = points
+                    .iter()
+                    .zip(points.iter().cycle().skip(1))
+                    .map(|(a, b)| a.x * b.y - b.x * a.y)
+                    .sum();
+                let sign = if area >= 0. { 1. } else { -1. };
+                let distance = points
+                    .iter()
+                    .zip(points.iter().cycle().skip(1))
+                    .map(|(a, b)| {
+                        let (ex, ey) = (b.x - a.x, b.y - a.y);
+                        let length = (ex * ex + ey * ey).sqrt().max(f32::EPSILON);
+                        sign * ((x - a.x) * ey - (y - a.y) * ex) / length
+                    })
+                    .fold(f32::MIN, f32::max);
+                (distance, *depth)
+            }
+            StrokePiece::Disc { centre, radius } => (
+                ((x - centre.x).powi(2) + (y - centre.y).powi(2)).sqrt() - radius,
+                centre.z,
+            ),
+        }
+    }
+}
+
+// Breaks a thick polyline into segment, join and cap pieces
+pub fn stroke_pieces(points: &[Point3<f32>], closed: bool, style: &LineStyle) -> Vec<StrokePiece> {
+    let half_width = style.width * 0.5;
+    let mut pieces = Vec::new();
+    let n = points.len();
+    let segment_count = if closed { n } else { n - 1 };
+
+    for i in 0..segment_count {
+        let (a, b) = (points[i], points[(i + 1) % n]);
+        let first = !closed && i == 0;
+        let last = !closed && i == segment_count - 1;
+        let cap = |is_end: bool| if is_end { style.cap } else { LineCap::Butt };
+        // caps only apply to the open ends, the joins take care of everything in between
+        let (start_cap, end_cap) = (cap(first), cap(last));
+        let extend = |cap: LineCap| {
+            if cap == LineCap::Square {
+                half_width
+            } else {
+                0.
+            }
+        };
+        pieces.push(StrokePiece::Segment {
+            a,
+            b,
+            half_width,
+            extend_start: extend(start_cap),
+            extend_end: extend(end_cap),
+        });
+        if start_cap == LineCap::Round {
+            pieces.push(StrokePiece::Disc {
+                centre: a,
+                radius: half_width,
+            });
+        }
+        if end_cap == LineCap::Round {
+            pieces.push(StrokePiece::Disc {
+                centre: b,
+                radius: half_width,
+            });
+        }
+    }
+
+    let join_count = if closed { n } else { n.saturating_sub(2) };
+    for j in 0..join_count {
+        let i = if closed { j } else { j + 1 };
+        let prev = points[(i + n - 1) % n];
+        let vertex = points[i];
+        let next = points[(i + 1) % n];
+        if let Some(join) = join_piece(prev, vertex, next, half_width, style) {
+            pieces.push(join);
+        }
+    }
+    pieces
+}
+
+fn join_piece(
+    prev: Point3<f32>,
+    vertex: Point3<f32>,
+    next: Point3<f32>,
+    half_width: f32,
+    style: &LineStyle,
+) -> Option<StrokePiece> {
+    if style.join == LineJoin::Round {
+        return Some(StrokePiece::Disc {
+            centre: vertex,
+            radius: half_width,
+        });
+    }
+
+    let normalise = |x: f32, y: f32| {
+        let length = (x * x + y * y).sqrt();
+        if length == 0. {
+            None
+        } else {
+            Some((x / length, y / length))
+        }
+    };
+    let d0 = normalise(vertex.x - prev.x, vertex.y - prev.y)?;
+    let d1 = normalise(next.x - vertex.x, next.y - vertex.y)?;
+    let turn = d0.0 * d1.1 - d0.1 * d1.0;
+    if turn.abs() < 1e-6 {
+        return None;
+    }
+    // the outside of the turn is on the right for left turns and vice versa
+    let side = if turn > 0. { -1. } else { 1. };
+    let n0 = (d0.1 * side, -d0.0 * side);
+    let n1 = (d1.1 * side, -d1.0 * side);
+    let corner0 = point3(
+        vertex.x + n0.0 * half_width,
+        vertex.y + n0.1 * half_width,
+        vertex.z,
+    );
+    let corner1 = point3(
+        vertex.x + n1.0 * half_width,
+        vertex.y + n1.1 * half_width,
+        vertex.z,
+    );
+
+    if style.join == LineJoin::Miter {
+        let (mx, my) = normalise(n0.0 + n1.0, n0.1 + n1.1)?;
+        let cos_half = mx * n0.0 + my * n0.1;
+        let miter_length = half_width / cos_half.max(1e-6);
+        if miter_length <= style.miter_limit * half_width {
+            let tip = point3(
+                vertex.x + mx * miter_length,
+                vertex.y + my * miter_length,
+                vertex.z,
+            );
+            return Some(StrokePiece::Polygon {
+                points: vec![vertex, corner0, tip, corner1],
+                depth: vertex.z,
+            });
+        }
+    }
+    Some(StrokePiece::Polygon {
+        points: vec![vertex, corner0, corner1],
+        depth: vertex.z,
+    })
+}
@@ -0,0 +1,37 @@
+use std::fmt;
+use std::path::PathBuf;
+
+// Why a model file couldn't be loaded
+#[derive(Debug)]
+pub enum LoadError {
+    // the model, or a file it refers to, couldn't be read
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    // anything wrong with the contents of the file
+    Invalid {
+        path: PathBuf,
+        message: String,
+    },
+}
+
+impl LoadError {
+    pub fn invalid(path: impl Into<PathBuf>, message: impl Into<String>) -> LoadError {
+        LoadError::Invalid {
+            path: path.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            LoadError::Invalid { path, message } => write!(f, "{}: {}", path.display(), message),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
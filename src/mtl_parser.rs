@@ -1,37 +1,121 @@
+use crate::Color;
+use crate::LoadError;
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+// The options a map_ statement can have in front of its file, and how many values they take. -o, -s
+// and -t can leave out all but their first.
+const MAP_OPTIONS: [(&str, usize); 12] = [
+    ("-blendu", 1),
+    ("-blendv", 1),
+    ("-bm", 1),
+    ("-boost", 1),
+    ("-cc", 1),
+    ("-clamp", 1),
+    ("-imfchan", 1),
+    ("-mm", 2),
+    ("-o", 3),
+    ("-s", 3),
+    ("-t", 3),
+    ("-texres", 1),
+];
+
+// The file of a map_ statement, given the rest of its line. The options are skipped and the file
+// is everything after them, since file names can have spaces in them.
+fn map_file(mut rest: &str) -> Result<&str, String> {
+    loop {
+        rest = rest.trim_start();
+        let option = rest.split_whitespace().next().unwrap_or("");
+        let Some(&(_, values)) = MAP_OPTIONS.iter().find(|(name, _)| *name == option) else {
+            return Ok(rest.trim_end());
+        };
+        rest = &rest[option.len()..];
+        for i in 0..values {
+            let value = rest.split_whitespace().next();
+            match value {
+                // the optional values are numbers, anything else is where the file starts
+                Some(value) if i > 0 && value.parse::<f32>().is_err() => break,
+                Some(value) => rest = &rest.trim_start()[value.len()..],
+                None => return Err(format!("{} needs a value", option)),
+            }
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct MtlData {
     pub texture_path_map: HashMap<String, String>,
+    // Kd of the materials that have one
+    pub colors: HashMap<String, Color>,
 }
 
 impl MtlData {
+    // Panics if the file can't be loaded, see MtlData::load()
     pub fn new(mtl_path: &str) -> MtlData {
-        let file = File::open(mtl_path).unwrap();
+        MtlData::load(Path::new(mtl_path)).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn load(path: &Path) -> Result<MtlData, LoadError> {
+        let io_error = |error| LoadError::Io {
+            path: path.to_path_buf(),
+            error,
+        };
+        let file = File::open(path).map_err(io_error)?;
         let reader = BufReader::new(file);
         let mut texture_path_map: HashMap<String, String> = HashMap::new();
+        let mut colors: HashMap<String, Color> = HashMap::new();
 
         let mut current_mtl_name: Option<String> = None;
 
-        for line in reader.lines() {
-            let line = line.unwrap();
-            if line == "" {
-                continue;
-            }
+        for (i, line) in reader.lines().enumerate() {
+            let line = line.map_err(io_error)?;
+            let error =
+                |message: &str| LoadError::invalid(path, format!("line {}: {}", i + 1, message));
             let elements = line.split_whitespace().collect::<Vec<&str>>();
-            let id = elements[0];
+            let Some(&id) = elements.first() else {
+                continue;
+            };
+            let material = || {
+                current_mtl_name
+                    .clone()
+                    .ok_or_else(|| error(&format!("{} before any newmtl", id)))
+            };
             match id {
-                "newmtl" => current_mtl_name = Some(elements[1].to_string()),
+                "newmtl" => {
+                    let name = elements
+                        .get(1)
+                        .ok_or_else(|| error("newmtl needs a name"))?;
+                    current_mtl_name = Some(name.to_string());
+                }
                 "map_Kd" => {
-                    //FIXME: is .clone.unwrap an anti pattenrn?
-                    texture_path_map
-                        .insert(current_mtl_name.clone().unwrap(), elements[1].to_string());
+                    let file = map_file(&line.trim_start()[id.len()..]).map_err(|e| error(&e))?;
+                    if file.is_empty() {
+                        return Err(error("map_Kd needs a file"));
+                    }
+                    texture_path_map.insert(material()?, file.to_string());
+                }
+                "Kd" => {
+                    let rgb = elements[1..]
+                        .iter()
+                        .map(|c| {
+                            let c = c.parse::<f32>().ok().filter(|c| c.is_finite())?;
+                            Some(c.clamp(0., 1.))
+                        })
+                        .collect::<Option<Vec<f32>>>()
+                        .ok_or_else(|| error("Kd has to be numbers"))?;
+                    let [r, g, b] = rgb[..] else {
+                        return Err(error("Kd needs 3 components"));
+                    };
+                    colors.insert(material()?, Color::new(r, g, b));
                 }
                 _ => println!("Unhandled .mtl expression: {}", line),
             }
         }
-        MtlData { texture_path_map }
+        Ok(MtlData {
+            texture_path_map,
+            colors,
+        })
     }
 }
@@ -0,0 +1,62 @@
+// Number of coverage/depth samples stored per pixel. Shading still only runs once per pixel, so
+// this only smooths out geometry edges (textures are left alone).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SampleCount {
+    X1,
+    X2,
+    X4,
+    X8,
+}
+
+// The standard D3D sample patterns, in 1/16th of a pixel relative to the sample origin of the
+// pixel. y is flipped compared to D3D since our origin is bottom left.
+#[rustfmt::skip]
+const PATTERN_X1: [(i32, i32); 1] = [(0, 0)];
+#[rustfmt::skip]
+const PATTERN_X2: [(i32, i32); 2] = [(4, -4), (-4, 4)];
+#[rustfmt::skip]
+const PATTERN_X4: [(i32, i32); 4] = [(-2, 6), (6, 2), (-6, -2), (2, -6)];
+#[rustfmt::skip]
+const PATTERN_X8: [(i32, i32); 8] = [(1, 3), (-1, -3), (5, -1), (-3, 5),
+                                     (-5, -5), (-7, 1), (3, -7), (7, 7)];
+
+impl SampleCount {
+    pub fn samples(self) -> usize {
+        match self {
+            SampleCount::X1 => 1,
+            SampleCount::X2 => 2,
+            SampleCount::X4 => 4,
+            SampleCount::X8 => 8,
+        }
+    }
+
+    pub fn pattern(self) -> &'static [(i32, i32)] {
+        match self {
+            SampleCount::X1 => &PATTERN_X1,
+            SampleCount::X2 => &PATTERN_X2,
+            SampleCount::X4 => &PATTERN_X4,
+            SampleCount::X8 => &PATTERN_X8,
+        }
+    }
+
+    // Sample offsets in pixels
+    pub fn offsets(self) -> impl Iterator<Item = (f32, f32)> {
+        self.pattern()
+            .iter()
+            .map(|&(x, y)| (x as f32 / 16., y as f32 / 16.))
+    }
+}
+
+// Averages each channel of the samples belonging to a pixel
+pub fn resolve_samples(samples: &[u32]) -> u32 {
+    let (mut r, mut g, mut b) = (0, 0, 0);
+    for s in samples {
+        r += (s >> 16) & 0xff;
+        g += (s >> 8) & 0xff;
+        b += s & 0xff;
+    }
+    let n = samples.len() as u32;
+    // round to nearest rather than truncating
+    let avg = |c: u32| (c + n / 2) / n;
+    avg(r) << 16 | avg(g) << 8 | avg(b)
+}
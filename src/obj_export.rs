@@ -0,0 +1,158 @@
+use crate::save_png;
+use crate::Color;
+use crate::Image;
+use crate::ObjData;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+// Writes the model to obj_path along with a .mtl named after it and a .png for each texture, all
+// next to each other so ObjData::new() can read them back.
+//
+// Positions, texture coordinates and normals shared between triangles are only written once. Each
+// submesh becomes a group, with a material for each color and texture it's drawn with. Vertex
+// colors and texture samplers have nowhere to go in .obj files and are left out.
+pub fn export_obj(obj: &ObjData, obj_path: &str) -> std::io::Result<()> {
+    let obj_path = Path::new(obj_path);
+    let dir = obj_path.parent().unwrap_or(Path::new(""));
+    // names the .mtl and .pngs, without spaces so other readers don't take them for separators
+    let stem = obj_path.file_stem().map_or(String::from("model"), |stem| {
+        stem.to_string_lossy().replace(char::is_whitespace, "_")
+    });
+
+    let mut positions = Indexer::default();
+    let mut tex_coords = Indexer::default();
+    let mut normals = Indexer::default();
+    // (color, index into textures) of each material, in the order they're first used
+    let mut materials: Vec<(Color, Option<usize>)> = Vec::new();
+    let mut textures: Vec<Image> = Vec::new();
+    // (group name, material, the v, vt and vn indices of each corner) of each triangle
+    let mut faces = Vec::with_capacity(obj.len());
+    for submesh in &obj.submeshes {
+        for i in submesh.triangles.clone() {
+            let texture = obj
+                .tri_textures
+                .as_ref()
+                .map(|tri_textures| &tri_textures[i]);
+            let texture = texture.map(|texture| {
+                match textures
+                    .iter()
+                    .position(|(pixels, ..)| Arc::ptr_eq(pixels, &texture.texture))
+                {
+                    Some(index) => index,
+                    None => {
+                        textures.push((texture.texture.clone(), texture.width, texture.height));
+                        textures.len() - 1
+                    }
+                }
+            });
+            let material = (submesh.color, texture);
+            let material = match materials.iter().position(|&(color, texture)| {
+                color.rgb() == material.0.rgb() && texture == material.1
+            }) {
+                Some(index) => index,
+                None => {
+                    materials.push(material);
+                    materials.len() - 1
+                }
+            };
+            let corners: [[Option<usize>; 3]; 3] = std::array::from_fn(|k| {
+                let p = obj.tri_positions[i][k];
+                [
+                    Some(positions.index([p.x, p.y, p.z])),
+                    obj.tri_texcoords.as_ref().map(|tri_texcoords| {
+                        let t = tri_texcoords[i][k];
+                        tex_coords.index([t.x, t.y, t.z])
+                    }),
+                    obj.tri_normals.as_ref().map(|tri_normals| {
+                        let n = tri_normals[i][k];
+                        normals.index([n.x, n.y, n.z])
+                    }),
+                ]
+            });
+            faces.push((&submesh.name, material, corners));
+        }
+    }
+
+    let texture_file = |i: usize| format!("{}_{}.png", stem, i);
+    for (i, texture) in textures.iter().enumerate() {
+        save_png(&dir.join(texture_file(i)), texture)?;
+    }
+
+    let mtl_file = format!("{}.mtl", stem);
+    let mut mtl = BufWriter::new(File::create(dir.join(&mtl_file))?);
+    for (i, (color, texture)) in materials.iter().enumerate() {
+        let [r, g, b] = color.rgb();
+        writeln!(mtl, "newmtl material{}", i)?;
+        writeln!(mtl, "Kd {} {} {}", r, g, b)?;
+        if let Some(texture) = texture {
+            writeln!(mtl, "map_Kd {}", texture_file(*texture))?;
+        }
+    }
+    mtl.flush()?;
+
+    let mut out = BufWriter::new(File::create(obj_path)?);
+    writeln!(out, "mtllib {}", mtl_file)?;
+    for [x, y, z] in &positions.values {
+        writeln!(out, "v {} {} {}", x, y, z)?;
+    }
+    for [u, v, w] in &tex_coords.values {
+        if *w == 0. {
+            writeln!(out, "vt {} {}", u, v)?;
+        } else {
+            writeln!(out, "vt {} {} {}", u, v, w)?;
+        }
+    }
+    for [x, y, z] in &normals.values {
+        writeln!(out, "vn {} {} {}", x, y, z)?;
+    }
+    let mut group = None;
+    let mut material = None;
+    for (name, face_material, corners) in faces {
+        if group != Some(name) {
+            // other readers would split a name with spaces into several groups
+            writeln!(out, "g {}", name.replace(char::is_whitespace, "_"))?;
+            group = Some(name);
+            // the reader starts each group without a material
+            material = None;
+        }
+        if material != Some(face_material) {
+            writeln!(out, "usemtl material{}", face_material)?;
+            material = Some(face_material);
+        }
+        write!(out, "f")?;
+        for corner in corners {
+            // indices start at 1
+            match corner.map(|index| index.map(|index| index + 1)) {
+                [Some(v), Some(vt), Some(vn)] => write!(out, " {}/{}/{}", v, vt, vn)?,
+                [Some(v), Some(vt), None] => write!(out, " {}/{}", v, vt)?,
+                [Some(v), None, Some(vn)] => write!(out, " {}//{}", v, vn)?,
+                [v, ..] => write!(out, " {}", v.unwrap())?,
+            }
+        }
+        writeln!(out)?;
+    }
+    out.flush()
+}
+
+// Hands out an index for each distinct value, keyed by their bits
+#[derive(Default)]
+struct Indexer {
+    values: Vec<[f32; 3]>,
+    indices: HashMap<[u32; 3], usize>,
+}
+
+impl Indexer {
+    fn index(&mut self, value: [f32; 3]) -> usize {
+        *self
+            .indices
+            .entry(value.map(f32::to_bits))
+            .or_insert_with(|| {
+                self.values.push(value);
+                self.values.len() - 1
+            })
+    }
+}
@@ -1,4 +1,12 @@
+use crate::load_png;
+use crate::Aabb;
+use crate::BoundingSphere;
+use crate::Bvh;
+use crate::Color;
+use crate::LoadError;
 use crate::MtlData;
+use crate::Sampler;
+use crate::TextureFilter;
 use cgmath::point3;
 use cgmath::vec3;
 use cgmath::InnerSpace;
@@ -6,28 +14,30 @@ use cgmath::Point3;
 use cgmath::Vector3;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::ops::Range;
 use std::path::Path;
-use std::rc::Rc;
+use std::sync::Arc;
 
 // Holds current texture data when parsing obj files
 // This is a 0: pointer to texture, 1: width, 2: height
 #[derive(Clone)]
-struct CurrentTextureData(Rc<Vec<u8>>, usize, usize);
+struct CurrentTextureData(Arc<Vec<u8>>, usize, usize);
 
 // TODO: restructure everything to use flattened arrays
 // Holds all the data needed to interpolate inside a texture,
 #[derive(Clone)]
 pub struct TextureData {
-    pub texture: Rc<Vec<u8>>,
+    pub texture: Arc<Vec<u8>>,
     pub width: usize,
     pub height: usize,
     pub points: [Point3<f32>; 3],
+    pub sampler: Sampler,
 }
 
 //TODO: figure out how like, specular textures and stuff work
 impl TextureData {
-    fn new(
-        texture: Rc<Vec<u8>>,
+    pub fn new(
+        texture: Arc<Vec<u8>>,
         width: usize,
         height: usize,
         points: [Point3<f32>; 3],
@@ -37,6 +47,63 @@ impl TextureData {
             width,
             height,
             points,
+            sampler: Sampler::default(),
+        }
+    }
+
+    // Color at (x, y) in texels (texture coordinates scaled up by the size of the texture)
+    #[inline(always)]
+    pub fn sample(&self, x: f32, y: f32) -> Color {
+        match self.sampler.filter {
+            TextureFilter::Nearest => self.texel(x.floor() as i32, y.floor() as i32),
+            TextureFilter::Linear => {
+                // texel centres are at .5
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (x0, y0) = (x.floor(), y.floor());
+                let (tx, ty) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i32, y0 as i32);
+                let bottom = self.texel(x0, y0).mix(&self.texel(x0 + 1, y0), tx);
+                let top = self.texel(x0, y0 + 1).mix(&self.texel(x0 + 1, y0 + 1), tx);
+                bottom.mix(&top, ty)
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn texel(&self, x: i32, y: i32) -> Color {
+        let x = self.sampler.wrap_s.apply(x, self.width);
+        let y = self.sampler.wrap_t.apply(y, self.height);
+        let idx = (x + y * self.width) * 4;
+        Color::new_from_rgb(
+            self.texture[idx],
+            self.texture[idx + 1],
+            self.texture[idx + 2],
+        )
+    }
+}
+
+// A run of triangles from the same o/g group using the same material, with bounds for culling
+#[derive(Clone)]
+pub struct Submesh {
+    pub name: String,
+    pub triangles: Range<usize>,
+    pub bounds: Aabb,
+    pub bounding_sphere: BoundingSphere,
+    pub bvh: Bvh,
+    // the material's color, multiplies the lit or textured color
+    pub color: Color,
+}
+
+impl Submesh {
+    pub fn new(name: &str, triangles: Range<usize>, tri_positions: &[[Point3<f32>; 3]]) -> Submesh {
+        let positions = tri_positions[triangles.clone()].iter().flatten();
+        Submesh {
+            name: name.to_string(),
+            bounds: Aabb::from_points(positions.clone()),
+            bounding_sphere: BoundingSphere::from_points(positions),
+            bvh: Bvh::new(tri_positions, triangles.clone()),
+            triangles,
+            color: Color::new(1., 1., 1.),
         }
     }
 }
@@ -47,17 +114,36 @@ pub struct ObjData {
     // Triplet of vertices, Triplet of normals, Texture coords
     pub tri_positions: Vec<[Point3<f32>; 3]>,
     pub tri_textures: Option<Vec<TextureData>>,
+    // kept even when there's no texture to go with them, so one can be put on the model later
+    pub tri_texcoords: Option<Vec<[Point3<f32>; 3]>>,
     pub tri_normals: Option<Vec<[Vector3<f32>; 3]>>,
+    // per vertex colors, which .obj files don't have but e.g. .ply files can
+    pub tri_colors: Option<Vec<[Color; 3]>>,
     pub mtl: Option<MtlData>,
-    textures: Vec<Rc<Vec<u8>>>,
+    pub textures: Vec<Arc<Vec<u8>>>,
+    // cover tri_positions between them, in order
+    pub submeshes: Vec<Submesh>,
+    // of the whole model
+    pub bounds: Aabb,
+    pub bounding_sphere: BoundingSphere,
 }
 
 impl ObjData {
     pub fn len(&self) -> usize {
-        return self.tri_positions.len();
+        self.tri_positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tri_positions.is_empty()
     }
 
+    // Panics if the file can't be loaded, see ObjData::load()
     pub fn new(obj_path: &str) -> ObjData {
+        ObjData::load(obj_path).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn load(obj_path: &str) -> Result<ObjData, LoadError> {
+        let path = Path::new(obj_path);
         // Temp buffers to be indexed into
         let mut temp_vertex_buffer: Vec<f32> = Vec::new();
         let mut temp_vertex_texture_buffer: Vec<f32> = Vec::new();
@@ -67,273 +153,292 @@ impl ObjData {
         let mut tri_positions: Vec<[Point3<f32>; 3]> = Vec::new();
         let mut tri_normals: Option<Vec<[Vector3<f32>; 3]>> = Some(Vec::new());
 
-        // let mut tri_textures: Option<Vec<TextureInfo>> = Some(Vec::new());
         let mut tri_textures: Option<Vec<TextureData>> = Some(Vec::new());
+        let mut tri_texcoords: Option<Vec<[Point3<f32>; 3]>> = Some(Vec::new());
         let mut current_texture_info: Option<CurrentTextureData> = None;
-        let mut textures: Vec<Rc<Vec<u8>>> = Vec::new();
+        let mut textures: Vec<Arc<Vec<u8>>> = Vec::new();
         let mut mtl: Option<MtlData> = None;
+        // (name, triangles, Kd) of each submesh, the current one doesn't get added until it ends
+        let mut submesh_ranges: Vec<(String, Range<usize>, Color)> = Vec::new();
+        // faces before the first o/g line belong to the default group
+        let mut submesh_name = String::from("default");
+        let mut submesh_color = Color::new(1., 1., 1.);
 
-        let file = File::open(obj_path).unwrap();
+        let io_error = |error| LoadError::Io {
+            path: path.to_path_buf(),
+            error,
+        };
+        let file = File::open(path).map_err(io_error)?;
         let reader = BufReader::new(file);
-        let obj_dir = Path::new(obj_path).parent().unwrap().to_str().unwrap();
+        let obj_dir = path.parent().unwrap_or(Path::new(""));
 
-        for line in reader.lines() {
-            let line = line.unwrap();
-            if line == "" {
-                continue;
-            }
+        for (i, line) in reader.lines().enumerate() {
+            let line = line.map_err(io_error)?;
+            let error =
+                |message: &str| LoadError::invalid(path, format!("line {}: {}", i + 1, message));
             let elements = line.split_whitespace().collect::<Vec<&str>>();
-            let id = elements[0];
-            {
-                match id {
-                    "mtllib" => {
-                        //TODO
-                        let mtl_name = elements[1];
-                        let mtl_path = Path::new(obj_dir).join(mtl_name);
-                        println!("Loaded .mtl file: {:?}", mtl_path);
-                        mtl = Some(MtlData::new(mtl_path.to_str().unwrap()));
+            let Some(&id) = elements.first() else {
+                continue;
+            };
+            let float = |element: &str| {
+                element
+                    .parse::<f32>()
+                    .map_err(|_| error(&format!("\"{}\" isn't a number", element)))
+            };
+            match id {
+                "mtllib" => {
+                    // the rest of the line, file names can have spaces in them
+                    let mtl_name = line.trim_start()[id.len()..].trim();
+                    if mtl_name.is_empty() {
+                        return Err(error("mtllib needs a file"));
                     }
-                    "usemtl" => {
-                        //TODO: change this back
-                        let texture_name = &mtl.as_ref().unwrap().texture_path_map[elements[1]];
-                        let texture_path = Path::new(obj_dir).join(texture_name);
-                        let file_type = Path::new(&texture_path)
-                            .extension()
-                            .unwrap()
-                            .to_str()
-                            .unwrap();
-
-                        println!("Loaded texture: {:?} {}", texture_path, file_type);
-
-                        // load png data into vector
-                        match file_type {
-                            "png" => {
-                                let decoder = png::Decoder::new(File::open(texture_path).unwrap());
-                                let mut reader = decoder.read_info().unwrap();
-                                // Allocate the output buffer.
-                                let mut buf = vec![0; reader.output_buffer_size()];
-                                // Read the next frame. An APNG might contain multiple frames.
-                                let png_info = reader.next_frame(&mut buf).unwrap();
-
-                                assert!(
-                                    png_info.bit_depth == png::BitDepth::Eight,
-                                    "PNG bit depth not 8!"
-                                );
-                                assert!(
-                                    png_info.color_type == png::ColorType::Rgba,
-                                    "PNG color type not rgba!"
-                                );
-
-                                let mut bytes = vec![0; png_info.buffer_size()];
-                                // this makes origin bottom left instead of top left
-                                for y in 0..png_info.height as usize {
-                                    for x in 0..png_info.width as usize {
-                                        let idx_transform = x * 4 + y * png_info.width as usize * 4;
-                                        let idx_original = x * 4
-                                            + (png_info.height as usize - y - 1)
-                                                * png_info.width as usize
-                                                * 4;
-                                        for i in 0..3 {
-                                            bytes[idx_transform + i] = buf[idx_original + i];
-                                        }
-                                    }
-                                }
+                    let mtl_path = obj_dir.join(mtl_name);
+                    mtl = Some(MtlData::load(&mtl_path)?);
+                    println!("Loaded .mtl file: {:?}", mtl_path);
+                }
+                "o" | "g" => {
+                    end_submesh(
+                        &mut submesh_ranges,
+                        &submesh_name,
+                        submesh_color,
+                        tri_positions.len(),
+                    );
+                    submesh_name = elements[1..].join(" ");
+                }
+                "usemtl" => {
+                    end_submesh(
+                        &mut submesh_ranges,
+                        &submesh_name,
+                        submesh_color,
+                        tri_positions.len(),
+                    );
+                    let name = elements
+                        .get(1)
+                        .ok_or_else(|| error("usemtl needs a material"))?;
+                    let Some(mtl) = mtl.as_ref() else {
+                        println!(
+                            "{}: line {}: usemtl before any mtllib, using the default material",
+                            path.display(),
+                            i + 1
+                        );
+                        submesh_color = Color::new(1., 1., 1.);
+                        current_texture_info = None;
+                        continue;
+                    };
+                    submesh_color = mtl
+                        .colors
+                        .get(*name)
+                        .copied()
+                        .unwrap_or(Color::new(1., 1., 1.));
+                    // materials without a map_Kd are just colored
+                    let Some(texture_name) = mtl.texture_path_map.get(*name) else {
+                        current_texture_info = None;
+                        continue;
+                    };
+                    let texture_path = obj_dir.join(texture_name);
+                    let file_type = texture_path
+                        .extension()
+                        .and_then(|extension| extension.to_str())
+                        .unwrap_or("");
 
-                                let texture = Rc::new(bytes);
-                                current_texture_info = Some(CurrentTextureData(
-                                    Rc::clone(&texture),
-                                    png_info.width as usize,
-                                    png_info.height as usize,
-                                ));
+                    println!("Loaded texture: {:?} {}", texture_path, file_type);
 
-                                println!("png metadata: {:?}", png_info);
-                                textures.push(texture);
-                            }
-                            _ => panic!("unhandled texture file type!"),
+                    // load png data into vector
+                    match file_type {
+                        "png" => {
+                            let (texture, width, height) =
+                                load_png(&texture_path).map_err(|e| LoadError::invalid(path, e))?;
+                            current_texture_info =
+                                Some(CurrentTextureData(Arc::clone(&texture), width, height));
+                            textures.push(texture);
                         }
-                    }
-                    "v" | "vt" | "vn" => match line.chars().nth(1).unwrap() {
-                        ' ' => {
-                            temp_vertex_buffer.push(elements[1].parse::<f32>().unwrap());
-                            temp_vertex_buffer.push(elements[2].parse::<f32>().unwrap());
-                            temp_vertex_buffer.push(elements[3].parse::<f32>().unwrap());
+                        _ => {
+                            return Err(error(&format!(
+                                "{} isn't a png, the only kind of texture supported",
+                                texture_name
+                            )))
                         }
-                        't' => {
-                            //(u, [v, w]) coordinates, these will vary between 0 and 1.
-                            // v, w are optional and default to 0.
-                            match elements.len() {
-                                2 => {
-                                    temp_vertex_texture_buffer
-                                        .push(elements[1].parse::<f32>().unwrap());
-                                    temp_vertex_texture_buffer.push(0.);
-                                    temp_vertex_texture_buffer.push(0.);
-                                }
-                                3 => {
-                                    temp_vertex_texture_buffer
-                                        .push(elements[1].parse::<f32>().unwrap());
-                                    temp_vertex_texture_buffer
-                                        .push(elements[2].parse::<f32>().unwrap());
-                                    temp_vertex_texture_buffer.push(0.);
-                                }
-                                4 => {
-                                    temp_vertex_texture_buffer
-                                        .push(elements[1].parse::<f32>().unwrap());
-                                    temp_vertex_texture_buffer
-                                        .push(elements[2].parse::<f32>().unwrap());
-                                    temp_vertex_texture_buffer
-                                        .push(elements[3].parse::<f32>().unwrap());
-                                }
-                                _ => panic!("Error parsing vertex textures: {}", line),
-                            }
+                    }
+                }
+                "v" | "vn" => {
+                    let [x, y, z] = match elements.get(1..4) {
+                        Some(&[x, y, z]) => [float(x)?, float(y)?, float(z)?],
+                        _ => return Err(error(&format!("{} needs 3 coordinates", id))),
+                    };
+                    let buffer = match id {
+                        "v" => &mut temp_vertex_buffer,
+                        _ => &mut temp_vertex_normal_buffer,
+                    };
+                    buffer.extend([x, y, z]);
+                }
+                "vt" => {
+                    //(u, [v, w]) coordinates, these will vary between 0 and 1.
+                    // v, w are optional and default to 0.
+                    if !(2..=4).contains(&elements.len()) {
+                        return Err(error("vt needs 1 to 3 coordinates"));
+                    }
+                    let mut uvw = [0.; 3];
+                    for (c, element) in uvw.iter_mut().zip(&elements[1..]) {
+                        *c = float(element)?;
+                    }
+                    temp_vertex_texture_buffer.extend(uvw);
+                }
+                "f" => {
+                    if elements.len() < 4 {
+                        return Err(error("faces need at least 3 vertices"));
+                    }
+                    // v, v/vt, v/vt/vn or v//vn, indices start at 1
+                    let index = |index: &str| {
+                        index
+                            .parse::<usize>()
+                            .ok()
+                            .filter(|&index| index > 0)
+                            .map(|index| index - 1)
+                            .ok_or_else(|| error(&format!("bad index \"{}\"", index)))
+                    };
+                    let mut corners = Vec::with_capacity(elements.len() - 1);
+                    for element in &elements[1..] {
+                        let group = element.split('/').collect::<Vec<&str>>();
+                        if group.len() > 3 {
+                            return Err(error(&format!("unhandled format of faces: {}", line)));
                         }
-                        'n' => {
-                            temp_vertex_normal_buffer.push(elements[1].parse::<f32>().unwrap());
-                            temp_vertex_normal_buffer.push(elements[2].parse::<f32>().unwrap());
-                            temp_vertex_normal_buffer.push(elements[3].parse::<f32>().unwrap());
+                        let optional_index = |k: usize| match group.get(k) {
+                            Some(&i) if !i.is_empty() => index(i).map(Some),
+                            _ => Ok(None),
+                        };
+                        corners.push((index(group[0])?, optional_index(1)?, optional_index(2)?));
+                    }
+                    let lookup = |buffer: &[f32], i: usize, what: &str| {
+                        buffer
+                            .get(i * 3..i * 3 + 3)
+                            .map(|c| [c[0], c[1], c[2]])
+                            .ok_or_else(|| {
+                                error(&format!("{} index {} out of bounds", what, i + 1))
+                            })
+                    };
+
+                    // polygons are fanned, which works for convex ones
+                    for k in 1..corners.len() - 1 {
+                        let triangle = [corners[0], corners[k], corners[k + 1]];
+                        let mut tri_position = [point3(0., 0., 0.); 3];
+                        for (point, &(v, ..)) in tri_position.iter_mut().zip(&triangle) {
+                            let [x, y, z] = lookup(&temp_vertex_buffer, v, "vertex")?;
+                            *point = point3(x, y, z);
                         }
-                        _ => println!("Unhandled obj expression: {}", line),
-                    },
-                    "f" => {
-                        let slash_frequency: usize = elements
-                            .iter()
-                            .map(|x| x.chars().filter(|y| *y == '/').count())
-                            .sum();
-                        let v0: usize;
-                        let v1: usize;
-                        let v2: usize;
-                        let mut vt0: Option<usize> = None;
-                        let mut vt1: Option<usize> = None;
-                        let mut vt2: Option<usize> = None;
-                        let mut vn0: Option<usize> = None;
-                        let mut vn1: Option<usize> = None;
-                        let mut vn2: Option<usize> = None;
-                        match slash_frequency {
-                            0 => {
-                                v0 = elements[1].parse::<usize>().unwrap() - 1;
-                                v1 = elements[2].parse::<usize>().unwrap() - 1;
-                                v2 = elements[3].parse::<usize>().unwrap() - 1;
+                        tri_positions.push(tri_position);
+
+                        let vt = triangle.map(|(_, vt, _)| vt);
+                        if let [Some(vt0), Some(vt1), Some(vt2)] = vt {
+                            let mut points = [point3(0., 0., 0.); 3];
+                            for (point, &vt) in points.iter_mut().zip(&[vt0, vt1, vt2]) {
+                                let [u, v, w] =
+                                    lookup(&temp_vertex_texture_buffer, vt, "texture coordinate")?;
+                                *point = point3(u, v, w);
                             }
-                            3 => {
-                                let group0 = elements[1].split('/').collect::<Vec<&str>>();
-                                let group1 = elements[2].split('/').collect::<Vec<&str>>();
-                                let group2 = elements[3].split('/').collect::<Vec<&str>>();
-                                v0 = group0[0].parse::<usize>().unwrap() - 1;
-                                v1 = group1[0].parse::<usize>().unwrap() - 1;
-                                v2 = group2[0].parse::<usize>().unwrap() - 1;
-                                vt0 = Some(group0[1].parse::<usize>().unwrap() - 1);
-                                vt1 = Some(group1[1].parse::<usize>().unwrap() - 1);
-                                vt2 = Some(group2[1].parse::<usize>().unwrap() - 1);
+                            if let Some(tri_texcoords) = tri_texcoords.as_mut() {
+                                tri_texcoords.push(points);
                             }
-                            6 => {
-                                // FIXME: breaks with v//vn format
-                                let group0 = elements[1].split('/').collect::<Vec<&str>>();
-                                let group1 = elements[2].split('/').collect::<Vec<&str>>();
-                                let group2 = elements[3].split('/').collect::<Vec<&str>>();
-                                v0 = group0[0].parse::<usize>().unwrap() - 1;
-                                v1 = group1[0].parse::<usize>().unwrap() - 1;
-                                v2 = group2[0].parse::<usize>().unwrap() - 1;
-                                vt0 = Some(group0[1].parse::<usize>().unwrap() - 1);
-                                vt1 = Some(group1[1].parse::<usize>().unwrap() - 1);
-                                vt2 = Some(group2[1].parse::<usize>().unwrap() - 1);
-                                vn0 = Some(group0[2].parse::<usize>().unwrap() - 1);
-                                vn1 = Some(group1[2].parse::<usize>().unwrap() - 1);
-                                vn2 = Some(group2[2].parse::<usize>().unwrap() - 1);
+                            match (&current_texture_info, tri_textures.as_mut()) {
+                                (Some(texture_info), Some(tri_textures)) => {
+                                    tri_textures.push(TextureData::new(
+                                        Arc::clone(&texture_info.0),
+                                        texture_info.1,
+                                        texture_info.2,
+                                        points,
+                                    ))
+                                }
+                                _ => tri_textures = None,
                             }
-                            _ => panic!("Unhandled format of faces: {}", line),
-                        }
-
-                        let tri_position: [Point3<f32>; 3] = [
-                            point3(
-                                temp_vertex_buffer[v0 * 3],
-                                temp_vertex_buffer[v0 * 3 + 1],
-                                temp_vertex_buffer[v0 * 3 + 2],
-                            ),
-                            point3(
-                                temp_vertex_buffer[v1 * 3],
-                                temp_vertex_buffer[v1 * 3 + 1],
-                                temp_vertex_buffer[v1 * 3 + 2],
-                            ),
-                            point3(
-                                temp_vertex_buffer[v2 * 3],
-                                temp_vertex_buffer[v2 * 3 + 1],
-                                temp_vertex_buffer[v2 * 3 + 2],
-                            ),
-                        ];
-                        tri_positions.push(tri_position);
-
-                        if let (Some(vt0), Some(vt1), Some(vt2)) = (vt0, vt1, vt2) {
-                            let points = [
-                                point3(
-                                    temp_vertex_texture_buffer[vt0 * 3],
-                                    temp_vertex_texture_buffer[vt0 * 3 + 1],
-                                    temp_vertex_texture_buffer[vt0 * 3 + 2],
-                                ),
-                                point3(
-                                    temp_vertex_texture_buffer[vt1 * 3],
-                                    temp_vertex_texture_buffer[vt1 * 3 + 1],
-                                    temp_vertex_texture_buffer[vt1 * 3 + 2],
-                                ),
-                                point3(
-                                    temp_vertex_texture_buffer[vt2 * 3],
-                                    temp_vertex_texture_buffer[vt2 * 3 + 1],
-                                    temp_vertex_texture_buffer[vt2 * 3 + 2],
-                                ),
-                            ];
-                            let tri_texture_data = TextureData::new(
-                                Rc::clone(&current_texture_info.as_ref().unwrap().0),
-                                current_texture_info.as_ref().unwrap().1,
-                                current_texture_info.as_ref().unwrap().2,
-                                points,
-                            );
-                            tri_textures.as_mut().unwrap().push(tri_texture_data);
                         } else {
                             tri_textures = None;
+                            tri_texcoords = None;
                         }
 
-                        if let (Some(vn0), Some(vn1), Some(vn2)) = (vn0, vn1, vn2) {
-                            let tri_normal = [
-                                vec3(
-                                    temp_vertex_normal_buffer[vn0 * 3],
-                                    temp_vertex_normal_buffer[vn0 * 3 + 1],
-                                    temp_vertex_normal_buffer[vn0 * 3 + 2],
-                                )
-                                .normalize(),
-                                vec3(
-                                    temp_vertex_normal_buffer[vn1 * 3],
-                                    temp_vertex_normal_buffer[vn1 * 3 + 1],
-                                    temp_vertex_normal_buffer[vn1 * 3 + 2],
-                                )
-                                .normalize(),
-                                vec3(
-                                    temp_vertex_normal_buffer[vn2 * 3],
-                                    temp_vertex_normal_buffer[vn2 * 3 + 1],
-                                    temp_vertex_normal_buffer[vn2 * 3 + 2],
-                                )
-                                .normalize(),
-                            ];
-                            tri_normals.as_mut().unwrap().push(tri_normal);
+                        let vn = triangle.map(|(.., vn)| vn);
+                        if let [Some(vn0), Some(vn1), Some(vn2)] = vn {
+                            let mut tri_normal = [vec3(0., 0., 0.); 3];
+                            for (normal, &vn) in tri_normal.iter_mut().zip(&[vn0, vn1, vn2]) {
+                                let [x, y, z] = lookup(&temp_vertex_normal_buffer, vn, "normal")?;
+                                *normal = vec3(x, y, z).normalize();
+                            }
+                            if let Some(tri_normals) = tri_normals.as_mut() {
+                                tri_normals.push(tri_normal);
+                            }
                         } else {
                             tri_normals = None;
                         }
                     }
-                    "#" => println!(".obj file comment: {}", line),
-                    _ => println!("Unhandled .obj expression: {}", line), // should panic!() instead
                 }
+                "#" => println!(".obj file comment: {}", line),
+                _ => println!("Unhandled .obj expression: {}", line),
             }
         }
 
-        /*
-        assert!(
-            tri_positions.len() == tri_textures.as_ref().unwrap().len(),
-            "REMOVE ME"
+        end_submesh(
+            &mut submesh_ranges,
+            &submesh_name,
+            submesh_color,
+            tri_positions.len(),
         );
-        */
+
+        let submeshes: Vec<Submesh> = submesh_ranges
+            .into_iter()
+            .map(|(name, triangles, color)| Submesh {
+                color,
+                ..Submesh::new(&name, triangles, &tri_positions)
+            })
+            .collect();
+        Ok(ObjData {
+            mtl,
+            tri_texcoords,
+            ..ObjData::from_triangles(
+                tri_positions,
+                tri_textures,
+                tri_normals,
+                textures,
+                submeshes,
+            )
+        })
+    }
+
+    // For models loaded from other formats, works out the bounds of the whole model and takes the
+    // texture coordinates from tri_textures. submeshes have to cover tri_positions between them,
+    // in order.
+    pub fn from_triangles(
+        tri_positions: Vec<[Point3<f32>; 3]>,
+        tri_textures: Option<Vec<TextureData>>,
+        tri_normals: Option<Vec<[Vector3<f32>; 3]>>,
+        textures: Vec<Arc<Vec<u8>>>,
+        submeshes: Vec<Submesh>,
+    ) -> ObjData {
+        let positions = tri_positions.iter().flatten();
+        let tri_texcoords = tri_textures
+            .as_ref()
+            .map(|tri_textures| tri_textures.iter().map(|texture| texture.points).collect());
         ObjData {
+            bounds: Aabb::from_points(positions.clone()),
+            bounding_sphere: BoundingSphere::from_points(positions),
+            submeshes,
             tri_positions,
             tri_textures,
+            tri_texcoords,
             tri_normals,
+            tri_colors: None,
             textures,
-            mtl,
+            mtl: None,
         }
     }
 }
+
+// Adds the triangles since the end of the last submesh as a new one, if there are any
+fn end_submesh(
+    submesh_ranges: &mut Vec<(String, Range<usize>, Color)>,
+    name: &str,
+    color: Color,
+    triangles: usize,
+) {
+    let start = submesh_ranges.last().map_or(0, |(_, range, _)| range.end);
+    if triangles > start {
+        submesh_ranges.push((name.to_string(), start..triangles, color));
+    }
+}
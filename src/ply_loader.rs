@@ -0,0 +1,386 @@
+use crate::Color;
+use crate::LoadError;
+use crate::ObjData;
+use crate::Submesh;
+use cgmath::point3;
+use cgmath::vec3;
+use cgmath::InnerSpace;
+use cgmath::Point3;
+use cgmath::Vector3;
+use std::path::Path;
+
+// Loads an ASCII or binary (either endianness) .ply file's faces, with the vertices' normals
+// (nx, ny, nz) and colors (red, green, blue) if it has them. Faces with more than 3 vertices get
+// split into triangles, other elements are skipped over.
+pub fn load_ply(path: &str) -> Result<ObjData, LoadError> {
+    let path = Path::new(path);
+    let bytes = std::fs::read(path).map_err(|error| LoadError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    let mesh = parse_ply(&bytes).map_err(|e| LoadError::invalid(path, e))?;
+
+    let tri_positions: Vec<_> = mesh
+        .triangles
+        .iter()
+        .map(|t| t.map(|i| mesh.positions[i]))
+        .collect();
+    let tri_normals = mesh.normals.map(|normals| {
+        mesh.triangles
+            .iter()
+            .map(|t| t.map(|i| normals[i]))
+            .collect()
+    });
+    let tri_colors = mesh.colors.map(|colors| {
+        mesh.triangles
+            .iter()
+            .map(|t| t.map(|i| colors[i]))
+            .collect()
+    });
+    let submeshes = vec![Submesh::new(
+        "default",
+        0..tri_positions.len(),
+        &tri_positions,
+    )];
+    Ok(ObjData {
+        tri_colors,
+        ..ObjData::from_triangles(tri_positions, None, tri_normals, Vec::new(), submeshes)
+    })
+}
+
+struct PlyMesh {
+    positions: Vec<Point3<f32>>,
+    normals: Option<Vec<Vector3<f32>>>,
+    colors: Option<Vec<Color>>,
+    triangles: Vec<[usize; 3]>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ScalarType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> Result<ScalarType, String> {
+        Ok(match name {
+            "char" | "int8" => ScalarType::I8,
+            "uchar" | "uint8" => ScalarType::U8,
+            "short" | "int16" => ScalarType::I16,
+            "ushort" | "uint16" => ScalarType::U16,
+            "int" | "int32" => ScalarType::I32,
+            "uint" | "uint32" => ScalarType::U32,
+            "float" | "float32" => ScalarType::F32,
+            "double" | "float64" => ScalarType::F64,
+            _ => return Err(format!("unknown property type \"{}\"", name)),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            ScalarType::I8 | ScalarType::U8 => 1,
+            ScalarType::I16 | ScalarType::U16 => 2,
+            ScalarType::I32 | ScalarType::U32 | ScalarType::F32 => 4,
+            ScalarType::F64 => 8,
+        }
+    }
+
+    // What a color channel of this type is divided by to get it between 0 and 1
+    fn color_scale(self) -> f64 {
+        match self {
+            ScalarType::I8 => i8::MAX as f64,
+            ScalarType::U8 => u8::MAX as f64,
+            ScalarType::I16 => i16::MAX as f64,
+            ScalarType::U16 => u16::MAX as f64,
+            ScalarType::I32 => i32::MAX as f64,
+            ScalarType::U32 => u32::MAX as f64,
+            ScalarType::F32 | ScalarType::F64 => 1.,
+        }
+    }
+}
+
+enum Property {
+    Scalar(ScalarType),
+    // number of items, then the items
+    List { count: ScalarType, item: ScalarType },
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<(String, Property)>,
+}
+
+impl Element {
+    fn property(&self, name: &str) -> Option<usize> {
+        self.properties.iter().position(|(n, _)| n == name)
+    }
+}
+
+fn parse_ply(bytes: &[u8]) -> Result<PlyMesh, String> {
+    let header_end = bytes
+        .windows(11)
+        .position(|w| w == b"end_header\n" || w == b"end_header\r")
+        .ok_or("there's no end_header")?;
+    let header = std::str::from_utf8(&bytes[..header_end])
+        .map_err(|_| "the header isn't text".to_string())?;
+    // the body starts after the end_header line
+    let mut body = header_end + 10;
+    body += bytes[body..].iter().take_while(|&&b| b == b'\r').count();
+    body += bytes[body..]
+        .iter()
+        .take_while(|&&b| b == b'\n')
+        .count()
+        .min(1);
+
+    let mut lines = header.lines().enumerate();
+    if lines.next().map(|(_, line)| line.trim()) != Some("ply") {
+        return Err("not a ply file".to_string());
+    }
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for (i, line) in lines {
+        let error = |message: String| format!("header line {}: {}", i + 1, message);
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words[..] {
+            ["format", name, _version] => {
+                format = Some(match name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => return Err(error(format!("unknown format \"{}\"", name))),
+                })
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| error(format!("bad element count \"{}\"", count)))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, item, name] => {
+                let property = Property::List {
+                    count: ScalarType::parse(count).map_err(error)?,
+                    item: ScalarType::parse(item).map_err(error)?,
+                };
+                elements
+                    .last_mut()
+                    .ok_or_else(|| error("property before any element".to_string()))?
+                    .properties
+                    .push((name.to_string(), property));
+            }
+            ["property", kind, name] => {
+                let property = Property::Scalar(ScalarType::parse(kind).map_err(error)?);
+                elements
+                    .last_mut()
+                    .ok_or_else(|| error("property before any element".to_string()))?
+                    .properties
+                    .push((name.to_string(), property));
+            }
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => return Err(error(format!("can't understand \"{}\"", line))),
+        }
+    }
+    let format = format.ok_or("there's no format line")?;
+
+    let mut reader = Reader {
+        format,
+        bytes,
+        pos: body,
+    };
+    let mut mesh = PlyMesh {
+        positions: Vec::new(),
+        normals: None,
+        colors: None,
+        triangles: Vec::new(),
+    };
+    let mut has_faces = false;
+    let mut values = Vec::new();
+    for element in &elements {
+        match element.name.as_str() {
+            "vertex" => read_vertices(&mut reader, element, &mut mesh, &mut values)?,
+            "face" => {
+                read_faces(&mut reader, element, &mut mesh, &mut values)?;
+                has_faces = true;
+            }
+            _ => {
+                for _ in 0..element.count {
+                    for (_, property) in &element.properties {
+                        reader.read_property(property, &mut values)?;
+                    }
+                }
+            }
+        }
+    }
+    if !has_faces {
+        return Err("there are no faces, point clouds can't be drawn".to_string());
+    }
+    if let Some(&i) = mesh
+        .triangles
+        .iter()
+        .flatten()
+        .find(|&&i| i >= mesh.positions.len())
+    {
+        return Err(format!(
+            "face refers to vertex {} but there are only {}",
+            i,
+            mesh.positions.len()
+        ));
+    }
+    Ok(mesh)
+}
+
+fn read_vertices(
+    reader: &mut Reader,
+    element: &Element,
+    mesh: &mut PlyMesh,
+    values: &mut Vec<f64>,
+) -> Result<(), String> {
+    let find = |names: [&str; 3]| -> Option<[usize; 3]> {
+        let [a, b, c] = names.map(|name| element.property(name));
+        Some([a?, b?, c?])
+    };
+    let position = find(["x", "y", "z"]).ok_or("vertices don't have x, y and z")?;
+    let normal = find(["nx", "ny", "nz"]);
+    let color = find(["red", "green", "blue"])
+        .or_else(|| find(["diffuse_red", "diffuse_green", "diffuse_blue"]));
+    let color_scale = color.map(|[r, _, _]| match element.properties[r].1 {
+        Property::Scalar(kind) => kind.color_scale(),
+        Property::List { .. } => 1.,
+    });
+    let mut normals = Vec::new();
+    let mut colors = Vec::new();
+    // one value for each scalar property, lists end up as 0
+    let mut vertex = vec![0.; element.properties.len()];
+    for _ in 0..element.count {
+        for (i, (_, property)) in element.properties.iter().enumerate() {
+            vertex[i] = reader.read_property(property, values)?;
+        }
+        let [x, y, z] = position.map(|i| vertex[i] as f32);
+        mesh.positions.push(point3(x, y, z));
+        if let Some(normal) = normal {
+            let [x, y, z] = normal.map(|i| vertex[i] as f32);
+            normals.push(vec3(x, y, z).normalize());
+        }
+        if let (Some(color), Some(scale)) = (color, color_scale) {
+            let rgb = color.map(|i| vertex[i] / scale);
+            if !rgb.iter().all(|c| c.is_finite()) {
+                return Err("bad color".to_string());
+            }
+            let [r, g, b] = rgb.map(|c| c.clamp(0., 1.) as f32);
+            colors.push(Color::new(r, g, b));
+        }
+    }
+    mesh.normals = normal.map(|_| normals);
+    mesh.colors = color.map(|_| colors);
+    Ok(())
+}
+
+fn read_faces(
+    reader: &mut Reader,
+    element: &Element,
+    mesh: &mut PlyMesh,
+    values: &mut Vec<f64>,
+) -> Result<(), String> {
+    let indices = element
+        .property("vertex_indices")
+        .or_else(|| element.property("vertex_index"))
+        .ok_or("faces don't have vertex_indices")?;
+    let mut face = Vec::new();
+    for _ in 0..element.count {
+        for (i, (_, property)) in element.properties.iter().enumerate() {
+            reader.read_property(property, values)?;
+            if i == indices {
+                face.clone_from(values);
+            }
+        }
+        if face.iter().any(|&i| i < 0.) {
+            return Err("faces can't have negative vertex indices".to_string());
+        }
+        // fan, like convex polygons in .obj files
+        for i in 1..face.len().saturating_sub(1) {
+            mesh.triangles
+                .push([face[0], face[i], face[i + 1]].map(|i| i as usize));
+        }
+    }
+    Ok(())
+}
+
+struct Reader<'a> {
+    format: Format,
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    // Reads a scalar property, or a list into values
+    fn read_property(&mut self, property: &Property, values: &mut Vec<f64>) -> Result<f64, String> {
+        match *property {
+            Property::Scalar(kind) => self.read(kind),
+            Property::List { count, item } => {
+                let count = self.read(count)?;
+                values.clear();
+                for _ in 0..count as usize {
+                    values.push(self.read(item)?);
+                }
+                Ok(0.)
+            }
+        }
+    }
+
+    fn read(&mut self, kind: ScalarType) -> Result<f64, String> {
+        if self.format == Format::Ascii {
+            let rest = &self.bytes[self.pos..];
+            let start = rest
+                .iter()
+                .position(|b| !b.is_ascii_whitespace())
+                .ok_or("the file ends too soon")?;
+            let len = rest[start..]
+                .iter()
+                .position(|b| b.is_ascii_whitespace())
+                .unwrap_or(rest.len() - start);
+            self.pos += start + len;
+            let word = std::str::from_utf8(&rest[start..start + len]).unwrap_or("");
+            return word
+                .parse()
+                .map_err(|_| format!("\"{}\" isn't a number", word));
+        }
+
+        let size = kind.size();
+        let mut b = [0; 8];
+        b[..size].copy_from_slice(
+            self.bytes
+                .get(self.pos..self.pos + size)
+                .ok_or("the file ends too soon")?,
+        );
+        self.pos += size;
+        if self.format == Format::BinaryBigEndian {
+            b[..size].reverse();
+        }
+        let b2 = [b[0], b[1]];
+        let b4 = [b[0], b[1], b[2], b[3]];
+        Ok(match kind {
+            ScalarType::I8 => b[0] as i8 as f64,
+            ScalarType::U8 => b[0] as f64,
+            ScalarType::I16 => i16::from_le_bytes(b2) as f64,
+            ScalarType::U16 => u16::from_le_bytes(b2) as f64,
+            ScalarType::I32 => i32::from_le_bytes(b4) as f64,
+            ScalarType::U32 => u32::from_le_bytes(b4) as f64,
+            ScalarType::F32 => f32::from_le_bytes(b4) as f64,
+            ScalarType::F64 => f64::from_le_bytes(b),
+        })
+    }
+}
@@ -0,0 +1,342 @@
+// Full screen passes that run on the finished image. All buffers are 0x00RRGGBB, row major.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DownsampleFilter {
+    // Plain average of the factor x factor block, cheap and never rings
+    Box,
+    // Lanczos with a = 2, sharper but can overshoot slightly around hard edges
+    Lanczos,
+}
+
+#[inline(always)]
+fn unpack(color: u32) -> [f32; 3] {
+    [
+        ((color >> 16) & 0xff) as f32,
+        ((color >> 8) & 0xff) as f32,
+        (color & 0xff) as f32,
+    ]
+}
+
+#[inline(always)]
+fn pack(color: [f32; 3]) -> u32 {
+    let c = color.map(|c| (c + 0.5).clamp(0., 255.) as u32);
+    c[0] << 16 | c[1] << 8 | c[2]
+}
+
+// Shrinks a (width * factor) x (height * factor) image down to width x height
+pub fn downsample(
+    src: &[u32],
+    dst: &mut [u32],
+    width: usize,
+    height: usize,
+    factor: usize,
+    filter: DownsampleFilter,
+) {
+    debug_assert!(src.len() == width * height * factor * factor && dst.len() == width * height);
+    match filter {
+        DownsampleFilter::Box => downsample_box(src, dst, width, height, factor),
+        DownsampleFilter::Lanczos => downsample_lanczos(src, dst, width, height, factor),
+    }
+}
+
+fn downsample_box(src: &[u32], dst: &mut [u32], width: usize, height: usize, factor: usize) {
+    let src_width = width * factor;
+    let n = (factor * factor) as f32;
+    for y in 0..height {
+        for x in 0..width {
+            let mut sum = [0.; 3];
+            for sy in y * factor..(y + 1) * factor {
+                for sx in x * factor..(x + 1) * factor {
+                    let c = unpack(src[sx + sy * src_width]);
+                    sum[0] += c[0];
+                    sum[1] += c[1];
+                    sum[2] += c[2];
+                }
+            }
+            dst[x + y * width] = pack(sum.map(|c| c / n));
+        }
+    }
+}
+
+const LANCZOS_A: f32 = 2.;
+
+fn lanczos(x: f32) -> f32 {
+    if x == 0. {
+        return 1.;
+    }
+    if x.abs() >= LANCZOS_A {
+        return 0.;
+    }
+    let px = std::f32::consts::PI * x;
+    LANCZOS_A * px.sin() * (px / LANCZOS_A).sin() / (px * px)
+}
+
+// Normalised weights for every destination pixel along one axis, as (first source index, weights)
+fn lanczos_weights(dst_len: usize, factor: usize) -> Vec<(usize, Vec<f32>)> {
+    let src_len = dst_len * factor;
+    let scale = factor as f32;
+    let radius = (LANCZOS_A * scale).ceil() as isize;
+    (0..dst_len)
+        .map(|i| {
+            // centre of the destination pixel in source pixel coordinates
+            let centre = (i as f32 + 0.5) * scale - 0.5;
+            let first = (centre.floor() as isize - radius).max(0);
+            let last = (centre.ceil() as isize + radius).min(src_len as isize - 1);
+            let mut weights: Vec<f32> = (first..=last)
+                .map(|s| lanczos((s as f32 - centre) / scale))
+                .collect();
+            let total: f32 = weights.iter().sum();
+            for w in weights.iter_mut() {
+                *w /= total;
+            }
+            (first as usize, weights)
+        })
+        .collect()
+}
+
+// Separable, filters horizontally into a float buffer and then vertically into dst
+fn downsample_lanczos(src: &[u32], dst: &mut [u32], width: usize, height: usize, factor: usize) {
+    let src_width = width * factor;
+    let src_height = height * factor;
+    let x_weights = lanczos_weights(width, factor);
+    let y_weights = lanczos_weights(height, factor);
+
+    let mut horizontal = vec![[0f32; 3]; width * src_height];
+    for y in 0..src_height {
+        let row = &src[y * src_width..(y + 1) * src_width];
+        for (x, (first, weights)) in x_weights.iter().enumerate() {
+            let mut sum = [0.; 3];
+            for (i, w) in weights.iter().enumerate() {
+                let c = unpack(row[first + i]);
+                sum[0] += c[0] * w;
+                sum[1] += c[1] * w;
+                sum[2] += c[2] * w;
+            }
+            horizontal[x + y * width] = sum;
+        }
+    }
+
+    for (y, (first, weights)) in y_weights.iter().enumerate() {
+        for x in 0..width {
+            let mut sum = [0.; 3];
+            for (i, w) in weights.iter().enumerate() {
+                let c = horizontal[x + (first + i) * width];
+                sum[0] += c[0] * w;
+                sum[1] += c[1] * w;
+                sum[2] += c[2] * w;
+            }
+            dst[x + y * width] = pack(sum);
+        }
+    }
+}
+
+// FXAA tuning values, these are the defaults from the FXAA 3.11 quality preset
+const FXAA_EDGE_THRESHOLD: f32 = 0.125;
+const FXAA_EDGE_THRESHOLD_MIN: f32 = 0.0312;
+const FXAA_SUBPIXEL_QUALITY: f32 = 0.75;
+const FXAA_SEARCH_STEPS: usize = 12;
+
+struct FxaaImage<'a> {
+    pixels: &'a [u32],
+    luma: Vec<f32>,
+    width: usize,
+    height: usize,
+}
+
+impl FxaaImage<'_> {
+    #[inline(always)]
+    fn luma(&self, x: isize, y: isize) -> f32 {
+        let x = x.clamp(0, self.width as isize - 1) as usize;
+        let y = y.clamp(0, self.height as isize - 1) as usize;
+        self.luma[x + y * self.width]
+    }
+
+    // Bilinear luma lookup, (x, y) are in pixel units where pixel centres are at integers
+    fn luma_bilinear(&self, x: f32, y: f32) -> f32 {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as isize, y0 as isize);
+        let top = self.luma(x0, y0) * (1. - fx) + self.luma(x0 + 1, y0) * fx;
+        let bottom = self.luma(x0, y0 + 1) * (1. - fx) + self.luma(x0 + 1, y0 + 1) * fx;
+        top * (1. - fy) + bottom * fy
+    }
+
+    fn color_bilinear(&self, x: f32, y: f32) -> u32 {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let fetch = |x: isize, y: isize| {
+            let x = x.clamp(0, self.width as isize - 1) as usize;
+            let y = y.clamp(0, self.height as isize - 1) as usize;
+            unpack(self.pixels[x + y * self.width])
+        };
+        let (x0, y0) = (x0 as isize, y0 as isize);
+        let (c00, c10) = (fetch(x0, y0), fetch(x0 + 1, y0));
+        let (c01, c11) = (fetch(x0, y0 + 1), fetch(x0 + 1, y0 + 1));
+        let mut out = [0.; 3];
+        for i in 0..3 {
+            let top = c00[i] * (1. - fx) + c10[i] * fx;
+            let bottom = c01[i] * (1. - fx) + c11[i] * fx;
+            out[i] = top * (1. - fy) + bottom * fy;
+        }
+        pack(out)
+    }
+}
+
+// Perceptual luma in 0..1, FXAA only needs something roughly monotonic with brightness
+#[inline(always)]
+fn luma(color: u32) -> f32 {
+    let c = unpack(color);
+    (0.299 * c[0] + 0.587 * c[1] + 0.114 * c[2]) / 255.
+}
+
+// Based on Timothy Lottes' FXAA 3.11 (quality variant): find pixels on a luma edge, walk along
+// the edge in both directions to find its ends and blend across it based on where the pixel sits
+// along the edge.
+pub fn fxaa(src: &[u32], dst: &mut [u32], width: usize, height: usize) {
+    let image = FxaaImage {
+        pixels: src,
+        luma: src.iter().map(|&c| luma(c)).collect(),
+        width,
+        height,
+    };
+
+    for y in 0..height as isize {
+        for x in 0..width as isize {
+            let idx = x as usize + y as usize * width;
+            let luma_centre = image.luma(x, y);
+            let luma_up = image.luma(x, y - 1);
+            let luma_down = image.luma(x, y + 1);
+            let luma_left = image.luma(x - 1, y);
+            let luma_right = image.luma(x + 1, y);
+
+            let luma_min = luma_centre
+                .min(luma_up)
+                .min(luma_down)
+                .min(luma_left)
+                .min(luma_right);
+            let luma_max = luma_centre
+                .max(luma_up)
+                .max(luma_down)
+                .max(luma_left)
+                .max(luma_right);
+            let luma_range = luma_max - luma_min;
+            // not on an edge (or too dark to notice)
+            if luma_range < FXAA_EDGE_THRESHOLD_MIN.max(luma_max * FXAA_EDGE_THRESHOLD) {
+                dst[idx] = src[idx];
+                continue;
+            }
+
+            let luma_up_left = image.luma(x - 1, y - 1);
+            let luma_up_right = image.luma(x + 1, y - 1);
+            let luma_down_left = image.luma(x - 1, y + 1);
+            let luma_down_right = image.luma(x + 1, y + 1);
+
+            let luma_vertical = luma_up + luma_down;
+            let luma_horizontal = luma_left + luma_right;
+            let luma_left_corners = luma_down_left + luma_up_left;
+            let luma_down_corners = luma_down_left + luma_down_right;
+            let luma_right_corners = luma_down_right + luma_up_right;
+            let luma_up_corners = luma_up_right + luma_up_left;
+
+            let edge_horizontal = (-2. * luma_left + luma_left_corners).abs()
+                + (-2. * luma_centre + luma_vertical).abs() * 2.
+                + (-2. * luma_right + luma_right_corners).abs();
+            let edge_vertical = (-2. * luma_up + luma_up_corners).abs()
+                + (-2. * luma_centre + luma_horizontal).abs() * 2.
+                + (-2. * luma_down + luma_down_corners).abs();
+            let is_horizontal = edge_horizontal >= edge_vertical;
+
+            // pick the side of the edge with the steepest gradient
+            let (luma_1, luma_2) = if is_horizontal {
+                (luma_up, luma_down)
+            } else {
+                (luma_left, luma_right)
+            };
+            let gradient_1 = luma_1 - luma_centre;
+            let gradient_2 = luma_2 - luma_centre;
+            let is_1_steepest = gradient_1.abs() >= gradient_2.abs();
+            let gradient_scaled = 0.25 * gradient_1.abs().max(gradient_2.abs());
+
+            let mut step_length: f32 = if is_1_steepest { -1. } else { 1. };
+            let luma_local_average = if is_1_steepest {
+                0.5 * (luma_1 + luma_centre)
+            } else {
+                0.5 * (luma_2 + luma_centre)
+            };
+
+            // move half a pixel onto the edge, then search along it
+            let (mut cx, mut cy) = (x as f32, y as f32);
+            let (step_x, step_y) = if is_horizontal {
+                cy += step_length * 0.5;
+                (1., 0.)
+            } else {
+                cx += step_length * 0.5;
+                (0., 1.)
+            };
+
+            let (mut x1, mut y1) = (cx - step_x, cy - step_y);
+            let (mut x2, mut y2) = (cx + step_x, cy + step_y);
+            let mut luma_end_1 = image.luma_bilinear(x1, y1) - luma_local_average;
+            let mut luma_end_2 = image.luma_bilinear(x2, y2) - luma_local_average;
+            let mut reached_1 = luma_end_1.abs() >= gradient_scaled;
+            let mut reached_2 = luma_end_2.abs() >= gradient_scaled;
+
+            for _ in 0..FXAA_SEARCH_STEPS {
+                if reached_1 && reached_2 {
+                    break;
+                }
+                if !reached_1 {
+                    x1 -= step_x;
+                    y1 -= step_y;
+                    luma_end_1 = image.luma_bilinear(x1, y1) - luma_local_average;
+                    reached_1 = luma_end_1.abs() >= gradient_scaled;
+                }
+                if !reached_2 {
+                    x2 += step_x;
+                    y2 += step_y;
+                    luma_end_2 = image.luma_bilinear(x2, y2) - luma_local_average;
+                    reached_2 = luma_end_2.abs() >= gradient_scaled;
+                }
+            }
+
+            let (distance_1, distance_2) = if is_horizontal {
+                (x as f32 - x1, x2 - x as f32)
+            } else {
+                (y as f32 - y1, y2 - y as f32)
+            };
+            let is_direction_1 = distance_1 < distance_2;
+            let distance_final = distance_1.min(distance_2);
+            let edge_thickness = distance_1 + distance_2;
+
+            // only blend if the closest end of the edge has the variation we expect
+            let is_luma_centre_smaller = luma_centre < luma_local_average;
+            let correct_variation = if is_direction_1 {
+                (luma_end_1 < 0.) != is_luma_centre_smaller
+            } else {
+                (luma_end_2 < 0.) != is_luma_centre_smaller
+            };
+            let edge_offset = if correct_variation {
+                -distance_final / edge_thickness + 0.5
+            } else {
+                0.
+            };
+
+            // subpixel aliasing, e.g. single pixel features
+            let luma_average = (1. / 12.)
+                * (2. * (luma_vertical + luma_horizontal) + luma_left_corners + luma_right_corners);
+            let sub_pixel_offset_1 =
+                ((luma_average - luma_centre).abs() / luma_range).clamp(0., 1.);
+            let sub_pixel_offset_2 =
+                (-2. * sub_pixel_offset_1 + 3.) * sub_pixel_offset_1 * sub_pixel_offset_1;
+            let sub_pixel_offset = sub_pixel_offset_2 * sub_pixel_offset_2 * FXAA_SUBPIXEL_QUALITY;
+
+            step_length *= edge_offset.max(sub_pixel_offset);
+            let (sx, sy) = if is_horizontal {
+                (x as f32, y as f32 + step_length)
+            } else {
+                (x as f32 + step_length, y as f32)
+            };
+            dst[idx] = image.color_bilinear(sx, sy);
+        }
+    }
+}
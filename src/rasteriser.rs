@@ -1,7 +1,50 @@
+use crate::bresenham_line;
+use crate::clip_line;
+use crate::dash_polyline;
+use crate::depth_range;
+use crate::downsample;
+use crate::draw_text;
+use crate::fxaa;
+use crate::interpolate;
+use crate::load_gltf;
+use crate::resolve_samples;
+use crate::span_fragments;
+use crate::stroke_pieces;
+use crate::wu_line;
+use crate::BitmapFont;
+use crate::Camera;
 use crate::Color;
+use crate::CompareFunc;
+use crate::Containment;
+use crate::DepthBounds;
+use crate::DownsampleFilter;
+use crate::Frustum;
+use crate::Light;
+use crate::LineStyle;
+use crate::LoadError;
+use crate::MaterialOverride;
+use crate::MeshRef;
+use crate::Node;
+use crate::NodeId;
 use crate::ObjData;
+use crate::RenderState;
+use crate::RenderStats;
+use crate::RenderStatsHistory;
+use crate::SampleCount;
+use crate::SampleTarget;
+use crate::Scene;
+use crate::SimdLevel;
+use crate::SpanDepthState;
+use crate::SpanEdges;
+use crate::StencilFaceState;
+use crate::StencilOp;
+use crate::StencilState;
+use crate::Submesh;
+use crate::TextStyle;
 use crate::TextureData;
-use cgmath::perspective;
+use crate::TileGrid;
+use crate::TriangleSetup;
+use crate::BLOCK_SIZE;
 use cgmath::point3;
 use cgmath::vec3;
 use cgmath::vec4;
@@ -9,9 +52,14 @@ use cgmath::Angle;
 use cgmath::Deg;
 use cgmath::InnerSpace;
 use cgmath::Matrix;
+use cgmath::Matrix4;
 use cgmath::Point3;
 use cgmath::Transform;
 use cgmath::Vector3;
+use rayon::prelude::*;
+use rayon::ThreadPool;
+use std::collections::HashMap;
+use std::time::Instant;
 //use rand::Rng;
 
 // To interface with the rasteriser
@@ -21,8 +69,12 @@ pub struct TriangleData<'a> {
     pub position: [Point3<f32>; 3],
     pub texture: Option<&'a TextureData>,
     pub normal: Option<[Vector3<f32>; 3]>,
+    pub vertex_colors: Option<[Color; 3]>,
+    // multiplies the lit or textured color
+    pub color: Color,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum TriangleShading {
     Points,
     Wireframe,
@@ -30,12 +82,162 @@ pub enum TriangleShading {
     Gouraud,
 }
 
+impl TriangleShading {
+    // For cycling through the modes, wrapping around after Gouraud
+    pub fn next(self) -> TriangleShading {
+        match self {
+            TriangleShading::Points => TriangleShading::Wireframe,
+            TriangleShading::Wireframe => TriangleShading::Flat,
+            TriangleShading::Flat => TriangleShading::Gouraud,
+            TriangleShading::Gouraud => TriangleShading::Points,
+        }
+    }
+}
+
+// Replaces the colors draw_objects() writes with something to look at while debugging
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugView {
+    Off,
+    // linear distance from the camera, white for the nearest thing in view and black for the
+    // furthest
+    Depth,
+    // view space normals mapped from -1..1 to 0..1, so surfaces facing the camera are blue
+    Normals,
+    // texture coordinates as red and green, black where there's no texture
+    TexCoords,
+    // how many fragments landed on each pixel, blue for one through to red for five and white
+    // for more, hidden ones included
+    Overdraw,
+}
+
+impl DebugView {
+    // For cycling through the views, wrapping around after Overdraw
+    pub fn next(self) -> DebugView {
+        match self {
+            DebugView::Off => DebugView::Depth,
+            DebugView::Depth => DebugView::Normals,
+            DebugView::Normals => DebugView::TexCoords,
+            DebugView::TexCoords => DebugView::Overdraw,
+            DebugView::Overdraw => DebugView::Off,
+        }
+    }
+}
+
+// Spins every mesh around the world's y axis, under lights that stay put
+#[derive(Clone, Copy, Debug)]
+pub struct Turntable {
+    pub angle: Deg<f32>,
+    // added to angle after each frame, zero holds the scene still
+    pub speed: Deg<f32>,
+}
+
+impl Default for Turntable {
+    fn default() -> Turntable {
+        Turntable {
+            angle: Deg(0.),
+            speed: Deg(1.),
+        }
+    }
+}
+
+// A triangle that has been projected, set up for rasterising and survived culling
+struct SetupTriangle<'a> {
+    setup: TriangleSetup,
+    // screen/raster space
+    position: [Point3<f32>; 3],
+    // (flat shading) normal must be calculated before persp projection
+    face_normal: Vector3<f32>,
+    normal: Option<[Vector3<f32>; 3]>,
+    texture: Option<&'a TextureData>,
+    vertex_colors: Option<[Color; 3]>,
+    color: Color,
+    front_facing: bool,
+    shading: TriangleShading,
+    lighting: bool,
+    debug_view: DebugView,
+}
+
+// What happened to the fragments of some triangles, added up into the frame's RenderStats
+#[derive(Clone, Copy, Default)]
+struct FragmentCounts {
+    tested: usize,
+    passed: usize,
+    shaded: usize,
+    texture_samples: usize,
+}
+
+impl FragmentCounts {
+    fn merge(self, other: FragmentCounts) -> FragmentCounts {
+        FragmentCounts {
+            tested: self.tested + other.tested,
+            passed: self.passed + other.passed,
+            shaded: self.shaded + other.shaded,
+            texture_samples: self.texture_samples + other.texture_samples,
+        }
+    }
+
+    // Every fragment of a textured triangle samples its texture once when shaded
+    fn with_texture_samples(self, tri: &SetupTriangle) -> FragmentCounts {
+        let textured = tri.texture.is_some() && tri.debug_view != DebugView::TexCoords;
+        FragmentCounts {
+            texture_samples: if textured { self.shaded } else { 0 },
+            ..self
+        }
+    }
+}
+
 pub struct Rasteriser {
     width: usize,
     height: usize,
+    // resolution everything is rasterised at, bigger than width x height when supersampling
+    render_width: usize,
+    render_height: usize,
     pub buffer: Vec<u32>,
+    // every sample of every rendered pixel, turned into buffer at the end of the frame
+    color_buffer: Vec<u32>,
     zbuffer: Vec<f32>,
-    loaded_objs: Vec<ObjData>,
+    stencil_buffer: Vec<u8>,
+    // coarse min/max of zbuffer per block of pixels
+    hiz_buffer: Vec<DepthBounds>,
+    // skips blocks of pixels that are already known to be hidden, doesn't change the image. Costs a
+    // little when most triangles are drawn back to front, as every block has to be rescanned
+    pub hierarchical_z: bool,
+    sample_count: SampleCount,
+    supersample_factor: usize,
+    downsample_filter: DownsampleFilter,
+    pub fxaa: bool,
+    // scratch space for the passes between color_buffer and buffer
+    resolve_buffer: Vec<u32>,
+    post_buffer: Vec<u32>,
+    pub scene: Scene,
+    pub camera: Camera,
+    // how draw_objects() draws the scene's meshes
+    pub shading: TriangleShading,
+    // lights of the frame being drawn, in view space
+    lights: Vec<Light>,
+    // skips the vertex processing of triangles outside the camera's view, using the bounding
+    // volumes worked out when loading
+    pub frustum_culling: bool,
+    // per tile copies of the buffers for multithreaded rendering
+    tile_grid: TileGrid,
+    // None when rendering on a single thread
+    thread_pool: Option<ThreadPool>,
+    simd_level: SimdLevel,
+    pub render_state: RenderState,
+    // used for TriangleShading::Wireframe
+    pub wireframe_style: LineStyle,
+    // draws the edges of every triangle on top of Flat or Gouraud shading, in overlay_style
+    pub wireframe_overlay: bool,
+    pub overlay_style: LineStyle,
+    // false draws textured meshes as if they weren't
+    pub textures: bool,
+    // false draws untextured meshes in their plain color, ignoring the lights
+    pub lighting: bool,
+    pub debug_view: DebugView,
+    pub turntable: Turntable,
+    stats: RenderStats,
+    // keeps the stats of every frame when there is one, e.g. to save them as CSV
+    pub stats_history: Option<RenderStatsHistory>,
 }
 
 impl Rasteriser {
@@ -43,9 +245,45 @@ impl Rasteriser {
         Rasteriser {
             width,
             height,
-            buffer: vec![0; (width * height) as usize],
-            zbuffer: vec![-std::f32::INFINITY; (width * height) as usize],
-            loaded_objs: Vec::new(),
+            render_width: width,
+            render_height: height,
+            buffer: vec![0; width * height],
+            color_buffer: vec![0; width * height],
+            zbuffer: vec![f32::INFINITY; width * height],
+            stencil_buffer: vec![0; width * height],
+            hiz_buffer: vec![DepthBounds::new(f32::INFINITY); hiz_blocks(width, height)],
+            hierarchical_z: true,
+            sample_count: SampleCount::X1,
+            supersample_factor: 1,
+            downsample_filter: DownsampleFilter::Box,
+            fxaa: false,
+            resolve_buffer: Vec::new(),
+            post_buffer: Vec::new(),
+            scene: Scene::new(),
+            camera: Camera::default(),
+            shading: TriangleShading::Flat,
+            lights: vec![Light::default()],
+            frustum_culling: true,
+            tile_grid: TileGrid::new(width, height, 1),
+            thread_pool: build_thread_pool(
+                std::thread::available_parallelism().map_or(1, |threads| threads.get()),
+            ),
+            simd_level: SimdLevel::detect(),
+            render_state: RenderState::default(),
+            wireframe_style: LineStyle::default(),
+            wireframe_overlay: false,
+            overlay_style: LineStyle {
+                color: Color::new(0., 0., 0.),
+                depth_test: true,
+                depth_bias: 0.0001,
+                ..LineStyle::default()
+            },
+            textures: true,
+            lighting: true,
+            debug_view: DebugView::Off,
+            turntable: Turntable::default(),
+            stats: RenderStats::default(),
+            stats_history: None,
         }
     }
 
@@ -54,88 +292,531 @@ impl Rasteriser {
             *i = 0x00;
         }
 
+        for i in self.color_buffer.iter_mut() {
+            *i = 0x00;
+        }
+
         for i in self.zbuffer.iter_mut() {
-            *i = std::f32::INFINITY;
+            *i = self.render_state.clear_depth;
+        }
+
+        for i in self.stencil_buffer.iter_mut() {
+            *i = self.render_state.clear_stencil;
+        }
+
+        for i in self.hiz_buffer.iter_mut() {
+            *i = DepthBounds::new(self.render_state.clear_depth);
+        }
+    }
+
+    // Holds sample_count().samples() consecutive values per rendered pixel
+    pub fn stencil_buffer(&self) -> &[u8] {
+        &self.stencil_buffer
+    }
+
+    // Size of buffer, which is what gets displayed
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    // Reallocates every buffer, keeping the sample count and supersampling
+    pub fn set_size(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+        self.render_width = width * self.supersample_factor;
+        self.render_height = height * self.supersample_factor;
+        self.buffer = vec![0; width * height];
+        self.allocate_buffers();
+    }
+
+    pub fn sample_count(&self) -> SampleCount {
+        self.sample_count
+    }
+
+    // Reallocates the depth, stencil and color buffers, so should not be called every frame
+    pub fn set_sample_count(&mut self, sample_count: SampleCount) {
+        self.sample_count = sample_count;
+        self.allocate_buffers();
+    }
+
+    pub fn supersampling(&self) -> (usize, DownsampleFilter) {
+        (self.supersample_factor, self.downsample_filter)
+    }
+
+    // Renders at factor times the resolution in each axis and filters the result down to
+    // width x height. A factor of 1 turns supersampling off.
+    pub fn set_supersampling(&mut self, factor: usize, filter: DownsampleFilter) {
+        assert!(factor >= 1, "Supersampling factor must be at least 1");
+        self.supersample_factor = factor;
+        self.downsample_filter = filter;
+        self.render_width = self.width * factor;
+        self.render_height = self.height * factor;
+        self.allocate_buffers();
+    }
+
+    pub fn threads(&self) -> usize {
+        self.thread_pool
+            .as_ref()
+            .map_or(1, |pool| pool.current_num_threads())
+    }
+
+    // With more than one thread draw_objects() bins triangles into screen tiles which get
+    // rasterised in parallel, otherwise every triangle is drawn straight into the frame. Both give
+    // exactly the same image. Defaults to the number of cores.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.thread_pool = build_thread_pool(threads);
+    }
+
+    pub fn simd_level(&self) -> SimdLevel {
+        self.simd_level
+    }
+
+    // Picks the detected instruction set by default, this is mostly for checking the other paths
+    // against it
+    pub fn set_simd_level(&mut self, simd_level: SimdLevel) {
+        let supported = match SimdLevel::detect() {
+            SimdLevel::Scalar => simd_level == SimdLevel::Scalar,
+            SimdLevel::Sse2 => simd_level != SimdLevel::Avx2,
+            SimdLevel::Avx2 => true,
+        };
+        assert!(supported, "{:?} isn't supported by this CPU", simd_level);
+        self.simd_level = simd_level;
+    }
+
+    fn allocate_buffers(&mut self) {
+        let pixels = self.render_width * self.render_height;
+        let size = pixels * self.sample_count.samples();
+        self.color_buffer = vec![0; size];
+        self.zbuffer = vec![self.render_state.clear_depth; size];
+        self.stencil_buffer = vec![self.render_state.clear_stencil; size];
+        self.hiz_buffer = vec![
+            DepthBounds::new(self.render_state.clear_depth);
+            hiz_blocks(self.render_width, self.render_height)
+        ];
+        self.tile_grid = TileGrid::new(
+            self.render_width,
+            self.render_height,
+            self.sample_count.samples(),
+        );
+        // only needed when both the multisample resolve and the downsample happen
+        self.resolve_buffer = if self.sample_count != SampleCount::X1 && self.supersample_factor > 1
+        {
+            vec![0; pixels]
+        } else {
+            Vec::new()
+        };
+    }
+
+    // Multisample resolve -> supersample downsample -> fxaa, ending up in buffer
+    fn finish_frame(&mut self) {
+        let samples = self.sample_count.samples();
+        let (width, height, factor) = (self.width, self.height, self.supersample_factor);
+        let filter = self.downsample_filter;
+
+        fn resolve(src: &[u32], dst: &mut [u32], samples: usize) {
+            for (pixel, samples) in dst.iter_mut().zip(src.chunks_exact(samples)) {
+                *pixel = resolve_samples(samples);
+            }
+        }
+
+        match (samples > 1, factor > 1) {
+            // nothing to filter, so the rendered image can be handed over as is
+            (false, false) => std::mem::swap(&mut self.buffer, &mut self.color_buffer),
+            (true, false) => resolve(&self.color_buffer, &mut self.buffer, samples),
+            (false, true) => downsample(
+                &self.color_buffer,
+                &mut self.buffer,
+                width,
+                height,
+                factor,
+                filter,
+            ),
+            (true, true) => {
+                resolve(&self.color_buffer, &mut self.resolve_buffer, samples);
+                downsample(
+                    &self.resolve_buffer,
+                    &mut self.buffer,
+                    width,
+                    height,
+                    factor,
+                    filter,
+                );
+            }
         }
+
+        if self.fxaa {
+            self.post_buffer.resize(width * height, 0);
+            fxaa(&self.buffer, &mut self.post_buffer, width, height);
+            std::mem::swap(&mut self.buffer, &mut self.post_buffer);
+        }
+    }
+
+    // Adds the model to the root of the scene, in a node named after the file
+    pub fn load_obj(&mut self, obj_path: &str) -> NodeId {
+        let name = std::path::Path::new(obj_path)
+            .file_stem()
+            .map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+        self.scene.add_obj(ObjData::new(obj_path), &name, None)
+    }
+
+    // Adds a .gltf or .glb model to the scene, see load_gltf()
+    pub fn load_gltf(&mut self, path: &str) -> Result<NodeId, LoadError> {
+        load_gltf(&mut self.scene, path, None)
+    }
+
+    // Adds a model in any of the formats Scene::load_model() supports
+    pub fn load_model(&mut self, path: &str) -> Result<NodeId, LoadError> {
+        self.scene.load_model(path, None)
     }
 
-    pub fn load_obj(&mut self, obj_path: &str) {
-        self.loaded_objs.push(ObjData::new(obj_path));
+    // Draws the scene into buffer, returning what it took
+    pub fn render_frame(&mut self) -> RenderStats {
+        self.begin_frame();
+        self.draw_objects();
+        self.end_frame();
+        self.stats
     }
 
-    pub fn render_frame(&mut self) {
+    // render_frame() split up, so extra things (e.g. debug lines) can be drawn into the frame
+    // before it gets resolved
+    pub fn begin_frame(&mut self) {
         self.clear_buffers();
-        unsafe {
-            static mut ANGLE: f32 = 0.;
-            let rcol1 = vec4(0., 1., 0., 0.);
-            let rcol3 = vec4(0., 0., 0., 1.);
-            #[rustfmt::skip]
-            let translation_matrix = cgmath::Matrix4::new(  1.,0.,0.,0.,
-                                                            0.,1.,0.,0.,
-                                                            0.,0.,1.,0.,
-                                                            0.,0.,0.,1.,);
-            for mut obj in self.loaded_objs.clone() {
-                let has_normals = obj.tri_normals.is_some();
-                let has_textures: bool = obj.tri_textures.is_some();
-                for i in 0..obj.len() {
-                    // vertex shader?
-                    let rcol0 = vec4(Deg::cos(Deg(ANGLE)), 0., Deg::sin(Deg(ANGLE)), 0.);
-                    let rcol2 = vec4(-Deg::sin(Deg(ANGLE)), 0., Deg::cos(Deg(ANGLE)), 0.);
-                    let rotation_matrix = cgmath::Matrix4 {
-                        x: rcol0,
-                        y: rcol1,
-                        z: rcol2,
-                        w: rcol3,
-                    };
-                    let transformation_matrix = translation_matrix * rotation_matrix;
+        self.stats = RenderStats::default();
+    }
 
-                    for i in obj.tri_positions[i].iter_mut() {
-                        *i = Point3::<f32>::from_homogeneous(
-                            transformation_matrix * (*i).to_homogeneous(),
-                        );
-                    }
-                    // TODO: make this less ugly
-                    if has_normals {
-                        for i in obj.tri_normals.as_mut().unwrap()[i].iter_mut() {
-                            let v = cgmath::Vector4 {
-                                x: (*i).x,
-                                y: (*i).y,
-                                z: (*i).z,
-                                w: 0.,
-                            };
-                            let o = transformation_matrix
-                                .inverse_transform()
-                                .unwrap()
-                                .transpose()
-                                * v;
-                            *i = Vector3 {
-                                x: o.x,
-                                y: o.y,
-                                z: o.z,
-                            }
-                        }
-                    }
-                    // vertex shader //
-
-                    let tri = TriangleData {
-                        position: obj.tri_positions[i],
-                        texture: if has_textures {
-                            Some(&obj.tri_textures.as_ref().unwrap()[i])
-                        } else {
-                            None
-                        },
-                        normal: if has_normals {
-                            Some(obj.tri_normals.as_ref().unwrap()[i])
-                        } else {
-                            None
-                        },
-                    };
+    // What has been drawn since begin_frame()
+    pub fn stats(&self) -> RenderStats {
+        self.stats
+    }
+
+    pub fn end_frame(&mut self) {
+        let start = Instant::now();
+        self.finish_frame();
+        self.stats.post_time += start.elapsed();
+        if let Some(history) = &mut self.stats_history {
+            history.push(self.stats);
+        }
+    }
+
+    pub fn draw_objects(&mut self) {
+        let angle = self.turntable.angle.0;
+        self.turntable.angle += self.turntable.speed;
+        let rcol0 = vec4(Deg::cos(Deg(angle)), 0., Deg::sin(Deg(angle)), 0.);
+        let rcol1 = vec4(0., 1., 0., 0.);
+        let rcol2 = vec4(-Deg::sin(Deg(angle)), 0., Deg::cos(Deg(angle)), 0.);
+        let rcol3 = vec4(0., 0., 0., 1.);
+        let rotation_matrix = cgmath::Matrix4 {
+            x: rcol0,
+            y: rcol1,
+            z: rcol2,
+            w: rcol3,
+        };
+        #[rustfmt::skip]
+        let translation_matrix = cgmath::Matrix4::new(  1.,0.,0.,0.,
+                                                        0.,1.,0.,0.,
+                                                        0.,0.,1.,0.,
+                                                        0.,0.,0.,1.,);
+        let turntable_matrix = translation_matrix * rotation_matrix;
+        let view_matrix = self.camera.view_matrix();
+        let projection_matrix = self.projection_matrix();
+
+        self.scene.update_world_matrices();
+        self.lights = self
+            .scene
+            .world_lights()
+            .into_iter()
+            .map(|light| Light {
+                direction: view_matrix.transform_vector(light.direction).normalize(),
+                ..light
+            })
+            .collect();
+        if self.lights.is_empty() {
+            // a light at the camera, already in view space
+            self.lights.push(Light::default());
+        }
+
+        // counts every fragment in the stencil buffer, including the ones failing the depth test
+        let render_state = self.render_state;
+        if self.debug_view == DebugView::Overdraw {
+            let count = StencilFaceState {
+                func: CompareFunc::Always,
+                depth_fail_op: StencilOp::IncrementClamp,
+                pass_op: StencilOp::IncrementClamp,
+                ..StencilFaceState::default()
+            };
+            self.render_state.stencil = StencilState {
+                enabled: true,
+                front: count,
+                back: count,
+                ..StencilState::default()
+            };
+            self.stencil_buffer.fill(0);
+        }
+
+        // taken out of self so the triangles can borrow their textures while drawing
+        let scene = std::mem::take(&mut self.scene);
+        let frustum_culling = self.frustum_culling;
+        let shading = self.shading;
+        let transform_node = |(node, mesh): (&Node, &MeshRef)| {
+            let model_view_matrix = view_matrix * turntable_matrix * node.world_matrix;
+            let frustum = Frustum::from_matrix(projection_matrix * model_view_matrix);
+            transform_object(
+                &scene.meshes[mesh.mesh],
+                &mesh.submeshes,
+                model_view_matrix,
+                frustum_culling.then_some(&frustum),
+                node.material,
+            )
+        };
+        let start = Instant::now();
+        let submitted: usize = scene
+            .mesh_nodes()
+            .map(|(_, mesh)| {
+                let obj = &scene.meshes[mesh.mesh];
+                mesh.submeshes
+                    .iter()
+                    .map(|&i| obj.submeshes[i].triangles.len())
+                    .sum::<usize>()
+            })
+            .sum();
+        let triangles: Vec<TriangleData> = match &self.thread_pool {
+            Some(pool) => pool.install(|| {
+                let nodes: Vec<_> = scene.mesh_nodes().collect();
+                nodes
+                    .into_par_iter()
+                    .map(transform_node)
+                    .collect::<Vec<_>>()
+                    .concat()
+            }),
+            None => scene.mesh_nodes().flat_map(transform_node).collect(),
+        };
+        self.stats.triangles_submitted += submitted;
+        self.stats.triangles_culled += submitted - triangles.len();
+        self.stats.vertex_time += start.elapsed();
+
+        let filled = matches!(shading, TriangleShading::Flat | TriangleShading::Gouraud);
+        if filled {
+            match self.thread_pool.take() {
+                Some(pool) => {
+                    pool.install(|| self.draw_triangles_tiled(&triangles, shading));
+                    self.thread_pool = Some(pool);
+                }
+                None => self.draw_triangles(&triangles, shading),
+            }
+        } else {
+            // points and lines aren't set up or worth binning
+            let start = Instant::now();
+            for tri in &triangles {
+                self.draw_triangle(tri.clone(), shading);
+            }
+            self.stats.raster_time += start.elapsed();
+        }
+
+        let start = Instant::now();
+        match self.debug_view {
+            DebugView::Depth => self.show_depth(),
+            DebugView::Overdraw => {
+                self.show_overdraw();
+                self.render_state = render_state;
+            }
+            _ => {}
+        }
+        self.stats.post_time += start.elapsed();
+        if self.wireframe_overlay && filled {
+            let start = Instant::now();
+            let style = self.overlay_style.clone();
+            for tri in &triangles {
+                self.draw_wireframe(tri, &style);
+            }
+            self.stats.raster_time += start.elapsed();
+        }
+        drop(triangles);
+        self.scene = scene;
+    }
+
+    // Replaces the color of everything drawn with its depth, stretched over the range of depths
+    // in view
+    fn show_depth(&mut self) {
+        let clear_depth = self.render_state.clear_depth;
+        let (near, far) = (self.camera.near, self.camera.far);
+        // back from the projection's -1..1 to the distance from the camera
+        let linear = |z: f32| 2. * near * far / (far + near - z * (far - near));
+        let (min, max) = self
+            .zbuffer
+            .iter()
+            .filter(|&&z| z != clear_depth)
+            .map(|&z| linear(z))
+            .filter(|depth| depth.is_finite())
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), depth| {
+                (min.min(depth), max.max(depth))
+            });
+        let range = (max - min).max(f32::EPSILON);
+        for (color, &z) in self.color_buffer.iter_mut().zip(&self.zbuffer) {
+            let grey = 1. - (linear(z) - min) / range;
+            // depths that don't map back to a distance keep their color
+            if z != clear_depth && grey.is_finite() {
+                let grey = grey.clamp(0., 1.);
+                *color = Color::new(grey, grey, grey).get_pixel_color();
+            }
+        }
+    }
+
+    // Replaces the color of everything drawn with how many fragments the stencil buffer counted
+    fn show_overdraw(&mut self) {
+        let colors = [
+            Color::new(0., 0., 1.),
+            Color::new(0., 1., 1.),
+            Color::new(0., 1., 0.),
+            Color::new(1., 1., 0.),
+            Color::new(1., 0., 0.),
+            Color::new(1., 1., 1.),
+        ];
+        for (color, &count) in self.color_buffer.iter_mut().zip(&self.stencil_buffer) {
+            if count > 0 {
+                *color = colors[(count as usize - 1).min(colors.len() - 1)].get_pixel_color();
+            }
+        }
+    }
+
+    fn draw_wireframe(&mut self, tri: &TriangleData, style: &LineStyle) {
+        let position = tri.position.map(|p| self.project_to_screen(p));
+        self.stroke(&position, true, style, style.depth_test);
+    }
+
+    // Sets up and fills in Flat or Gouraud shaded triangles one after the other, for rendering on
+    // a single thread
+    fn draw_triangles(&mut self, triangles: &[TriangleData], shading: TriangleShading) {
+        let start = Instant::now();
+        let setups: Vec<SetupTriangle> = triangles
+            .iter()
+            .filter_map(|tri| self.setup_triangle(tri, shading))
+            .collect();
+        self.count_setups(triangles.len(), &setups);
+        self.stats.setup_time += start.elapsed();
+
+        let start = Instant::now();
+        let state = self.render_state;
+        let sample_count = self.sample_count;
+        let mut target = SampleTarget {
+            x0: 0,
+            y0: 0,
+            width: self.render_width,
+            height: self.render_height,
+            samples: sample_count.samples(),
+            color: &mut self.color_buffer,
+            depth: &mut self.zbuffer,
+            stencil: &mut self.stencil_buffer,
+            hiz: &mut self.hiz_buffer,
+        };
+        let mut counts = FragmentCounts::default();
+        for tri in &setups {
+            counts = counts.merge(fill_triangle(
+                &mut target,
+                &state,
+                sample_count,
+                self.simd_level,
+                self.hierarchical_z,
+                &self.lights,
+                tri,
+                &tri.setup,
+            ));
+        }
+        self.count_fragments(counts);
+        self.stats.raster_time += start.elapsed();
+    }
+
+    // Sets every Flat or Gouraud shaded triangle up on the thread pool, bins them into screen
+    // tiles and then rasterises the tiles in parallel. Should be called from inside the pool.
+    fn draw_triangles_tiled(&mut self, triangles: &[TriangleData], shading: TriangleShading) {
+        let start = Instant::now();
+        let setups: Vec<SetupTriangle> = triangles
+            .par_iter()
+            .filter_map(|tri| self.setup_triangle(tri, shading))
+            .collect();
+        self.count_setups(triangles.len(), &setups);
+
+        self.tile_grid.clear_bins();
+        for (i, tri) in setups.iter().enumerate() {
+            self.tile_grid.bin(i as u32, &tri.setup);
+        }
+        self.stats.setup_time += start.elapsed();
+
+        let start = Instant::now();
 
-                    self.draw_triangle(tri, TriangleShading::Flat);
+        let state = self.render_state;
+        let sample_count = self.sample_count;
+        let simd_level = self.simd_level;
+        let hierarchical_z = self.hierarchical_z;
+        let lights = &self.lights;
+        let samples = sample_count.samples();
+        let mut screen = SampleTarget {
+            x0: 0,
+            y0: 0,
+            width: self.render_width,
+            height: self.render_height,
+            samples,
+            color: &mut self.color_buffer,
+            depth: &mut self.zbuffer,
+            stencil: &mut self.stencil_buffer,
+            hiz: &mut self.hiz_buffer,
+        };
+        let grid = &mut self.tile_grid;
+        let shared_screen = &screen;
+        let counts = grid
+            .tiles
+            .par_iter_mut()
+            .zip(grid.bins.par_iter())
+            .filter(|(_, bin)| !bin.is_empty())
+            .map(|(tile, bin)| {
+                tile.load(shared_screen);
+                let (min_x, min_y) = (tile.x0, tile.y0);
+                let max_x = min_x + tile.width as i32 - 1;
+                let max_y = min_y + tile.height as i32 - 1;
+                let mut target = tile.target(samples);
+                let mut counts = FragmentCounts::default();
+                for &i in bin {
+                    let tri = &setups[i as usize];
+                    if let Some(setup) = tri.setup.clip(min_x, min_y, max_x, max_y) {
+                        counts = counts.merge(fill_triangle(
+                            &mut target,
+                            &state,
+                            sample_count,
+                            simd_level,
+                            hierarchical_z,
+                            lights,
+                            tri,
+                            &setup,
+                        ));
+                    }
                 }
+                counts
+            })
+            .reduce(FragmentCounts::default, FragmentCounts::merge);
+
+        for (tile, bin) in grid.tiles.iter().zip(grid.bins.iter()) {
+            if !bin.is_empty() {
+                tile.store(&mut screen);
             }
-            ANGLE += 1.;
         }
+        self.count_fragments(counts);
+        self.stats.raster_time += start.elapsed();
+    }
+
+    // The setup stage's share of the stats, out of the triangles that went into it
+    fn count_setups(&mut self, triangles: usize, setups: &[SetupTriangle]) {
+        self.stats.triangles_culled += triangles - setups.len();
+        self.stats.triangles_partly_offscreen += setups
+            .iter()
+            .filter(|tri| tri.setup.partly_offscreen)
+            .count();
+        self.stats.triangles_rasterised += setups.len();
+    }
+
+    fn count_fragments(&mut self, counts: FragmentCounts) {
+        self.stats.fragments_tested += counts.tested;
+        self.stats.fragments_passed += counts.passed;
+        self.stats.fragments_shaded += counts.shaded;
+        self.stats.texture_samples += counts.texture_samples;
     }
 
     #[inline(always)]
@@ -144,81 +825,258 @@ impl Rasteriser {
         // framebuffer is laid out in memory)
         // TODO: would it be faster to not do this calculation and instead transform the frambuffer
         // every frame?
-        (self.width * self.height) - ((self.width - x) + y * self.width)
+        (self.render_width * self.render_height) - ((self.render_width - x) + y * self.render_width)
     }
 
+    // Writes every sample of the pixel
     fn draw_pixel(&mut self, coord: usize, color: u32) {
-        self.buffer[coord as usize] = color; //RGBA32, except minifb makes A always 1
-    }
-
-    fn draw_line(&mut self, mut x0: usize, mut y0: usize, x1: usize, y1: usize, color: u32) {
-        let dx = (x1 as i32 - x0 as i32).abs();
-        let dy = -((y1 as i32 - y0 as i32).abs());
-        let sx: i32 = if x0 < x1 { 1 } else { -1 };
-        let sy: i32 = if y0 < y1 { 1 } else { -1 };
-        let mut err = dx + dy;
-        loop {
-            self.draw_pixel(self.calculate_coord(x0, y0), color);
-            if x0 == x1 && y0 == y1 {
-                break;
+        let samples = self.sample_count.samples();
+        for i in &mut self.color_buffer[coord * samples..(coord + 1) * samples] {
+            *i = color; //RGBA32, except minifb makes A always 1
+        }
+    }
+
+    // Draws a line in the screen space of buffer (pixels, origin bottom left) on top of whatever
+    // is there, should be called between begin_frame() and end_frame()
+    pub fn draw_line(&mut self, from: (f32, f32), to: (f32, f32), style: &LineStyle) {
+        self.draw_polyline(&[from, to], false, style);
+    }
+
+    pub fn draw_polyline(&mut self, points: &[(f32, f32)], closed: bool, style: &LineStyle) {
+        // scale up to the resolution we are actually rendering at
+        let factor = self.supersample_factor as f32;
+        let points: Vec<Point3<f32>> = points
+            .iter()
+            .map(|&(x, y)| point3(x * factor, y * factor, 0.))
+            .collect();
+        let mut style = style.clone();
+        style.width *= factor;
+        self.stroke(&points, closed, &style, false);
+    }
+
+    // Draws text straight into buffer, so it should be called after end_frame(). See draw_text().
+    pub fn draw_text(&mut self, font: &BitmapFont, text: &str, x: i32, y: i32, style: &TextStyle) {
+        let (width, height) = self.size();
+        draw_text(&mut self.buffer, width, height, font, text, x, y, style);
+    }
+
+    // Draws a line between two world space points, projected the same way as triangles
+    pub fn draw_line_3d(&mut self, from: Point3<f32>, to: Point3<f32>, style: &LineStyle) {
+        self.draw_polyline_3d(&[from, to], false, style);
+    }
+
+    pub fn draw_polyline_3d(&mut self, points: &[Point3<f32>], closed: bool, style: &LineStyle) {
+        let view_matrix = self.camera.view_matrix();
+        let points: Vec<Point3<f32>> = points
+            .iter()
+            .map(|&p| self.project_to_screen(view_matrix.transform_point(p)))
+            .collect();
+        self.stroke(&points, closed, style, style.depth_test);
+    }
+
+    // points are in screen/raster space
+    fn stroke(
+        &mut self,
+        points: &[Point3<f32>],
+        closed: bool,
+        style: &LineStyle,
+        depth_test: bool,
+    ) {
+        // the line algorithms treat integer coordinates as pixel centres, whereas pixel x
+        // is centred on x + 0.5 everywhere else
+        let points: Vec<Point3<f32>> = points
+            .iter()
+            .map(|p| point3(p.x - 0.5, p.y - 0.5, p.z))
+            .collect();
+        self.stroke_centred(&points, closed, style, depth_test);
+    }
+
+    fn stroke_centred(
+        &mut self,
+        points: &[Point3<f32>],
+        closed: bool,
+        style: &LineStyle,
+        depth_test: bool,
+    ) {
+        if points.len() < 2 {
+            return;
+        }
+        if let Some(pattern) = &style.dash {
+            let mut points = points.to_vec();
+            if closed {
+                points.push(points[0]);
             }
-            let e2 = err * 2;
-            // why, in the name of all that is holy and good,
-            // does this not work if we substitue e2 with err*2
-            // WTF?
-            if e2 >= dy {
-                err += dy;
-                x0 = (x0 as i32 + sx) as usize;
+            let mut solid = style.clone();
+            solid.dash = None;
+            for dash in dash_polyline(&points, pattern) {
+                self.stroke_centred(&dash, false, &solid, depth_test);
             }
-            if e2 <= dx {
-                err += dx;
-                y0 = (y0 as i32 + sy) as usize;
+            return;
+        }
+
+        let color = &style.color;
+        let depth = |z: f32| {
+            if depth_test {
+                Some(z - style.depth_bias)
+            } else {
+                None
             }
+        };
+        let segments: Vec<(Point3<f32>, Point3<f32>)> = points
+            .windows(2)
+            .map(|w| (w[0], w[1]))
+            .chain(closed.then(|| (points[points.len() - 1], points[0])))
+            .collect();
+
+        if style.width <= 1. {
+            // the line algorithms step a pixel at a time, so only the part on screen is walked.
+            // Wu's lines touch the pixels either side of them, hence the margin.
+            let min = (-1., -1.);
+            let max = (self.render_width as f32, self.render_height as f32);
+            for (a, b) in segments {
+                let Some((a, b)) = clip_line(a, b, min, max) else {
+                    continue;
+                };
+                let z = |t: f32| a.z + (b.z - a.z) * t;
+                if style.anti_aliased {
+                    wu_line(a, b, |x, y, coverage, t| {
+                        self.blend_line_pixel(x, y, color, coverage, depth(z(t)))
+                    });
+                } else {
+                    bresenham_line(a, b, |x, y, t| {
+                        self.blend_line_pixel(x, y, color, 1., depth(z(t)))
+                    });
+                }
+            }
+            return;
+        }
+
+        // thick lines: work out the coverage of every piece of the stroke, keeping the maximum per
+        // pixel so overlaps at the joins aren't blended twice
+        let mut coverage: HashMap<(i32, i32), (f32, f32)> = HashMap::new();
+        for piece in stroke_pieces(points, closed, style) {
+            let (min_x, min_y, max_x, max_y) = piece.bounds();
+            for y in min_y.max(0)..=max_y.min(self.render_height as i32 - 1) {
+                for x in min_x.max(0)..=max_x.min(self.render_width as i32 - 1) {
+                    let (distance, z) = piece.distance(x as f32, y as f32);
+                    let alpha = if style.anti_aliased {
+                        (0.5 - distance).clamp(0., 1.)
+                    } else if distance <= 0. {
+                        1.
+                    } else {
+                        0.
+                    };
+                    if alpha <= 0. {
+                        continue;
+                    }
+                    let entry = coverage.entry((x, y)).or_insert((0., z));
+                    if alpha > entry.0 {
+                        *entry = (alpha, z);
+                    }
+                }
+            }
+        }
+        for ((x, y), (alpha, z)) in coverage {
+            self.blend_line_pixel(x, y, color, alpha, depth(z));
         }
     }
 
-    fn draw_triangle(&mut self, mut tri: TriangleData, triangle_type: TriangleShading) {
-        let mut color = Color::new(1., 1., 1.);
-        let coloru32 = color.get_pixel_color();
+    // Blends color over every sample of the pixel that passes the (optional) depth test
+    fn blend_line_pixel(&mut self, x: i32, y: i32, color: &Color, alpha: f32, depth: Option<f32>) {
+        if x < 0 || y < 0 || x >= self.render_width as i32 || y >= self.render_height as i32 {
+            return;
+        }
+        let samples = self.sample_count.samples();
+        let coord = self.calculate_coord(x as usize, y as usize);
+        for sample in coord * samples..(coord + 1) * samples {
+            if let Some(z) = depth {
+                if !self.render_state.depth_func.test(z, self.zbuffer[sample]) {
+                    continue;
+                }
+            }
+            let dst = Color::new_from_pixel_color(self.color_buffer[sample]);
+            self.color_buffer[sample] = dst.mix(color, alpha.min(1.)).get_pixel_color();
+        }
+    }
 
-        // (flat shading) normal must be calculated before persp projection
-        // light intensity
-        let light_dir = vec3(0., 0., -1.).normalize();
-        let unchanged_tri_position = tri.position;
+    // The camera's projection for the aspect ratio being rendered at
+    pub fn projection_matrix(&self) -> Matrix4<f32> {
+        self.camera
+            .projection_matrix(self.render_width as f32 / self.render_height as f32)
+    }
 
-        let projection_matrix = perspective(Deg(90.), (self.width / self.height) as f32, 0.1, 100.);
+    // Projects a view space point into screen/raster space, z is the depth value that gets tested
+    fn project_to_screen(&self, p: Point3<f32>) -> Point3<f32> {
+        let projection_matrix = self.projection_matrix();
 
         // cumulative model matrix = translation * rotation * scale * vector
         // screen space matrix = viewport * projection * camera * model
         // viewport matrix basically does (NDC which ranges from -1 to +1) + 1 * width or height
-
-        for i in tri.position.iter_mut() {
-            *i = Point3::<f32>::from_homogeneous(projection_matrix * (*i).to_homogeneous());
-        }
+        let mut p = Point3::<f32>::from_homogeneous(projection_matrix * p.to_homogeneous());
 
         // convert points from NDC to screen/raster space
-        tri.position[0].x = (tri.position[0].x + 1.) * self.width as f32 * 0.5;
-        tri.position[1].x = (tri.position[1].x + 1.) * self.width as f32 * 0.5;
-        tri.position[2].x = (tri.position[2].x + 1.) * self.width as f32 * 0.5;
-        tri.position[0].y = (tri.position[0].y + 1.) * self.width as f32 * 0.5;
-        tri.position[1].y = (tri.position[1].y + 1.) * self.width as f32 * 0.5;
-        tri.position[2].y = (tri.position[2].y + 1.) * self.width as f32 * 0.5;
+        p.x = (p.x + 1.) * self.render_width as f32 * 0.5;
+        p.y = (p.y + 1.) * self.render_height as f32 * 0.5;
+        p
+    }
+
+    // Projects the triangle and sets it up for rasterising, None if it's culled or has nothing to
+    // rasterise
+    fn setup_triangle<'a>(
+        &self,
+        tri: &TriangleData<'a>,
+        shading: TriangleShading,
+    ) -> Option<SetupTriangle<'a>> {
+        let unchanged_tri_position = tri.position;
+        let position = tri.position.map(|p| self.project_to_screen(p));
+
+        // sample offsets can reach into the neighbouring pixels of the integer bounds
+        let pad = if self.sample_count == SampleCount::X1 {
+            0
+        } else {
+            1
+        };
+        let setup = TriangleSetup::new(&position, self.render_width, self.render_height, pad)?;
+        if self.render_state.should_cull(setup.signed_area) {
+            return None;
+        }
 
         // NOTE: winding order of vertices in .obj files are counter-clockwise
+        Some(SetupTriangle {
+            setup,
+            position,
+            // only needs working out once rather than per pixel
+            face_normal: (unchanged_tri_position[2] - unchanged_tri_position[0])
+                .cross(unchanged_tri_position[1] - unchanged_tri_position[0])
+                .normalize(),
+            normal: tri.normal,
+            // normals only get worked out for untextured triangles
+            texture: tri
+                .texture
+                .filter(|_| self.textures && self.debug_view != DebugView::Normals),
+            vertex_colors: tri.vertex_colors,
+            color: tri.color,
+            front_facing: self.render_state.is_front_facing(setup.signed_area),
+            shading: match (shading, tri.normal) {
+                // nothing to interpolate
+                (TriangleShading::Gouraud, None) => TriangleShading::Flat,
+                _ => shading,
+            },
+            lighting: self.lighting,
+            debug_view: self.debug_view,
+        })
+    }
 
-        /*
-        let tri[0].x = tri[0].x as i32;
-        let tri[0].y = tri[0].y as i32;
-        let tri[1].x = tri[1].x as i32;
-        let tri[1].y = tri[1].y as i32;
-        let tri[2].x = tri[2].x as i32;
-        let tri[2].y = tri[2].y as i32;
-        */
+    fn draw_triangle(&mut self, mut tri: TriangleData, triangle_type: TriangleShading) {
+        let base_color = Color::new(1., 1., 1.);
+        let coloru32 = base_color.get_pixel_color();
 
         match triangle_type {
             TriangleShading::Points => {
+                self.stats.triangles_rasterised += 1;
+                tri.position = tri.position.map(|p| self.project_to_screen(p));
                 for p in tri.position {
-                    if p.x as usize >= self.width || p.y as usize >= self.height {
+                    if p.x as usize >= self.render_width || p.y as usize >= self.render_height {
                         return;
                     }
                 }
@@ -236,174 +1094,459 @@ impl Rasteriser {
                 );
             }
             TriangleShading::Wireframe => {
-                for p in tri.position {
-                    if p.x as usize >= self.width || p.y as usize >= self.height {
-                        return;
-                    }
-                }
-                self.draw_line(
-                    tri.position[0].x as usize,
-                    tri.position[0].y as usize,
-                    tri.position[1].x as usize,
-                    tri.position[1].y as usize,
-                    coloru32,
-                );
-                self.draw_line(
-                    tri.position[1].x as usize,
-                    tri.position[1].y as usize,
-                    tri.position[2].x as usize,
-                    tri.position[2].y as usize,
-                    coloru32,
-                );
-                self.draw_line(
-                    tri.position[2].x as usize,
-                    tri.position[2].y as usize,
-                    tri.position[0].x as usize,
-                    tri.position[0].y as usize,
-                    coloru32,
-                );
+                self.stats.triangles_rasterised += 1;
+                let style = self.wireframe_style.clone();
+                self.draw_wireframe(&tri, &style);
             }
             TriangleShading::Flat | TriangleShading::Gouraud => {
-                //TODO: unsafe unwrap?
+                unreachable!("filled triangles are drawn in batches by draw_triangles(_tiled)()")
+            }
+        }
+    }
+}
 
-                // Computes triangle bounding box and clips against screen bounds
-                let tri_position_integer = tri
-                    .position
-                    .map(|p| point3(p.x as i32, p.y as i32, p.z as i32));
+fn hiz_blocks(width: usize, height: usize) -> usize {
+    width.div_ceil(BLOCK_SIZE as usize) * height.div_ceil(BLOCK_SIZE as usize)
+}
 
-                let min_x: i32 = std::cmp::max(
-                    0,
-                    tri_position_integer.iter().min_by_key(|p| p.x).unwrap().x,
-                );
-                let min_y: i32 = std::cmp::max(
-                    0,
-                    tri_position_integer.iter().min_by_key(|p| p.y).unwrap().y,
-                );
-                let max_x: i32 = std::cmp::min(
-                    (self.width - 1) as i32,
-                    tri_position_integer.iter().max_by_key(|p| p.x).unwrap().x,
-                );
-                let max_y: i32 = std::cmp::min(
-                    (self.height - 1) as i32,
-                    tri_position_integer.iter().max_by_key(|p| p.y).unwrap().y,
-                );
+fn build_thread_pool(threads: usize) -> Option<ThreadPool> {
+    if threads <= 1 {
+        return None;
+    }
+    Some(
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap(),
+    )
+}
 
-                // doesn't actually need z coord
-                #[inline(always)]
-                fn edge<T: std::ops::Mul<Output = T> + std::ops::Sub<Output = T> + Copy>(
-                    v0: Point3<T>,
-                    v1: Point3<T>,
-                    v2: Point3<T>,
-                ) -> T {
-                    (v2.x - v0.x) * (v1.y - v0.y) - (v2.y - v0.y) * (v1.x - v0.x)
-                }
+// Adds the indices of the submesh's triangles that might be inside the frustum (in the obj's
+// model space) to visible. sphere is how the whole object's bounding sphere tested.
+fn cull_submesh(
+    obj: &ObjData,
+    submesh: &Submesh,
+    sphere: Containment,
+    frustum: &Frustum,
+    visible: &mut Vec<u32>,
+) {
+    let containment = match sphere {
+        // so is everything in the object
+        Containment::Inside => Containment::Inside,
+        Containment::Outside => Containment::Outside,
+        Containment::Intersecting => frustum.test_aabb(&submesh.bounds),
+    };
+    match containment {
+        Containment::Outside => {}
+        Containment::Inside => {
+            visible.extend(submesh.triangles.start as u32..submesh.triangles.end as u32)
+        }
+        Containment::Intersecting => {
+            // submission order decides depth ties, so it has to be kept
+            let start = visible.len();
+            submesh.bvh.cull(frustum, &obj.tri_positions, visible);
+            visible[start..].sort_unstable();
+        }
+    }
+}
 
-                for y in min_y..=max_y {
-                    for x in min_x..=max_x {
-                        // make everything negative as obj files define points in counter clockwise order
-                        let p = point3(x as f32, y as f32, 0.);
-                        let area = -edge(tri.position[0], tri.position[1], tri.position[2]);
-
-                        let mut w0 = -edge(tri.position[0], tri.position[1], p) as f32;
-                        let mut w1 = -edge(tri.position[1], tri.position[2], p) as f32;
-                        let mut w2 = -edge(tri.position[2], tri.position[0], p) as f32;
-                        if w0 < 0. || w1 < 0. || w2 < 0. || area <= 0. {
-                            continue;
-                        }
-
-                        w0 /= area;
-                        w1 /= area;
-                        w2 /= area;
-
-                        // Shading
-                        // TODO: https://learnopengl.com/Advanced-Lighting/Gamma-Correction
-                        let gamma = 2.2;
-
-                        let normal: Vector3<f32>;
-                        match triangle_type {
-                            TriangleShading::Flat => {
-                                normal = (unchanged_tri_position[2] - unchanged_tri_position[0])
-                                    .cross(unchanged_tri_position[1] - unchanged_tri_position[0])
-                                    .normalize();
-                            }
-                            TriangleShading::Gouraud => {
-                                // why negative?
-                                if let Some(n) = tri.normal {
-                                    normal = -(n[2] * w0 + n[0] * w1 + n[1] * w2);
-                                } else {
-                                    panic!("How do you gouraud shade without vertex normals from .obj file?")
-                                }
-                            }
-                            _ => panic!("Invalid triangle shading type!"),
-                        }
-                        let intensity = normal.dot(light_dir).powf(gamma);
-                        // back-face culling
-                        if intensity <= 0. {
-                            return;
-                        }
-
-                        // Texturing
-                        let texture_data = tri.texture.as_ref();
-                        if let Some(texture_data) = texture_data {
-                            let texture = &texture_data.texture;
-                            let texcoords = texture_data.points;
-                            let u =
-                                ((w0 * texcoords[2].x + w1 * texcoords[0].x + w2 * texcoords[1].x)
-                                    * texture_data.width as f32)
-                                    as usize;
-                            let v =
-                                ((w0 * texcoords[2].y + w1 * texcoords[0].y + w2 * texcoords[1].y)
-                                    * texture_data.height as f32)
-                                    as usize;
-
-                            let idx = u * 4 + v * 4 * texture_data.width;
-                            color = Color::new_from_rgb(
-                                texture[idx + 0],
-                                texture[idx + 1],
-                                texture[idx + 2],
-                            );
-                            // Texturing //
-                        }
-                        //tex_color.modify_intensity(intensity);
-                        color.modify_intensity(1.);
-                        // Shading //
-
-                        // TODOS:
-                        // -> learn about rc and lifetimes
-
-                        let zdepth = w0 * tri.position[2].z
-                            + w1 * tri.position[0].z
-                            + w2 * tri.position[1].z;
-
-                        //TODO: fix
-                        /*
-                        if zdepth < 0. || zdepth > 1. {
-                            return;
-                        }
-                        */
-
-                        let coord = self.calculate_coord(x as usize, y as usize);
-                        // +z is towards us, however the cgmath::projection matrix transforms
-                        // visible points into the 0. to 1. region, where smaller numbers are closer
-                        // to the camera
-                        if zdepth < self.zbuffer[coord] {
-                            self.zbuffer[coord] = zdepth;
-                            self.draw_pixel(coord, color.get_pixel_color());
-
-                            // render zbuffer
-                            /*
-                            let zdepth_color = 1. - zdepth; //TODO: map this onto some curve for
-                            //better visibility
-                            self.draw_pixel(
-                                coord,
-                                Color::new(zdepth_color, zdepth_color, zdepth_color)
-                                    .get_pixel_color(),
-                            );
-                            */
-                        }
+// Vertex stage, puts every triangle of the obj's submeshes through the model view transform,
+// skipping the ones outside the frustum if there is one
+fn transform_object<'a>(
+    obj: &'a ObjData,
+    submeshes: &[usize],
+    model_view_matrix: Matrix4<f32>,
+    frustum: Option<&Frustum>,
+    material: Option<MaterialOverride>,
+) -> Vec<TriangleData<'a>> {
+    let material = material.unwrap_or_default();
+    let textures = obj.tri_textures.as_ref().filter(|_| material.textured);
+    // a node scaled to nothing along an axis has no volume, so nothing to draw
+    let Some(normal_matrix) = model_view_matrix.inverse_transform() else {
+        return Vec::new();
+    };
+    let normal_matrix = normal_matrix.transpose();
+    let sphere = frustum.map(|frustum| frustum.test_sphere(&obj.bounding_sphere));
+    let mut triangles = Vec::new();
+    let mut visible = Vec::new();
+    for submesh in submeshes.iter().map(|&i| &obj.submeshes[i]) {
+        visible.clear();
+        match frustum {
+            Some(frustum) => cull_submesh(obj, submesh, sphere.unwrap(), frustum, &mut visible),
+            None => visible.extend(submesh.triangles.start as u32..submesh.triangles.end as u32),
+        }
+        let color = submesh.color.multiply(&material.color);
+        triangles.extend(visible.iter().map(|&i| i as usize).map(|i| TriangleData {
+            position:
+                obj.tri_positions[i].map(|p| {
+                    Point3::<f32>::from_homogeneous(model_view_matrix * p.to_homogeneous())
+                }),
+            texture: textures.map(|textures| &textures[i]),
+            // TODO: make this less ugly
+            normal: obj.tri_normals.as_ref().map(|normals| {
+                normals[i].map(|n| {
+                    let o = normal_matrix * n.extend(0.);
+                    // scaled nodes would change their length and so the lighting
+                    Vector3 {
+                        x: o.x,
+                        y: o.y,
+                        z: o.z,
                     }
+                    .normalize()
+                })
+            }),
+            vertex_colors: obj.tri_colors.as_ref().map(|colors| colors[i]),
+            color,
+        }));
+    }
+    triangles
+}
+
+// Rasterises the part of the triangle within `setup`'s bounds into the target, which has to
+// contain them. Returns what happened to its fragments.
+#[allow(clippy::too_many_arguments)]
+fn fill_triangle(
+    target: &mut SampleTarget,
+    state: &RenderState,
+    sample_count: SampleCount,
+    simd_level: SimdLevel,
+    hierarchical_z: bool,
+    lights: &[Light],
+    tri: &SetupTriangle,
+    setup: &TriangleSetup,
+) -> FragmentCounts {
+    // blocks can only be skipped if nothing happens to fragments failing the depth test, which
+    // isn't the case for the stencil depth fail op
+    let hierarchical_z = hierarchical_z && !state.stencil.enabled;
+
+    // the SIMD path works on whole pixels at a time, so can't do multisampling or stencil
+    if sample_count == SampleCount::X1 && !state.stencil.enabled {
+        return fill_triangle_spans(
+            target,
+            state,
+            simd_level,
+            hierarchical_z,
+            lights,
+            tri,
+            setup,
+        )
+        .with_texture_samples(tri);
+    }
+
+    let samples = sample_count.samples();
+    let (z_min, z_max) = depth_range(tri.position.map(|p| p.z));
+    let mut counts = FragmentCounts::default();
+    // coverage is evaluated for every sample in the pixel...
+    setup.rasterise(
+        sample_count.pattern(),
+        target,
+        |target, x, y| {
+            hierarchical_z
+                && target
+                    .depth_bounds(x, y)
+                    .rejects(state.depth_func, z_min, z_max)
+        },
+        |target, x, y, coverage, sample_weights| {
+            // early z: shading never changes the depth, so only samples that survive the depth
+            // and stencil tests need a color
+            let first_sample = target.sample_index(x, y);
+            let mut passed = 0u32;
+            for (s, [l0, l1, l2]) in sample_weights.iter().take(samples).enumerate() {
+                if coverage & (1 << s) == 0 {
+                    continue;
+                }
+                let zdepth =
+                    l0 * tri.position[0].z + l1 * tri.position[1].z + l2 * tri.position[2].z;
+
+                //TODO: fix
+                /*
+                if zdepth < 0. || zdepth > 1. {
+                    return;
                 }
+                */
+
+                let sample = first_sample + s;
+                if state.depth_stencil_test(
+                    &mut target.depth[sample],
+                    &mut target.stencil[sample],
+                    zdepth,
+                    tri.front_facing,
+                ) {
+                    passed |= 1 << s;
+                }
+            }
+            counts.tested += coverage.count_ones() as usize;
+            counts.passed += passed.count_ones() as usize;
+            if passed != 0 && state.depth_write {
+                target.mark_depth_written(x, y);
+            }
+            if passed == 0 || !state.color_write {
+                return;
+            }
+
+            // ...but shading only happens once, at the first covered sample so that
+            // texture lookups never land outside of the triangle
+            let [l0, l1, l2] = sample_weights[coverage.trailing_zeros() as usize];
+            let color = shade_fragment(
+                tri,
+                lights,
+                || match tri.shading {
+                    TriangleShading::Flat => tri.face_normal,
+                    TriangleShading::Gouraud => {
+                        // why negative?
+                        let n = tri.normal.expect(
+                            "How do you gouraud shade without vertex normals from .obj file?",
+                        );
+                        -(n[0] * l0 + n[1] * l1 + n[2] * l2)
+                    }
+                    _ => panic!("Invalid triangle shading type!"),
+                },
+                |texture_data| {
+                    let texcoords = texture_data.points;
+                    (
+                        (l0 * texcoords[0].x + l1 * texcoords[1].x + l2 * texcoords[2].x)
+                            * texture_data.width as f32,
+                        (l0 * texcoords[0].y + l1 * texcoords[1].y + l2 * texcoords[2].y)
+                            * texture_data.height as f32,
+                    )
+                },
+                |colors| Color::blend(colors, [l0, l1, l2]),
+            );
+            counts.shaded += 1;
+            while passed != 0 {
+                target.color[first_sample + passed.trailing_zeros() as usize] = color;
+                passed &= passed - 1;
             }
+        },
+    );
+    counts.with_texture_samples(tri)
+}
+
+// fill_triangle() for single sampled targets without stencil, working out coverage, depth and the
+// interpolated attributes of up to SPAN_LANES pixels at a time
+fn fill_triangle_spans(
+    target: &mut SampleTarget,
+    state: &RenderState,
+    simd_level: SimdLevel,
+    hierarchical_z: bool,
+    lights: &[Light],
+    tri: &SetupTriangle,
+    setup: &TriangleSetup,
+) -> FragmentCounts {
+    // the whole loop gets compiled for AVX2, otherwise the span functions couldn't be inlined
+    // into it
+    #[cfg(target_arch = "x86_64")]
+    if simd_level == SimdLevel::Avx2 {
+        #[target_feature(enable = "avx2")]
+        unsafe fn fill_triangle_spans_avx2(
+            target: &mut SampleTarget,
+            state: &RenderState,
+            hierarchical_z: bool,
+            lights: &[Light],
+            tri: &SetupTriangle,
+            setup: &TriangleSetup,
+        ) -> FragmentCounts {
+            fill_triangle_spans_with(
+                target,
+                state,
+                SimdLevel::Avx2,
+                hierarchical_z,
+                lights,
+                tri,
+                setup,
+            )
+        }
+        // safe as the level only says AVX2 if the CPU supports it
+        return unsafe {
+            fill_triangle_spans_avx2(target, state, hierarchical_z, lights, tri, setup)
+        };
+    }
+    fill_triangle_spans_with(
+        target,
+        state,
+        simd_level,
+        hierarchical_z,
+        lights,
+        tri,
+        setup,
+    )
+}
+
+#[inline(always)]
+fn fill_triangle_spans_with(
+    target: &mut SampleTarget,
+    state: &RenderState,
+    simd_level: SimdLevel,
+    hierarchical_z: bool,
+    lights: &[Light],
+    tri: &SetupTriangle,
+    setup: &TriangleSetup,
+) -> FragmentCounts {
+    let inv_area = 1. / setup.area as f32;
+    let step = setup.edges.map(|e| e.step_x());
+    let bias = setup.edges.map(|e| e.bias);
+    let z = tri.position.map(|p| p.z);
+    let (z_min, z_max) = depth_range(z);
+    let depth_state = SpanDepthState {
+        func: state.depth_func,
+        write: state.depth_write,
+    };
+    // untextured flat shaded triangles without vertex colors are the same color all over
+    let flat_color = match (tri.shading, tri.texture, tri.vertex_colors) {
+        (TriangleShading::Flat, None, None) => Some(shade_fragment(
+            tri,
+            lights,
+            || tri.face_normal,
+            |_| unreachable!(),
+            |_| unreachable!(),
+        )),
+        _ => None,
+    };
+
+    let occluded = |target: &mut SampleTarget, x, y| {
+        hierarchical_z
+            && target
+                .depth_bounds(x, y)
+                .rejects(state.depth_func, z_min, z_max)
+    };
+    let mut counts = FragmentCounts::default();
+    setup.rasterise_spans(target, occluded, |target, x, y, len, w, inside| {
+        let first = target.sample_index(x, y);
+        let fragments = span_fragments(
+            simd_level,
+            &SpanEdges { w, step, bias },
+            len,
+            inside,
+            inv_area,
+            z,
+            depth_state,
+            &mut target.depth[first..first + len],
+        );
+        counts.tested += fragments.covered.count_ones() as usize;
+        counts.passed += fragments.passed.count_ones() as usize;
+        if fragments.passed != 0 && state.depth_write {
+            target.mark_depth_written(x, y);
+        }
+        if fragments.passed == 0 || !state.color_write {
+            return;
+        }
+
+        let colors = &mut target.color[first..first + len];
+        let mut passed = fragments.passed;
+        counts.shaded += passed.count_ones() as usize;
+        if let Some(color) = flat_color {
+            while passed != 0 {
+                colors[passed.trailing_zeros() as usize] = color;
+                passed &= passed - 1;
+            }
+            return;
+        }
+
+        let weights = &fragments.weights;
+        let texcoords = tri.texture.map(|texture_data| {
+            let t = texture_data.points;
+            (
+                interpolate(simd_level, weights, [t[0].x, t[1].x, t[2].x]),
+                interpolate(simd_level, weights, [t[0].y, t[1].y, t[2].y]),
+            )
+        });
+        let normals = match (tri.shading, tri.normal) {
+            (TriangleShading::Gouraud, Some(n)) if tri.texture.is_none() => Some([
+                interpolate(simd_level, weights, [n[0].x, n[1].x, n[2].x]),
+                interpolate(simd_level, weights, [n[0].y, n[1].y, n[2].y]),
+                interpolate(simd_level, weights, [n[0].z, n[1].z, n[2].z]),
+            ]),
+            _ => None,
+        };
+        while passed != 0 {
+            let i = passed.trailing_zeros() as usize;
+            passed &= passed - 1;
+            colors[i] = shade_fragment(
+                tri,
+                lights,
+                || match (tri.shading, normals) {
+                    (TriangleShading::Flat, _) => tri.face_normal,
+                    // why negative?
+                    (_, Some(n)) => -vec3(n[0][i], n[1][i], n[2][i]),
+                    _ => panic!("How do you gouraud shade without vertex normals from .obj file?"),
+                },
+                |texture_data| {
+                    let (u, v) = texcoords.unwrap();
+                    (
+                        u[i] * texture_data.width as f32,
+                        v[i] * texture_data.height as f32,
+                    )
+                },
+                |colors| Color::blend(colors, [weights[0][i], weights[1][i], weights[2][i]]),
+            );
+        }
+    });
+    counts
+}
+
+// Color of a fragment of the triangle. `normal` only gets called for untextured triangles, and
+// `texel` (the texture coordinates scaled up to the size of the texture) for textured ones.
+// `vertex_color` blends the triangle's vertex colors if it has them.
+#[inline(always)]
+fn shade_fragment(
+    tri: &SetupTriangle,
+    lights: &[Light],
+    normal: impl FnOnce() -> Vector3<f32>,
+    texel: impl FnOnce(&TextureData) -> (f32, f32),
+    vertex_color: impl FnOnce(&[Color; 3]) -> Color,
+) -> u32 {
+    match tri.debug_view {
+        DebugView::Normals => {
+            // flipped to point out of the surface
+            let n = -normal();
+            // degenerate normals, e.g. from zero length ones in the model, come out black
+            if !(n.x.is_finite() && n.y.is_finite() && n.z.is_finite()) {
+                return 0;
+            }
+            let channel = |c: f32| (c * 0.5 + 0.5).clamp(0., 1.);
+            return Color::new(channel(n.x), channel(n.y), channel(n.z)).get_pixel_color();
+        }
+        DebugView::TexCoords => {
+            let Some(texture_data) = tri.texture else {
+                return 0;
+            };
+            let (u, v) = texel(texture_data);
+            // like degenerate normals, coordinates that aren't numbers come out black
+            if !(u.is_finite() && v.is_finite()) {
+                return 0;
+            }
+            let u = (u / texture_data.width as f32).rem_euclid(1.);
+            let v = (v / texture_data.height as f32).rem_euclid(1.);
+            return Color::new(u, v, 0.).get_pixel_color();
+        }
+        _ => {}
+    }
+    let base_color = match &tri.vertex_colors {
+        Some(colors) => vertex_color(colors).multiply(&tri.color),
+        None => tri.color,
+    };
+    let color;
+    if let Some(texture_data) = tri.texture {
+        // Texturing
+        let (u, v) = texel(texture_data);
+        color = texture_data.sample(u, v).multiply(&base_color);
+        // Texturing //
+    } else if !tri.lighting {
+        color = base_color;
+    } else {
+        // Shading
+        // TODO: https://learnopengl.com/Advanced-Lighting/Gamma-Correction
+        let gamma = 2.2;
+        let normal = normal();
+        // faces pointing away from a light are black rather than skipped, culling
+        // is handled by the render state
+        let mut lit = Color::new(0., 0., 0.);
+        for light in lights {
+            let intensity = normal.dot(light.direction).max(0.).powf(gamma) * light.intensity;
+            lit = lit.add_scaled(&light.color.multiply(&base_color), intensity);
         }
+        color = lit;
+        // Shading //
     }
+    color.get_pixel_color()
 }
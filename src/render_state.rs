@@ -0,0 +1,233 @@
+// Fixed function state that controls how triangles are tested and written. Loosely follows the
+// OpenGL/Vulkan naming so the behaviour of each option should be unsurprising.
+
+// Comparison used by the depth test, where `value` is the incoming fragment and `reference` is
+// what is already stored in the buffer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompareFunc {
+    Never,
+    Less,
+    Equal,
+    LessEqual,
+    Greater,
+    NotEqual,
+    GreaterEqual,
+    Always,
+}
+
+impl CompareFunc {
+    #[inline(always)]
+    pub fn test<T: PartialOrd>(self, value: T, reference: T) -> bool {
+        match self {
+            CompareFunc::Never => false,
+            CompareFunc::Less => value < reference,
+            CompareFunc::Equal => value == reference,
+            CompareFunc::LessEqual => value <= reference,
+            CompareFunc::Greater => value > reference,
+            CompareFunc::NotEqual => value != reference,
+            CompareFunc::GreaterEqual => value >= reference,
+            CompareFunc::Always => true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CullMode {
+    None,
+    Back,
+    Front,
+}
+
+// Winding order (in screen space, origin bottom left) that is considered front facing
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrontFace {
+    CounterClockwise,
+    Clockwise,
+}
+
+// What happens to the stored stencil value after the stencil/depth tests
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StencilOp {
+    Keep,
+    Zero,
+    Replace,
+    IncrementClamp,
+    DecrementClamp,
+    Invert,
+    IncrementWrap,
+    DecrementWrap,
+}
+
+impl StencilOp {
+    #[inline(always)]
+    pub fn apply(self, stored: u8, reference: u8) -> u8 {
+        match self {
+            StencilOp::Keep => stored,
+            StencilOp::Zero => 0,
+            StencilOp::Replace => reference,
+            StencilOp::IncrementClamp => stored.saturating_add(1),
+            StencilOp::DecrementClamp => stored.saturating_sub(1),
+            StencilOp::Invert => !stored,
+            StencilOp::IncrementWrap => stored.wrapping_add(1),
+            StencilOp::DecrementWrap => stored.wrapping_sub(1),
+        }
+    }
+}
+
+// Front and back facing triangles get their own ops so shadow volumes can be done in one pass
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StencilFaceState {
+    pub func: CompareFunc,
+    // stencil test failed
+    pub fail_op: StencilOp,
+    // stencil test passed but depth test failed
+    pub depth_fail_op: StencilOp,
+    // both tests passed
+    pub pass_op: StencilOp,
+}
+
+impl Default for StencilFaceState {
+    fn default() -> StencilFaceState {
+        StencilFaceState {
+            func: CompareFunc::Always,
+            fail_op: StencilOp::Keep,
+            depth_fail_op: StencilOp::Keep,
+            pass_op: StencilOp::Keep,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StencilState {
+    pub enabled: bool,
+    pub front: StencilFaceState,
+    pub back: StencilFaceState,
+    pub reference: u8,
+    // applied to both the reference and the stored value before comparing
+    pub read_mask: u8,
+    // bits of the stored value that the ops are allowed to modify
+    pub write_mask: u8,
+}
+
+impl StencilState {
+    pub fn face(&self, front_facing: bool) -> &StencilFaceState {
+        if front_facing {
+            &self.front
+        } else {
+            &self.back
+        }
+    }
+
+    // Like glStencilFunc, the reference is on the left hand side of the comparison
+    #[inline(always)]
+    pub fn test(&self, face: &StencilFaceState, stored: u8) -> bool {
+        face.func
+            .test(self.reference & self.read_mask, stored & self.read_mask)
+    }
+
+    #[inline(always)]
+    pub fn update(&self, op: StencilOp, stored: u8) -> u8 {
+        let value = op.apply(stored, self.reference);
+        (stored & !self.write_mask) | (value & self.write_mask)
+    }
+}
+
+impl Default for StencilState {
+    fn default() -> StencilState {
+        StencilState {
+            enabled: false,
+            front: StencilFaceState::default(),
+            back: StencilFaceState::default(),
+            reference: 0,
+            read_mask: 0xff,
+            write_mask: 0xff,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RenderState {
+    pub depth_func: CompareFunc,
+    pub depth_write: bool,
+    // value the zbuffer is reset to every frame, should be +inf for Less and -inf for Greater
+    pub clear_depth: f32,
+    pub cull_mode: CullMode,
+    pub front_face: FrontFace,
+    pub stencil: StencilState,
+    pub clear_stencil: u8,
+    // disabling this is useful for stencil only passes e.g. shadow volumes
+    pub color_write: bool,
+}
+
+impl RenderState {
+    // `signed_area` is positive for counter-clockwise triangles
+    pub fn is_front_facing(&self, signed_area: f32) -> bool {
+        match self.front_face {
+            FrontFace::CounterClockwise => signed_area > 0.,
+            FrontFace::Clockwise => signed_area < 0.,
+        }
+    }
+
+    pub fn should_cull(&self, signed_area: f32) -> bool {
+        match self.cull_mode {
+            CullMode::None => false,
+            CullMode::Back => !self.is_front_facing(signed_area),
+            CullMode::Front => self.is_front_facing(signed_area),
+        }
+    }
+
+    // Runs the stencil and depth tests for a fragment against the values stored for its sample,
+    // updating both of them. Returns whether the fragment survived and its color should be
+    // written.
+    #[inline(always)]
+    pub fn depth_stencil_test(
+        &self,
+        stored_depth: &mut f32,
+        stored_stencil: &mut u8,
+        zdepth: f32,
+        front_facing: bool,
+    ) -> bool {
+        let stencil = &self.stencil;
+        let face = stencil.face(front_facing);
+
+        if stencil.enabled && !stencil.test(face, *stored_stencil) {
+            *stored_stencil = stencil.update(face.fail_op, *stored_stencil);
+            return false;
+        }
+
+        // +z is towards us, however the cgmath::projection matrix transforms
+        // visible points into the 0. to 1. region, where smaller numbers are closer
+        // to the camera
+        if !self.depth_func.test(zdepth, *stored_depth) {
+            if stencil.enabled {
+                *stored_stencil = stencil.update(face.depth_fail_op, *stored_stencil);
+            }
+            return false;
+        }
+
+        if stencil.enabled {
+            *stored_stencil = stencil.update(face.pass_op, *stored_stencil);
+        }
+        if self.depth_write {
+            *stored_depth = zdepth;
+        }
+        true
+    }
+}
+
+impl Default for RenderState {
+    // obj files define faces counter-clockwise, and cgmath's projection maps closer points to
+    // smaller depth values
+    fn default() -> RenderState {
+        RenderState {
+            depth_func: CompareFunc::Less,
+            depth_write: true,
+            clear_depth: f32::INFINITY,
+            cull_mode: CullMode::Back,
+            front_face: FrontFace::CounterClockwise,
+            stencil: StencilState::default(),
+            clear_stencil: 0,
+            color_write: true,
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::VecDeque;
+use std::io::Write;
+use std::time::Duration;
+
+// Counts and timings of what a frame drew, see Rasteriser::render_frame(). Fragments are samples,
+// so there are several per pixel when multisampling.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RenderStats {
+    // every triangle of the meshes in the scene
+    pub triangles_submitted: usize,
+    // outside the frustum, facing the wrong way, too small to cover a sample or off screen
+    pub triangles_culled: usize,
+    // rasterised triangles that reached past the edges of the screen. They aren't clipped, only
+    // the part inside their bounds on screen gets scanned.
+    pub triangles_partly_offscreen: usize,
+    // made it through culling to be rasterised, or drawn as points or lines
+    pub triangles_rasterised: usize,
+    // covered by a triangle and put through the depth and stencil tests, which leaves out the
+    // ones in blocks hierarchical z skipped
+    pub fragments_tested: usize,
+    pub fragments_passed: usize,
+    // fragments that got a color from a triangle, once per pixel when multisampling
+    pub fragments_shaded: usize,
+    pub texture_samples: usize,
+    // wall time of each stage: transforming and frustum culling the triangles, setting them up
+    // (and binning them into tiles), filling them in (plus points, lines and the wireframe
+    // overlay), and the debug views, resolve, downsampling and fxaa at the end of the frame
+    pub vertex_time: Duration,
+    pub setup_time: Duration,
+    pub raster_time: Duration,
+    pub post_time: Duration,
+}
+
+impl RenderStats {
+    pub const CSV_HEADER: &'static str = "triangles_submitted,triangles_culled,\
+        triangles_partly_offscreen,triangles_rasterised,fragments_tested,fragments_passed,\
+        fragments_shaded,texture_samples,vertex_ms,setup_ms,raster_ms,post_ms,total_ms";
+
+    pub fn total_time(&self) -> Duration {
+        self.vertex_time + self.setup_time + self.raster_time + self.post_time
+    }
+
+    // The fields in CSV_HEADER's order, without a line break
+    pub fn csv_row(&self) -> String {
+        let ms = |time: Duration| format!("{:.3}", time.as_secs_f64() * 1000.);
+        [
+            self.triangles_submitted.to_string(),
+            self.triangles_culled.to_string(),
+            self.triangles_partly_offscreen.to_string(),
+            self.triangles_rasterised.to_string(),
+            self.fragments_tested.to_string(),
+            self.fragments_passed.to_string(),
+            self.fragments_shaded.to_string(),
+            self.texture_samples.to_string(),
+            ms(self.vertex_time),
+            ms(self.setup_time),
+            ms(self.raster_time),
+            ms(self.post_time),
+            ms(self.total_time()),
+        ]
+        .join(",")
+    }
+}
+
+// The stats of the last `capacity` frames, dropping the oldest ones to make room
+pub struct RenderStatsHistory {
+    capacity: usize,
+    frames: VecDeque<RenderStats>,
+    // how many frames were ever added, so frames keep their numbers after older ones drop out
+    added: usize,
+}
+
+impl RenderStatsHistory {
+    pub fn new(capacity: usize) -> RenderStatsHistory {
+        RenderStatsHistory {
+            capacity: capacity.max(1),
+            frames: VecDeque::with_capacity(capacity.max(1)),
+            added: 0,
+        }
+    }
+
+    pub fn push(&mut self, stats: RenderStats) {
+        if self.frames.len() == self.capacity {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(stats);
+        self.added += 1;
+    }
+
+    // Oldest first, with the number of the frame counting from 0 for the first one ever added
+    pub fn frames(&self) -> impl Iterator<Item = (usize, &RenderStats)> {
+        let first = self.added - self.frames.len();
+        self.frames
+            .iter()
+            .enumerate()
+            .map(move |(i, stats)| (first + i, stats))
+    }
+
+    pub fn write_csv(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        writeln!(writer, "frame,{}", RenderStats::CSV_HEADER)?;
+        for (frame, stats) in self.frames() {
+            writeln!(writer, "{},{}", frame, stats.csv_row())?;
+        }
+        Ok(())
+    }
+
+    pub fn save_csv(&self, path: &str) -> std::io::Result<()> {
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(path)?);
+        self.write_csv(&mut writer)?;
+        writer.flush()
+    }
+}
@@ -0,0 +1,252 @@
+use crate::load_collada;
+use crate::load_fbx;
+use crate::load_gltf;
+use crate::load_ply;
+use crate::load_stl;
+use crate::Aabb;
+use crate::Camera;
+use crate::Color;
+use crate::LoadError;
+use crate::ObjData;
+use cgmath::vec3;
+use cgmath::InnerSpace;
+use cgmath::Matrix4;
+use cgmath::SquareMatrix;
+use cgmath::Transform;
+use cgmath::Vector3;
+use std::collections::HashMap;
+use std::path::Path;
+
+// Index of a node in Scene::nodes
+pub type NodeId = usize;
+
+// Which triangles of one of the scene's meshes a node draws
+#[derive(Clone, Debug)]
+pub struct MeshRef {
+    // index into Scene::meshes
+    pub mesh: usize,
+    // indices into the mesh's submeshes
+    pub submeshes: Vec<usize>,
+}
+
+// Directional light
+#[derive(Clone, Copy)]
+pub struct Light {
+    // the way the light travels, in the space of the node it's attached to
+    pub direction: Vector3<f32>,
+    pub color: Color,
+    pub intensity: f32,
+}
+
+impl Default for Light {
+    // White light shining down the node's -z, so one attached to the camera lights whatever it's
+    // looking at head on
+    fn default() -> Light {
+        Light {
+            direction: vec3(0., 0., -1.),
+            color: Color::new(1., 1., 1.),
+            intensity: 1.,
+        }
+    }
+}
+
+// Replaces parts of the material a node's mesh was loaded with
+#[derive(Clone, Copy)]
+pub struct MaterialOverride {
+    // tints the surface, textured or not
+    pub color: Color,
+    // false draws the mesh untextured
+    pub textured: bool,
+}
+
+impl Default for MaterialOverride {
+    fn default() -> MaterialOverride {
+        MaterialOverride {
+            color: Color::new(1., 1., 1.),
+            textured: true,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Node {
+    pub name: String,
+    // relative to the parent, or the world for root nodes
+    pub transform: Matrix4<f32>,
+    pub parent: Option<NodeId>,
+    pub children: Vec<NodeId>,
+    pub mesh: Option<MeshRef>,
+    pub light: Option<Light>,
+    pub camera: Option<Camera>,
+    // only applies to this node's mesh, see Scene::set_material() for whole subtrees
+    pub material: Option<MaterialOverride>,
+    // node space to world space, as of the last Scene::update_world_matrices()
+    pub world_matrix: Matrix4<f32>,
+}
+
+// A hierarchy of nodes along with the meshes they draw. Parents always come before their children
+// in nodes, so world matrices can be worked out in a single pass.
+#[derive(Clone, Default)]
+pub struct Scene {
+    pub nodes: Vec<Node>,
+    pub meshes: Vec<ObjData>,
+}
+
+impl Scene {
+    pub fn new() -> Scene {
+        Scene::default()
+    }
+
+    pub fn add_node(
+        &mut self,
+        name: &str,
+        parent: Option<NodeId>,
+        transform: Matrix4<f32>,
+    ) -> NodeId {
+        let id = self.nodes.len();
+        let world_matrix = match parent {
+            Some(parent) => {
+                self.nodes[parent].children.push(id);
+                self.nodes[parent].world_matrix * transform
+            }
+            None => transform,
+        };
+        self.nodes.push(Node {
+            name: name.to_string(),
+            transform,
+            parent,
+            children: Vec::new(),
+            mesh: None,
+            light: None,
+            camera: None,
+            material: None,
+            world_matrix,
+        });
+        id
+    }
+
+    // Adds the model under a node named name, with a child node for each of its o/g groups
+    pub fn add_obj(&mut self, obj: ObjData, name: &str, parent: Option<NodeId>) -> NodeId {
+        let mesh = self.meshes.len();
+        let root = self.add_node(name, parent, Matrix4::identity());
+        // groups can be split by material changes or opened again later on
+        let mut groups: HashMap<&str, NodeId> = HashMap::new();
+        for (i, submesh) in obj.submeshes.iter().enumerate() {
+            let group = *groups.entry(&submesh.name).or_insert_with(|| {
+                let group = self.add_node(&submesh.name, Some(root), Matrix4::identity());
+                self.nodes[group].mesh = Some(MeshRef {
+                    mesh,
+                    submeshes: Vec::new(),
+                });
+                group
+            });
+            self.nodes[group].mesh.as_mut().unwrap().submeshes.push(i);
+        }
+        self.meshes.push(obj);
+        root
+    }
+
+    // Loads a .obj, .gltf, .glb, .dae, .fbx, .stl or .ply model into the scene, under a node named
+    // after the file
+    pub fn load_model(&mut self, path: &str, parent: Option<NodeId>) -> Result<NodeId, LoadError> {
+        let file = Path::new(path);
+        let name = file
+            .file_stem()
+            .map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+        let extension = file.extension().and_then(|e| e.to_str()).unwrap_or("");
+        match extension.to_ascii_lowercase().as_str() {
+            "obj" => Ok(self.add_obj(ObjData::load(path)?, &name, parent)),
+            "gltf" | "glb" => load_gltf(self, path, parent),
+            "dae" => load_collada(self, path, parent),
+            "fbx" => load_fbx(self, path, parent),
+            "stl" => Ok(self.add_obj(load_stl(path)?, &name, parent)),
+            "ply" => Ok(self.add_obj(load_ply(path)?, &name, parent)),
+            _ => Err(LoadError::invalid(
+                file,
+                format!("unsupported model format \"{}\"", extension),
+            )),
+        }
+    }
+
+    // The first node called name
+    pub fn find(&self, name: &str) -> Option<NodeId> {
+        self.nodes.iter().position(|node| node.name == name)
+    }
+
+    // The node and everything below it, parents before their children
+    pub fn descendants(&self, id: NodeId) -> Vec<NodeId> {
+        let mut descendants = vec![id];
+        let mut i = 0;
+        while i < descendants.len() {
+            descendants.extend(&self.nodes[descendants[i]].children);
+            i += 1;
+        }
+        descendants
+    }
+
+    // Overrides the material of the node and everything below it
+    pub fn set_material(&mut self, id: NodeId, material: Option<MaterialOverride>) {
+        self.nodes[id].material = material;
+        for child in self.nodes[id].children.clone() {
+            self.set_material(child, material);
+        }
+    }
+
+    // Recalculates every node's world_matrix, has to be called after changing transforms
+    pub fn update_world_matrices(&mut self) {
+        for id in 0..self.nodes.len() {
+            let transform = self.nodes[id].transform;
+            self.nodes[id].world_matrix = match self.nodes[id].parent {
+                Some(parent) => self.nodes[parent].world_matrix * transform,
+                None => transform,
+            };
+        }
+    }
+
+    // Nodes that draw something, with the mesh they draw
+    pub fn mesh_nodes(&self) -> impl Iterator<Item = (&Node, &MeshRef)> {
+        self.nodes
+            .iter()
+            .filter_map(|node| node.mesh.as_ref().map(|mesh| (node, mesh)))
+    }
+
+    // Everything the scene draws, in world space, as of the last Scene::update_world_matrices()
+    pub fn bounds(&self) -> Aabb {
+        self.mesh_nodes()
+            .flat_map(|(node, mesh)| {
+                mesh.submeshes.iter().map(|&submesh| {
+                    self.meshes[mesh.mesh].submeshes[submesh]
+                        .bounds
+                        .transform(&node.world_matrix)
+                })
+            })
+            .fold(Aabb::empty(), |bounds, submesh| bounds.union(&submesh))
+    }
+
+    // Every light in the scene, with its direction in world space
+    pub fn world_lights(&self) -> Vec<Light> {
+        self.nodes
+            .iter()
+            .filter_map(|node| {
+                node.light.map(|light| Light {
+                    direction: node
+                        .world_matrix
+                        .transform_vector(light.direction)
+                        .normalize(),
+                    ..light
+                })
+            })
+            .collect()
+    }
+
+    // The camera attached to the node, moved into world space
+    pub fn world_camera(&self, id: NodeId) -> Option<Camera> {
+        let node = &self.nodes[id];
+        node.camera.map(|camera| Camera {
+            position: node.world_matrix.transform_point(camera.position),
+            target: node.world_matrix.transform_point(camera.target),
+            up: node.world_matrix.transform_vector(camera.up),
+            ..camera
+        })
+    }
+}
@@ -0,0 +1,535 @@
+use crate::load_png;
+use crate::Camera;
+use crate::Color;
+use crate::CullMode;
+use crate::DownsampleFilter;
+use crate::Light;
+use crate::MaterialOverride;
+use crate::NodeId;
+use crate::ObjData;
+use crate::Rasteriser;
+use crate::SampleCount;
+use crate::Sampler;
+use crate::TextureData;
+use crate::TriangleShading;
+use cgmath::point3;
+use cgmath::vec3;
+use cgmath::Deg;
+use cgmath::InnerSpace;
+use cgmath::Matrix4;
+use cgmath::SquareMatrix;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use toml::Spanned;
+
+// Scene files are TOML, describing the render settings, camera, lights and models to load:
+//
+//     [render]
+//     width = 800
+//     height = 600
+//     shading = "gouraud"        # points, wireframe, flat or gouraud
+//     samples = 4                # MSAA samples per pixel: 1, 2, 4 or 8
+//     supersampling = 2          # rendered at 2x the resolution and scaled down
+//     filter = "lanczos"         # box or lanczos, for scaling down
+//     fxaa = true
+//     cull = "back"              # none, back or front
+//     threads = 4                # defaults to one per core
+//     hierarchical_z = true
+//     frustum_culling = true
+//
+//     [camera]
+//     position = [0, 1, 3]
+//     target = [0, 0, 0]
+//     up = [0, 1, 0]
+//     fov = 60                   # vertical, in degrees
+//     near = 0.1
+//     far = 100
+//
+//     [[light]]                  # directional, any number of them
+//     direction = [0, -1, -1]    # the way the light travels
+//     color = [1, 0.9, 0.8]
+//     intensity = 0.8
+//
+//     [turntable]                # spins the models around the y axis while the lights stay put
+//     angle = 0                  # degrees to start at
+//     speed = 1                  # degrees per frame, 0 holds the models still
+//
+//     [[model]]
+//     path = "../models/teapot.obj"  # relative to the scene file, .obj, .gltf, .glb, .dae, .fbx,
+//                                     # .stl or .ply
+//     name = "teapot"            # defaults to the file name
+//     parent = "table"           # name of a model earlier in the file
+//     translation = [0, 0, -3]
+//     rotation = [0, 45, 0]      # degrees around x, then y, then z
+//     scale = 0.5                # or [x, y, z]
+//     material = { color = [1, 0.5, 0.5], texture = "red.png", textured = true }
+//
+// Everything apart from a model's path is optional.
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDescription {
+    #[serde(default)]
+    render: RenderDescription,
+    camera: Option<CameraDescription>,
+    #[serde(default, rename = "light")]
+    lights: Vec<LightDescription>,
+    turntable: Option<TurntableDescription>,
+    #[serde(default, rename = "model")]
+    models: Vec<ModelDescription>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RenderDescription {
+    width: Option<Spanned<usize>>,
+    height: Option<Spanned<usize>>,
+    shading: Option<Shading>,
+    samples: Option<Spanned<usize>>,
+    supersampling: Option<Spanned<usize>>,
+    filter: Option<Filter>,
+    fxaa: Option<bool>,
+    cull: Option<Cull>,
+    threads: Option<usize>,
+    hierarchical_z: Option<bool>,
+    frustum_culling: Option<bool>,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum Shading {
+    Points,
+    Wireframe,
+    Flat,
+    Gouraud,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum Filter {
+    Box,
+    Lanczos,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum Cull {
+    None,
+    Back,
+    Front,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDescription {
+    position: Option<Spanned<[f32; 3]>>,
+    target: Option<Spanned<[f32; 3]>>,
+    up: Option<Spanned<[f32; 3]>>,
+    fov: Option<Spanned<f32>>,
+    near: Option<Spanned<f32>>,
+    far: Option<Spanned<f32>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LightDescription {
+    direction: Option<Spanned<[f32; 3]>>,
+    color: Option<Spanned<[f32; 3]>>,
+    intensity: Option<Spanned<f32>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TurntableDescription {
+    angle: Option<f32>,
+    speed: Option<f32>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ModelDescription {
+    path: Spanned<String>,
+    name: Option<String>,
+    parent: Option<Spanned<String>>,
+    translation: Option<[f32; 3]>,
+    rotation: Option<[f32; 3]>,
+    scale: Option<Spanned<Scale>>,
+    material: Option<MaterialDescription>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Scale {
+    Uniform(f32),
+    PerAxis([f32; 3]),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MaterialDescription {
+    color: Option<Spanned<[f32; 3]>>,
+    texture: Option<Spanned<String>>,
+    textured: Option<bool>,
+}
+
+#[derive(Debug)]
+pub enum SceneFileError {
+    // the scene file itself couldn't be read
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    // anything wrong with the file's contents, including the files it refers to, with the line
+    // and column (both from 1) of the offending value
+    Invalid {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for SceneFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneFileError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            SceneFileError::Invalid {
+                path,
+                line,
+                column,
+                message,
+            } => write!(f, "{}:{}:{}: {}", path.display(), line, column, message),
+        }
+    }
+}
+
+impl std::error::Error for SceneFileError {}
+
+// The file being loaded, to point errors at
+struct SceneSource<'a> {
+    path: &'a Path,
+    text: String,
+}
+
+impl SceneSource<'_> {
+    fn error(&self, span: Range<usize>, message: impl Into<String>) -> SceneFileError {
+        let before = &self.text[..span.start.min(self.text.len())];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        SceneFileError::Invalid {
+            path: self.path.to_path_buf(),
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            message: message.into(),
+        }
+    }
+
+    fn color(&self, color: &Spanned<[f32; 3]>) -> Result<Color, SceneFileError> {
+        let [r, g, b] = *color.get_ref();
+        if ![r, g, b].iter().all(|c| (0. ..=1.).contains(c)) {
+            return Err(self.error(color.span(), "color components have to be between 0 and 1"));
+        }
+        Ok(Color::new(r, g, b))
+    }
+
+    // Paths in the file are relative to the file
+    fn resolve(&self, path: &str) -> PathBuf {
+        self.path.parent().unwrap_or(Path::new("")).join(path)
+    }
+}
+
+// Builds a rasteriser with the scene, settings and camera described in the file
+pub fn load_scene_file(path: &str) -> Result<Rasteriser, SceneFileError> {
+    let path = Path::new(path);
+    let text = std::fs::read_to_string(path).map_err(|error| SceneFileError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    let source = SceneSource { path, text };
+    let scene: SceneDescription = toml::from_str(&source.text)
+        .map_err(|e| source.error(e.span().unwrap_or(0..0), e.message()))?;
+
+    let render = &scene.render;
+    let positive = |value: &Option<Spanned<usize>>, name: &str, default: usize| match value {
+        Some(value) if *value.get_ref() == 0 => {
+            Err(source.error(value.span(), format!("{} has to be at least 1", name)))
+        }
+        Some(value) => Ok(*value.get_ref()),
+        None => Ok(default),
+    };
+    let width = positive(&render.width, "width", 1000)?;
+    let height = positive(&render.height, "height", 1000)?;
+    let supersampling = positive(&render.supersampling, "supersampling", 1)?;
+    let mut r = Rasteriser::new(width, height);
+
+    if let Some(shading) = render.shading {
+        r.shading = match shading {
+            Shading::Points => TriangleShading::Points,
+            Shading::Wireframe => TriangleShading::Wireframe,
+            Shading::Flat => TriangleShading::Flat,
+            Shading::Gouraud => TriangleShading::Gouraud,
+        };
+    }
+    if let Some(samples) = &render.samples {
+        r.set_sample_count(match samples.get_ref() {
+            1 => SampleCount::X1,
+            2 => SampleCount::X2,
+            4 => SampleCount::X4,
+            8 => SampleCount::X8,
+            _ => return Err(source.error(samples.span(), "samples has to be 1, 2, 4 or 8")),
+        });
+    }
+    let filter = match render.filter {
+        Some(Filter::Lanczos) => DownsampleFilter::Lanczos,
+        Some(Filter::Box) | None => DownsampleFilter::Box,
+    };
+    r.set_supersampling(supersampling, filter);
+    if let Some(fxaa) = render.fxaa {
+        r.fxaa = fxaa;
+    }
+    if let Some(cull) = render.cull {
+        r.render_state.cull_mode = match cull {
+            Cull::None => CullMode::None,
+            Cull::Back => CullMode::Back,
+            Cull::Front => CullMode::Front,
+        };
+    }
+    if let Some(threads) = render.threads {
+        r.set_threads(threads);
+    }
+    if let Some(hierarchical_z) = render.hierarchical_z {
+        r.hierarchical_z = hierarchical_z;
+    }
+    if let Some(frustum_culling) = render.frustum_culling {
+        r.frustum_culling = frustum_culling;
+    }
+
+    if let Some(camera) = &scene.camera {
+        r.camera = load_camera(&source, camera)?;
+    }
+
+    for light in &scene.lights {
+        let mut node_light = Light::default();
+        if let Some(direction) = &light.direction {
+            let [x, y, z] = *direction.get_ref();
+            if x == 0. && y == 0. && z == 0. {
+                return Err(source.error(direction.span(), "light direction can't be zero"));
+            }
+            node_light.direction = vec3(x, y, z).normalize();
+        }
+        if let Some(color) = &light.color {
+            node_light.color = source.color(color)?;
+        }
+        if let Some(intensity) = &light.intensity {
+            if *intensity.get_ref() < 0. {
+                return Err(source.error(intensity.span(), "intensity can't be negative"));
+            }
+            node_light.intensity = *intensity.get_ref();
+        }
+        let node = r.scene.add_node("light", None, Matrix4::identity());
+        r.scene.nodes[node].light = Some(node_light);
+    }
+
+    if let Some(turntable) = &scene.turntable {
+        if let Some(angle) = turntable.angle {
+            r.turntable.angle = Deg(angle);
+        }
+        if let Some(speed) = turntable.speed {
+            r.turntable.speed = Deg(speed);
+        }
+    }
+
+    let mut names: HashMap<String, NodeId> = HashMap::new();
+    for model in &scene.models {
+        let node = load_model(&source, &mut r, model, &names)?;
+        names.insert(r.scene.nodes[node].name.clone(), node);
+    }
+    r.scene.update_world_matrices();
+    Ok(r)
+}
+
+fn load_camera(
+    source: &SceneSource,
+    description: &CameraDescription,
+) -> Result<Camera, SceneFileError> {
+    let mut camera = Camera::default();
+    if let Some(position) = &description.position {
+        let [x, y, z] = *position.get_ref();
+        camera.position = point3(x, y, z);
+    }
+    if let Some(target) = &description.target {
+        let [x, y, z] = *target.get_ref();
+        camera.target = point3(x, y, z);
+    }
+    if camera.position == camera.target {
+        let span = (description.target.as_ref())
+            .or(description.position.as_ref())
+            .map_or(0..0, |p| p.span());
+        return Err(source.error(span, "camera position and target can't be the same"));
+    }
+    if let Some(up) = &description.up {
+        let [x, y, z] = *up.get_ref();
+        camera.up = vec3(x, y, z);
+        if camera
+            .up
+            .cross(camera.target - camera.position)
+            .magnitude2()
+            == 0.
+        {
+            return Err(source.error(up.span(), "up can't be zero or along the view direction"));
+        }
+    }
+    if let Some(fov) = &description.fov {
+        if !(*fov.get_ref() > 0. && *fov.get_ref() < 180.) {
+            return Err(source.error(fov.span(), "fov has to be between 0 and 180 degrees"));
+        }
+        camera.fov = Deg(*fov.get_ref());
+    }
+    if let Some(near) = &description.near {
+        if *near.get_ref() <= 0. {
+            return Err(source.error(near.span(), "near has to be greater than 0"));
+        }
+        camera.near = *near.get_ref();
+    }
+    if let Some(far) = &description.far {
+        camera.far = *far.get_ref();
+    }
+    if camera.far <= camera.near {
+        let span = description.far.as_ref().map_or(0..0, |far| far.span());
+        return Err(source.error(span, "far has to be greater than near"));
+    }
+    Ok(camera)
+}
+
+// Adds the model to the rasteriser's scene, returning its node
+fn load_model(
+    source: &SceneSource,
+    r: &mut Rasteriser,
+    model: &ModelDescription,
+    names: &HashMap<String, NodeId>,
+) -> Result<NodeId, SceneFileError> {
+    let path = source.resolve(model.path.get_ref());
+    if !path.is_file() {
+        return Err(source.error(
+            model.path.span(),
+            format!("{} doesn't exist", path.display()),
+        ));
+    }
+    let parent = match &model.parent {
+        Some(parent) => Some(*names.get(parent.get_ref()).ok_or_else(|| {
+            source.error(
+                parent.span(),
+                format!("no model called \"{}\" before this one", parent.get_ref()),
+            )
+        })?),
+        None => None,
+    };
+    let name = match &model.name {
+        Some(name) => name.clone(),
+        None => path
+            .file_stem()
+            .map_or(String::new(), |stem| stem.to_string_lossy().into_owned()),
+    };
+    let node = r
+        .scene
+        .load_model(path.to_str().unwrap(), parent)
+        .map_err(|e| source.error(model.path.span(), e.to_string()))?;
+    r.scene.nodes[node].name = name;
+
+    let mut material = None;
+    if let Some(description) = &model.material {
+        let mut node_material = MaterialOverride::default();
+        if let Some(color) = &description.color {
+            node_material.color = source.color(color)?;
+        }
+        if let Some(textured) = description.textured {
+            node_material.textured = textured;
+        }
+        if let Some(texture) = &description.texture {
+            let (pixels, width, height) = load_png(&source.resolve(texture.get_ref()))
+                .map_err(|e| source.error(texture.span(), e))?;
+            // the meshes get copied so anything else using them keeps its own texture
+            let mut copies: HashMap<usize, usize> = HashMap::new();
+            for id in r.scene.descendants(node) {
+                let Some(mesh) = r.scene.nodes[id].mesh.as_ref().map(|mesh| mesh.mesh) else {
+                    continue;
+                };
+                let copy = match copies.get(&mesh) {
+                    Some(&copy) => copy,
+                    None => {
+                        let obj = &r.scene.meshes[mesh];
+                        // parts without texture coordinates stay untextured
+                        let Some(tri_texcoords) = &obj.tri_texcoords else {
+                            continue;
+                        };
+                        let tri_textures = tri_texcoords
+                            .iter()
+                            .enumerate()
+                            .map(|(i, &points)| TextureData {
+                                // keeps the wrapping and filtering the model asked for
+                                sampler: obj
+                                    .tri_textures
+                                    .as_ref()
+                                    .map_or(Sampler::default(), |textures| textures[i].sampler),
+                                ..TextureData::new(pixels.clone(), width, height, points)
+                            })
+                            .collect();
+                        let obj = ObjData {
+                            tri_textures: Some(tri_textures),
+                            textures: vec![pixels.clone()],
+                            ..obj.clone()
+                        };
+                        r.scene.meshes.push(obj);
+                        copies.insert(mesh, r.scene.meshes.len() - 1);
+                        r.scene.meshes.len() - 1
+                    }
+                };
+                r.scene.nodes[id].mesh.as_mut().unwrap().mesh = copy;
+            }
+            let textured = !copies.is_empty();
+            if !textured {
+                return Err(source.error(
+                    texture.span(),
+                    "the model has no texture coordinates to put a texture on",
+                ));
+            }
+        }
+        material = Some(node_material);
+    }
+
+    let [tx, ty, tz] = model.translation.unwrap_or([0.; 3]);
+    let [rx, ry, rz] = model.rotation.unwrap_or([0.; 3]);
+    let scale = match model
+        .scale
+        .as_ref()
+        .map(|scale| (scale.get_ref(), scale.span()))
+    {
+        Some((Scale::Uniform(s), span)) => check_scale(source, [*s; 3], span)?,
+        Some((Scale::PerAxis(s), span)) => check_scale(source, *s, span)?,
+        None => [1.; 3],
+    };
+    let transform = Matrix4::from_translation(vec3(tx, ty, tz))
+        * Matrix4::from_angle_z(Deg(rz))
+        * Matrix4::from_angle_y(Deg(ry))
+        * Matrix4::from_angle_x(Deg(rx))
+        * Matrix4::from_nonuniform_scale(scale[0], scale[1], scale[2]);
+
+    // on top of any unit or up axis conversion the loader gave the model
+    let root = &mut r.scene.nodes[node];
+    root.transform = transform * root.transform;
+    r.scene.set_material(node, material);
+    Ok(node)
+}
+
+fn check_scale(
+    source: &SceneSource,
+    scale: [f32; 3],
+    span: Range<usize>,
+) -> Result<[f32; 3], SceneFileError> {
+    if scale.contains(&0.) {
+        return Err(source.error(span, "scale can't be zero"));
+    }
+    Ok(scale)
+}
@@ -0,0 +1,376 @@
+use crate::CompareFunc;
+
+// SIMD versions of the per pixel maths for single sampled triangles, which work on a span of up
+// to SPAN_LANES pixels of a row at a time. AVX2 or SSE2 gets picked at runtime, and the scalar
+// fallback does the exact same operations in the exact same order, so every path gives bit
+// identical images.
+pub const SPAN_LANES: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SimdLevel {
+    Scalar,
+    Sse2,
+    Avx2,
+}
+
+impl SimdLevel {
+    // The best instruction set the CPU we are running on supports
+    pub fn detect() -> SimdLevel {
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("avx2") {
+                return SimdLevel::Avx2;
+            }
+            // always there on x86_64
+            SimdLevel::Sse2
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        SimdLevel::Scalar
+    }
+}
+
+// Edge functions along a span, with their values at the centre of its first pixel and how much
+// they change from one pixel to the next
+#[derive(Clone, Copy, Debug)]
+pub struct SpanEdges {
+    pub w: [i64; 3],
+    pub step: [i64; 3],
+    pub bias: [i64; 3],
+}
+
+impl SpanEdges {
+    // The SIMD paths hold edge values as f64, which represents them exactly (converting those to
+    // f32 then rounds the same way as `as f32` does) unless a vertex is miles off screen. The
+    // values are linear along the span so checking both ends is enough.
+    fn fit_f64(&self, len: usize) -> bool {
+        const MAX: i64 = 1 << f64::MANTISSA_DIGITS;
+        let fits = |v: i64| v.abs() < MAX;
+        (0..3).all(|k| {
+            let last = self.w[k] + self.step[k] * (len as i64 - 1);
+            fits(self.w[k]) && fits(last)
+        })
+    }
+}
+
+// The depth test part of the render state, since the SIMD paths don't handle stencil
+#[derive(Clone, Copy, Debug)]
+pub struct SpanDepthState {
+    pub func: CompareFunc,
+    pub write: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SpanFragments {
+    // bit i is set if pixel i of the span is inside the triangle
+    pub covered: u32,
+    // bit i is set if pixel i was covered and passed the depth test
+    pub passed: u32,
+    // barycentric weights of each vertex for every pixel in the span
+    pub weights: [[f32; SPAN_LANES]; 3],
+}
+
+// Works out coverage, barycentrics and depth for the first `len` pixels of a span, depth testing
+// them against (and updating) `depth`, which holds the stored depth of those pixels. `inside`
+// skips the edge tests for spans known to be completely covered.
+#[allow(clippy::too_many_arguments)]
+#[inline(always)]
+pub fn span_fragments(
+    level: SimdLevel,
+    edges: &SpanEdges,
+    len: usize,
+    inside: bool,
+    inv_area: f32,
+    z: [f32; 3],
+    depth_state: SpanDepthState,
+    depth: &mut [f32],
+) -> SpanFragments {
+    debug_assert!(len <= SPAN_LANES && depth.len() >= len);
+    #[cfg(target_arch = "x86_64")]
+    if level != SimdLevel::Scalar && edges.fit_f64(len) {
+        // safe as the level only says AVX2 if the CPU supports it, and SSE2 is always there
+        unsafe {
+            return match level {
+                SimdLevel::Avx2 => {
+                    x86::span_fragments_avx2(edges, len, inside, inv_area, z, depth_state, depth)
+                }
+                _ => x86::span_fragments_sse2(edges, len, inside, inv_area, z, depth_state, depth),
+            };
+        }
+    }
+    let _ = level;
+    span_fragments_scalar(edges, len, inside, inv_area, z, depth_state, depth)
+}
+
+// a[0] * weights[0] + a[1] * weights[1] + a[2] * weights[2] for every pixel in the span
+#[inline(always)]
+pub fn interpolate(
+    level: SimdLevel,
+    weights: &[[f32; SPAN_LANES]; 3],
+    a: [f32; 3],
+) -> [f32; SPAN_LANES] {
+    #[cfg(target_arch = "x86_64")]
+    if level != SimdLevel::Scalar {
+        unsafe {
+            return match level {
+                SimdLevel::Avx2 => x86::interpolate_avx2(weights, a),
+                _ => x86::interpolate_sse2(weights, a),
+            };
+        }
+    }
+    let _ = level;
+    let mut out = [0.; SPAN_LANES];
+    for (i, o) in out.iter_mut().enumerate() {
+        *o = weights[0][i] * a[0] + weights[1][i] * a[1] + weights[2][i] * a[2];
+    }
+    out
+}
+
+fn span_fragments_scalar(
+    edges: &SpanEdges,
+    len: usize,
+    inside: bool,
+    inv_area: f32,
+    z: [f32; 3],
+    depth_state: SpanDepthState,
+    depth: &mut [f32],
+) -> SpanFragments {
+    let mut fragments = SpanFragments::default();
+    let mut w = edges.w;
+    for (i, stored) in depth.iter_mut().enumerate().take(len) {
+        if inside || (0..3).all(|k| w[k] + edges.bias[k] >= 0) {
+            fragments.covered |= 1 << i;
+            let l = w.map(|w| w as f32 * inv_area);
+            for (weights, l) in fragments.weights.iter_mut().zip(l) {
+                weights[i] = l;
+            }
+            let zdepth = l[0] * z[0] + l[1] * z[1] + l[2] * z[2];
+            if depth_state.func.test(zdepth, *stored) {
+                fragments.passed |= 1 << i;
+                if depth_state.write {
+                    *stored = zdepth;
+                }
+            }
+        }
+        w = [
+            w[0] + edges.step[0],
+            w[1] + edges.step[1],
+            w[2] + edges.step[2],
+        ];
+    }
+    fragments
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use super::*;
+    use std::arch::x86_64::*;
+
+    // These get inlined into callers compiled for the same instruction set, see
+    // fill_triangle_spans()
+
+    // Coverage mask of four pixels of the span starting at `first`, along with their edge values
+    // converted to f32
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn edge_lanes_avx2(edges: &SpanEdges, k: usize, first: f64) -> (__m256d, __m128) {
+        let lane_index = _mm256_setr_pd(first, first + 1., first + 2., first + 3.);
+        // the products and sums are whole numbers below 2^53, so exact
+        let w = _mm256_add_pd(
+            _mm256_set1_pd(edges.w[k] as f64),
+            _mm256_mul_pd(lane_index, _mm256_set1_pd(edges.step[k] as f64)),
+        );
+        // w + bias >= 0
+        let covered = _mm256_cmp_pd::<_CMP_GE_OQ>(w, _mm256_set1_pd(-edges.bias[k] as f64));
+        (covered, _mm256_cvtpd_ps(w))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn span_fragments_avx2(
+        edges: &SpanEdges,
+        len: usize,
+        inside: bool,
+        inv_area: f32,
+        z: [f32; 3],
+        depth_state: SpanDepthState,
+        depth: &mut [f32],
+    ) -> SpanFragments {
+        let mut fragments = SpanFragments::default();
+        let mut covered_bits = (1 << len) - 1;
+        let inv_area = _mm256_set1_ps(inv_area);
+        let mut l = [_mm256_setzero_ps(); 3];
+        for (k, l) in l.iter_mut().enumerate() {
+            let (covered_lo, w_lo) = edge_lanes_avx2(edges, k, 0.);
+            let (covered_hi, w_hi) = edge_lanes_avx2(edges, k, 4.);
+            if !inside {
+                covered_bits &=
+                    (_mm256_movemask_pd(covered_lo) | _mm256_movemask_pd(covered_hi) << 4) as u32;
+            }
+            *l = _mm256_mul_ps(_mm256_set_m128(w_hi, w_lo), inv_area);
+            _mm256_storeu_ps(fragments.weights[k].as_mut_ptr(), *l);
+        }
+        fragments.covered = covered_bits;
+        if covered_bits == 0 {
+            return fragments;
+        }
+        // back from bits to a mask of whole lanes
+        let lane_bits = _mm256_setr_epi32(1, 2, 4, 8, 16, 32, 64, 128);
+        let covered = _mm256_cmpeq_epi32(
+            _mm256_and_si256(_mm256_set1_epi32(covered_bits as i32), lane_bits),
+            lane_bits,
+        );
+
+        let zdepth = _mm256_add_ps(
+            _mm256_add_ps(
+                _mm256_mul_ps(l[0], _mm256_set1_ps(z[0])),
+                _mm256_mul_ps(l[1], _mm256_set1_ps(z[1])),
+            ),
+            _mm256_mul_ps(l[2], _mm256_set1_ps(z[2])),
+        );
+        // masked so nothing past the end of the span gets touched
+        let stored = _mm256_maskload_ps(depth.as_ptr(), covered);
+        let test = match depth_state.func {
+            CompareFunc::Never => _mm256_setzero_ps(),
+            CompareFunc::Less => _mm256_cmp_ps::<_CMP_LT_OQ>(zdepth, stored),
+            CompareFunc::Equal => _mm256_cmp_ps::<_CMP_EQ_OQ>(zdepth, stored),
+            CompareFunc::LessEqual => _mm256_cmp_ps::<_CMP_LE_OQ>(zdepth, stored),
+            CompareFunc::Greater => _mm256_cmp_ps::<_CMP_GT_OQ>(zdepth, stored),
+            CompareFunc::NotEqual => _mm256_cmp_ps::<_CMP_NEQ_UQ>(zdepth, stored),
+            CompareFunc::GreaterEqual => _mm256_cmp_ps::<_CMP_GE_OQ>(zdepth, stored),
+            CompareFunc::Always => _mm256_castsi256_ps(_mm256_set1_epi32(-1)),
+        };
+        let passed = _mm256_and_ps(_mm256_castsi256_ps(covered), test);
+        fragments.passed = _mm256_movemask_ps(passed) as u32;
+        if depth_state.write && fragments.passed != 0 {
+            _mm256_maskstore_ps(depth.as_mut_ptr(), _mm256_castps_si256(passed), zdepth);
+        }
+        fragments
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn interpolate_avx2(
+        weights: &[[f32; SPAN_LANES]; 3],
+        a: [f32; 3],
+    ) -> [f32; SPAN_LANES] {
+        let term =
+            |k: usize| _mm256_mul_ps(_mm256_loadu_ps(weights[k].as_ptr()), _mm256_set1_ps(a[k]));
+        let mut out = [0.; SPAN_LANES];
+        _mm256_storeu_ps(
+            out.as_mut_ptr(),
+            _mm256_add_ps(_mm256_add_ps(term(0), term(1)), term(2)),
+        );
+        out
+    }
+
+    // SSE2 only has 2 f64 lanes, so this works on a quarter of the span at a time
+    #[inline]
+    #[target_feature(enable = "sse2")]
+    unsafe fn edge_lanes_sse2(edges: &SpanEdges, k: usize, first: f64) -> (i32, __m128) {
+        let lane_index = _mm_setr_pd(first, first + 1.);
+        let step = _mm_set1_pd(edges.step[k] as f64);
+        let w = _mm_set1_pd(edges.w[k] as f64);
+        let w_lo = _mm_add_pd(w, _mm_mul_pd(lane_index, step));
+        let w_hi = _mm_add_pd(w, _mm_mul_pd(_mm_add_pd(lane_index, _mm_set1_pd(2.)), step));
+        let min = _mm_set1_pd(-edges.bias[k] as f64);
+        let covered = _mm_movemask_pd(_mm_cmpge_pd(w_lo, min))
+            | _mm_movemask_pd(_mm_cmpge_pd(w_hi, min)) << 2;
+        (
+            covered,
+            _mm_movelh_ps(_mm_cvtpd_ps(w_lo), _mm_cvtpd_ps(w_hi)),
+        )
+    }
+
+    #[inline]
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn span_fragments_sse2(
+        edges: &SpanEdges,
+        len: usize,
+        inside: bool,
+        inv_area: f32,
+        z: [f32; 3],
+        depth_state: SpanDepthState,
+        depth: &mut [f32],
+    ) -> SpanFragments {
+        let mut fragments = SpanFragments::default();
+        let mut stored = [0.; SPAN_LANES];
+        stored[..len].copy_from_slice(&depth[..len]);
+        let inv_area = _mm_set1_ps(inv_area);
+        let lane_bits = _mm_setr_epi32(1, 2, 4, 8);
+
+        for offset in [0, 4] {
+            let mut covered_bits = ((1u32 << len) - 1) >> offset & 0xf;
+            let mut l = [_mm_setzero_ps(); 3];
+            for (k, l) in l.iter_mut().enumerate() {
+                let (covered, w) = edge_lanes_sse2(edges, k, offset as f64);
+                if !inside {
+                    covered_bits &= covered as u32;
+                }
+                *l = _mm_mul_ps(w, inv_area);
+                _mm_storeu_ps(fragments.weights[k][offset..].as_mut_ptr(), *l);
+            }
+            if covered_bits == 0 {
+                continue;
+            }
+            fragments.covered |= covered_bits << offset;
+            let covered = _mm_castsi128_ps(_mm_cmpeq_epi32(
+                _mm_and_si128(_mm_set1_epi32(covered_bits as i32), lane_bits),
+                lane_bits,
+            ));
+
+            let zdepth = _mm_add_ps(
+                _mm_add_ps(
+                    _mm_mul_ps(l[0], _mm_set1_ps(z[0])),
+                    _mm_mul_ps(l[1], _mm_set1_ps(z[1])),
+                ),
+                _mm_mul_ps(l[2], _mm_set1_ps(z[2])),
+            );
+            let stored_v = _mm_loadu_ps(stored[offset..].as_ptr());
+            let test = match depth_state.func {
+                CompareFunc::Never => _mm_setzero_ps(),
+                CompareFunc::Less => _mm_cmplt_ps(zdepth, stored_v),
+                CompareFunc::Equal => _mm_cmpeq_ps(zdepth, stored_v),
+                CompareFunc::LessEqual => _mm_cmple_ps(zdepth, stored_v),
+                CompareFunc::Greater => _mm_cmpgt_ps(zdepth, stored_v),
+                CompareFunc::NotEqual => _mm_cmpneq_ps(zdepth, stored_v),
+                CompareFunc::GreaterEqual => _mm_cmpge_ps(zdepth, stored_v),
+                CompareFunc::Always => _mm_castsi128_ps(_mm_set1_epi32(-1)),
+            };
+            let passed = _mm_and_ps(covered, test);
+            let passed_bits = _mm_movemask_ps(passed) as u32;
+            fragments.passed |= passed_bits << offset;
+            if depth_state.write && passed_bits != 0 {
+                // no blendv before SSE4.1
+                let blended =
+                    _mm_or_ps(_mm_and_ps(passed, zdepth), _mm_andnot_ps(passed, stored_v));
+                _mm_storeu_ps(stored[offset..].as_mut_ptr(), blended);
+            }
+        }
+        if depth_state.write && fragments.passed != 0 {
+            depth[..len].copy_from_slice(&stored[..len]);
+        }
+        fragments
+    }
+
+    #[inline]
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn interpolate_sse2(
+        weights: &[[f32; SPAN_LANES]; 3],
+        a: [f32; 3],
+    ) -> [f32; SPAN_LANES] {
+        let mut out = [0.; SPAN_LANES];
+        for offset in [0, 4] {
+            let term = |k: usize| {
+                _mm_mul_ps(
+                    _mm_loadu_ps(weights[k][offset..].as_ptr()),
+                    _mm_set1_ps(a[k]),
+                )
+            };
+            _mm_storeu_ps(
+                out[offset..].as_mut_ptr(),
+                _mm_add_ps(_mm_add_ps(term(0), term(1)), term(2)),
+            );
+        }
+        out
+    }
+}
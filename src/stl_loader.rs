@@ -0,0 +1,134 @@
+use crate::LoadError;
+use crate::ObjData;
+use crate::Submesh;
+use cgmath::point3;
+use cgmath::Point3;
+use std::path::Path;
+
+// Loads an ASCII or binary .stl file, with a submesh for each solid in an ASCII file.
+//
+// STL only stores a normal per facet (often left as zero), which is no better than the face
+// normal that flat shading works out anyway, so the model has no vertex normals.
+pub fn load_stl(path: &str) -> Result<ObjData, LoadError> {
+    let path = Path::new(path);
+    let bytes = std::fs::read(path).map_err(|error| LoadError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    // binary files can start with "solid" too, but their size always matches their triangle count
+    // and they're rarely valid UTF-8
+    let binary_size = bytes
+        .get(80..84)
+        .map(|count| 84 + 50 * u32::from_le_bytes(count.try_into().unwrap()) as usize);
+    let solids = match std::str::from_utf8(&bytes) {
+        Ok(text) if text.starts_with("solid") && binary_size != Some(bytes.len()) => {
+            parse_ascii(text)
+        }
+        _ => parse_binary(&bytes),
+    }
+    .map_err(|e| LoadError::invalid(path, e))?;
+
+    let mut tri_positions = Vec::new();
+    let mut ranges = Vec::new();
+    for (name, triangles) in solids {
+        let start = tri_positions.len();
+        tri_positions.extend(triangles);
+        if tri_positions.len() > start {
+            ranges.push((name, start..tri_positions.len()));
+        }
+    }
+    if tri_positions.is_empty() {
+        return Err(LoadError::invalid(path, "there are no triangles"));
+    }
+    let submeshes = ranges
+        .into_iter()
+        .map(|(name, triangles)| Submesh::new(&name, triangles, &tri_positions))
+        .collect();
+    Ok(ObjData::from_triangles(
+        tri_positions,
+        None,
+        None,
+        Vec::new(),
+        submeshes,
+    ))
+}
+
+type Solid = (String, Vec<[Point3<f32>; 3]>);
+
+// 80 byte header, triangle count, then for each triangle a normal, three vertices and two
+// attribute bytes
+fn parse_binary(bytes: &[u8]) -> Result<Vec<Solid>, String> {
+    let count = bytes
+        .get(80..84)
+        .ok_or("file is too short to be a binary STL")?;
+    let count = u32::from_le_bytes(count.try_into().unwrap()) as usize;
+    let triangles = bytes[84..].chunks_exact(50);
+    if triangles.len() < count {
+        return Err(format!(
+            "header says there are {} triangles but there's only room for {}",
+            count,
+            triangles.len()
+        ));
+    }
+    let float = |b: &[u8], i: usize| f32::from_le_bytes(b[i * 4..i * 4 + 4].try_into().unwrap());
+    let triangles = triangles
+        .take(count)
+        .map(|t| {
+            // skipping the normal
+            [3, 6, 9].map(|i| point3(float(t, i), float(t, i + 1), float(t, i + 2)))
+        })
+        .collect();
+    Ok(vec![("default".to_string(), triangles)])
+}
+
+//  solid name
+//    facet normal nx ny nz
+//      outer loop
+//        vertex x y z
+//        vertex x y z
+//        vertex x y z
+//      endloop
+//    endfacet
+//  endsolid name
+fn parse_ascii(text: &str) -> Result<Vec<Solid>, String> {
+    let mut solids: Vec<Solid> = Vec::new();
+    let mut vertices = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let error = |message: &str| format!("line {}: {}", i + 1, message);
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("solid") => {
+                let name = words.collect::<Vec<_>>().join(" ");
+                let name = if name.is_empty() {
+                    "default".to_string()
+                } else {
+                    name
+                };
+                solids.push((name, Vec::new()));
+            }
+            Some("vertex") => {
+                let coords: Vec<f32> = words
+                    .map(|word| word.parse())
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| error("vertex coordinates have to be numbers"))?;
+                let [x, y, z] = coords[..] else {
+                    return Err(error("vertices need 3 coordinates"));
+                };
+                vertices.push(point3(x, y, z));
+            }
+            Some("endloop") => {
+                let solid = solids
+                    .last_mut()
+                    .ok_or_else(|| error("facet outside of a solid"))?;
+                let [a, b, c] = vertices[..] else {
+                    return Err(error("facets need 3 vertices"));
+                };
+                solid.1.push([a, b, c]);
+                vertices.clear();
+            }
+            Some("facet" | "outer" | "endfacet" | "endsolid") | None => {}
+            Some(word) => return Err(error(&format!("unexpected \"{}\"", word))),
+        }
+    }
+    Ok(solids)
+}
@@ -0,0 +1,103 @@
+use crate::BitmapFont;
+use crate::Color;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Clone)]
+pub struct TextStyle {
+    pub color: Color,
+    // every font pixel becomes scale x scale pixels
+    pub scale: usize,
+    // which part of each line x is
+    pub align: TextAlign,
+    // fills the box around the text first, to keep it readable over anything
+    pub background: Option<Color>,
+}
+
+impl Default for TextStyle {
+    fn default() -> TextStyle {
+        TextStyle {
+            color: Color::new(1., 1., 1.),
+            scale: 1,
+            align: TextAlign::Left,
+            background: None,
+        }
+    }
+}
+
+// Draws text, which can have several lines, into a buffer of 0xRRGGBB pixels laid out like
+// Rasteriser::buffer. (x, y) is in pixels from the top left, y being the top of the first line.
+// Anything outside the buffer is clipped.
+#[allow(clippy::too_many_arguments)]
+pub fn draw_text(
+    buffer: &mut [u32],
+    width: usize,
+    height: usize,
+    font: &BitmapFont,
+    text: &str,
+    x: i32,
+    y: i32,
+    style: &TextStyle,
+) {
+    let scale = style.scale.max(1);
+    let mut fill = |x: i32, y: i32, w: usize, h: usize, color: u32| {
+        let x_range = x.max(0)..(x + w as i32).min(width as i32);
+        for py in y.max(0)..(y + h as i32).min(height as i32) {
+            let row = py as usize * width;
+            for px in x_range.clone() {
+                buffer[row + px as usize] = color;
+            }
+        }
+    };
+    // where the line starts for the alignment
+    let line_x = |line: &str| {
+        let line_width = (font.line_width(line) * scale) as i32;
+        match style.align {
+            TextAlign::Left => x,
+            TextAlign::Center => x - line_width / 2,
+            TextAlign::Right => x - line_width,
+        }
+    };
+
+    if let Some(background) = style.background {
+        let (text_width, text_height) = font.measure(text);
+        let left = text.lines().map(line_x).min().unwrap_or(x);
+        // a font pixel of padding all round
+        fill(
+            left - scale as i32,
+            y - scale as i32,
+            (text_width + 2) * scale,
+            (text_height + 2) * scale,
+            background.get_pixel_color(),
+        );
+    }
+
+    let color = style.color.get_pixel_color();
+    for (i, line) in text.lines().enumerate() {
+        let mut pen_x = line_x(line);
+        let top = y + (i * font.line_height * scale) as i32;
+        for glyph in line.chars().filter_map(|c| font.glyph(c)) {
+            let glyph_x = pen_x + glyph.x_offset * scale as i32;
+            let glyph_y = top + glyph.y_offset * scale as i32;
+            for gy in 0..glyph.height {
+                for gx in 0..glyph.width {
+                    if glyph.pixels[gy * glyph.width + gx] {
+                        fill(
+                            glyph_x + (gx * scale) as i32,
+                            glyph_y + (gy * scale) as i32,
+                            scale,
+                            scale,
+                            color,
+                        );
+                    }
+                }
+            }
+            pen_x += (glyph.advance * scale) as i32;
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::sync::Arc;
+
+// What happens to texture coordinates outside of 0..1
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WrapMode {
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
+}
+
+impl WrapMode {
+    // Texel index along an axis of the given size
+    #[inline(always)]
+    pub fn apply(self, i: i32, size: usize) -> usize {
+        let size = size as i32;
+        let i = match self {
+            WrapMode::Repeat => i.rem_euclid(size),
+            WrapMode::MirroredRepeat => {
+                let i = i.rem_euclid(2 * size);
+                if i < size {
+                    i
+                } else {
+                    2 * size - 1 - i
+                }
+            }
+            WrapMode::ClampToEdge => i.clamp(0, size - 1),
+        };
+        i as usize
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureFilter {
+    Nearest,
+    // bilinear, there are no mipmaps
+    Linear,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sampler {
+    // along u and v
+    pub wrap_s: WrapMode,
+    pub wrap_t: WrapMode,
+    pub filter: TextureFilter,
+}
+
+impl Default for Sampler {
+    fn default() -> Sampler {
+        Sampler {
+            wrap_s: WrapMode::Repeat,
+            wrap_t: WrapMode::Repeat,
+            filter: TextureFilter::Nearest,
+        }
+    }
+}
+
+// Decoded images are (RGBA bytes with the origin bottom left, width, height), the layout textures
+// are sampled in
+pub type Image = (Arc<Vec<u8>>, usize, usize);
+
+// Loads a png from a file, see decode_png()
+pub fn load_png(path: &Path) -> Result<Image, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    decode_png(&bytes).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// Writes an image as a png, the way decode_png() would read it back
+pub fn save_png(path: &Path, (pixels, width, height): &Image) -> std::io::Result<()> {
+    let mut rgb = Vec::with_capacity(width * height * 3);
+    // top row first
+    for y in (0..*height).rev() {
+        for x in 0..*width {
+            let idx = (x + y * width) * 4;
+            rgb.extend_from_slice(&pixels[idx..idx + 3]);
+        }
+    }
+    write_png(path, *width, *height, &rgb)
+}
+
+// Writes 8 bit RGB pixels, top row first, to an RGB png
+pub fn write_png(path: &Path, width: usize, height: usize, rgb: &[u8]) -> std::io::Result<()> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(std::io::Error::other)?;
+    writer
+        .write_image_data(rgb)
+        .map_err(std::io::Error::other)?;
+    writer.finish().map_err(std::io::Error::other)
+}
+
+// Decodes a png or jpeg, telling them apart by their first bytes
+pub fn decode_image(bytes: &[u8]) -> Result<Image, String> {
+    if bytes.starts_with(b"\x89PNG") {
+        decode_png(bytes)
+    } else if bytes.starts_with(&[0xff, 0xd8]) {
+        decode_jpeg(bytes)
+    } else {
+        Err("image isn't a png or jpeg".to_string())
+    }
+}
+
+// Any png, with palettes expanded and 16 bit channels cut down to 8
+pub fn decode_png(bytes: &[u8]) -> Result<Image, String> {
+    let mut decoder = png::Decoder::new(bytes);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+    // Allocate the output buffer.
+    let mut buf = vec![0; reader.output_buffer_size()];
+    // Read the next frame. An APNG might contain multiple frames.
+    let png_info = reader.next_frame(&mut buf).map_err(|e| e.to_string())?;
+    let channels = match png_info.color_type {
+        png::ColorType::Grayscale => 1,
+        png::ColorType::GrayscaleAlpha => 2,
+        png::ColorType::Rgb => 3,
+        png::ColorType::Rgba => 4,
+        png::ColorType::Indexed => unreachable!("palettes are expanded"),
+    };
+    let (width, height) = (png_info.width as usize, png_info.height as usize);
+    Ok(flip_to_rgba(&buf, width, height, png_info.line_size, |p| {
+        let p = &p[..channels];
+        match channels {
+            1 | 2 => [p[0], p[0], p[0]],
+            _ => [p[0], p[1], p[2]],
+        }
+    }))
+}
+
+pub fn decode_jpeg(bytes: &[u8]) -> Result<Image, String> {
+    let mut decoder = jpeg_decoder::Decoder::new(bytes);
+    let pixels = decoder.decode().map_err(|e| e.to_string())?;
+    let info = decoder.info().unwrap();
+    let (width, height) = (info.width as usize, info.height as usize);
+    let image = match info.pixel_format {
+        jpeg_decoder::PixelFormat::L8 => {
+            flip_to_rgba(&pixels, width, height, width, |p| [p[0], p[0], p[0]])
+        }
+        // big endian, so the first byte is the top 8 bits
+        jpeg_decoder::PixelFormat::L16 => {
+            flip_to_rgba(&pixels, width, height, width * 2, |p| [p[0], p[0], p[0]])
+        }
+        jpeg_decoder::PixelFormat::RGB24 => {
+            flip_to_rgba(&pixels, width, height, width * 3, |p| [p[0], p[1], p[2]])
+        }
+        jpeg_decoder::PixelFormat::CMYK32 => flip_to_rgba(&pixels, width, height, width * 4, |p| {
+            let k = 255 - p[3] as u32;
+            [p[0], p[1], p[2]].map(|c| ((255 - c as u32) * k / 255) as u8)
+        }),
+    };
+    Ok(image)
+}
+
+// Turns rows of pixels, top row first, into RGBA bottom row first. rgb gets handed the bytes
+// starting at each pixel.
+fn flip_to_rgba(
+    pixels: &[u8],
+    width: usize,
+    height: usize,
+    row_size: usize,
+    rgb: impl Fn(&[u8]) -> [u8; 3],
+) -> Image {
+    let pixel_size = row_size / width.max(1);
+    let mut bytes = vec![0; width * height * 4];
+    // this makes origin bottom left instead of top left
+    for y in 0..height {
+        let row = &pixels[(height - y - 1) * row_size..];
+        for x in 0..width {
+            let idx = x * 4 + y * width * 4;
+            bytes[idx..idx + 3].copy_from_slice(&rgb(&row[x * pixel_size..]));
+        }
+    }
+    (Arc::new(bytes), width, height)
+}
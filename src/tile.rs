@@ -0,0 +1,228 @@
+use crate::DepthBounds;
+use crate::TriangleSetup;
+use crate::BLOCK_SIZE;
+
+// Width and height of the screen tiles triangles get binned into. A multiple of BLOCK_SIZE, so the
+// blocks walked inside a tile are the same ones that get walked across the whole screen.
+pub const TILE_SIZE: i32 = 8 * BLOCK_SIZE;
+
+// A rectangle of samples that gets rasterised into, either the whole render target or one tile's
+// copy of it. Like the framebuffer, rows are stored top to bottom while y points up.
+pub struct SampleTarget<'a> {
+    pub x0: i32,
+    pub y0: i32,
+    pub width: usize,
+    pub height: usize,
+    pub samples: usize,
+    pub color: &'a mut [u32],
+    pub depth: &'a mut [f32],
+    pub stencil: &'a mut [u8],
+    // one per BLOCK_SIZE x BLOCK_SIZE block, bottom row first
+    pub hiz: &'a mut [DepthBounds],
+}
+
+impl<'a> SampleTarget<'a> {
+    // Index of the first sample of the pixel at (x, y) in screen space
+    #[inline(always)]
+    pub fn sample_index(&self, x: i32, y: i32) -> usize {
+        let row = (self.y0 + self.height as i32 - 1 - y) as usize;
+        (row * self.width + (x - self.x0) as usize) * self.samples
+    }
+
+    #[inline(always)]
+    fn block_index(&self, x: i32, y: i32) -> usize {
+        let blocks_x = self.width.div_ceil(BLOCK_SIZE as usize);
+        let (bx, by) = ((x - self.x0) / BLOCK_SIZE, (y - self.y0) / BLOCK_SIZE);
+        by as usize * blocks_x + bx as usize
+    }
+
+    // Depth bounds of the block holding pixel (x, y), recalculated if it has been written to
+    pub fn depth_bounds(&mut self, x: i32, y: i32) -> DepthBounds {
+        let index = self.block_index(x, y);
+        if self.hiz[index].dirty {
+            let bx0 = x - (x - self.x0) % BLOCK_SIZE;
+            let by0 = y - (y - self.y0) % BLOCK_SIZE;
+            let bx1 = (bx0 + BLOCK_SIZE).min(self.x0 + self.width as i32);
+            let by1 = (by0 + BLOCK_SIZE).min(self.y0 + self.height as i32);
+            let mut bounds = DepthBounds {
+                min: f32::INFINITY,
+                max: f32::NEG_INFINITY,
+                dirty: false,
+            };
+            for y in by0..by1 {
+                let start = self.sample_index(bx0, y);
+                let end = start + (bx1 - bx0) as usize * self.samples;
+                for &depth in &self.depth[start..end] {
+                    bounds.min = bounds.min.min(depth);
+                    bounds.max = bounds.max.max(depth);
+                }
+            }
+            self.hiz[index] = bounds;
+        }
+        self.hiz[index]
+    }
+
+    // Has to be called after changing any depth in the block holding pixel (x, y)
+    #[inline(always)]
+    pub fn mark_depth_written(&mut self, x: i32, y: i32) {
+        let index = self.block_index(x, y);
+        self.hiz[index].dirty = true;
+    }
+}
+
+// A tile with its own color, depth and stencil buffers, so that tiles can be rasterised in
+// parallel without sharing anything
+pub struct Tile {
+    pub x0: i32,
+    pub y0: i32,
+    pub width: usize,
+    pub height: usize,
+    color: Vec<u32>,
+    depth: Vec<f32>,
+    stencil: Vec<u8>,
+    hiz: Vec<DepthBounds>,
+}
+
+impl Tile {
+    fn new(x0: i32, y0: i32, width: usize, height: usize, samples: usize) -> Tile {
+        let size = width * height * samples;
+        let blocks = width.div_ceil(BLOCK_SIZE as usize) * height.div_ceil(BLOCK_SIZE as usize);
+        Tile {
+            x0,
+            y0,
+            width,
+            height,
+            color: vec![0; size],
+            depth: vec![0.; size],
+            stencil: vec![0; size],
+            hiz: vec![DepthBounds::new(0.); blocks],
+        }
+    }
+
+    pub fn target(&mut self, samples: usize) -> SampleTarget<'_> {
+        SampleTarget {
+            x0: self.x0,
+            y0: self.y0,
+            width: self.width,
+            height: self.height,
+            samples,
+            color: &mut self.color,
+            depth: &mut self.depth,
+            stencil: &mut self.stencil,
+            hiz: &mut self.hiz,
+        }
+    }
+
+    // Ranges of the tile's rows in (tile, full screen) sample buffers
+    fn rows(
+        &self,
+        screen: &SampleTarget,
+    ) -> impl Iterator<Item = (std::ops::Range<usize>, std::ops::Range<usize>)> {
+        let (x0, top) = (self.x0 as usize, self.y0 as usize + self.height);
+        let (screen_width, screen_height, samples) = (screen.width, screen.height, screen.samples);
+        let row_len = self.width * samples;
+        (0..self.height).map(move |row| {
+            let screen_row = screen_height - top + row;
+            let start = (screen_row * screen_width + x0) * samples;
+            (row * row_len..(row + 1) * row_len, start..start + row_len)
+        })
+    }
+
+    // Ranges of the tile's rows of blocks in (tile, full screen) hierarchical z buffers
+    fn block_rows(
+        &self,
+        screen: &SampleTarget,
+    ) -> impl Iterator<Item = (std::ops::Range<usize>, std::ops::Range<usize>)> {
+        let block_size = BLOCK_SIZE as usize;
+        let screen_blocks_x = screen.width.div_ceil(block_size);
+        let blocks_x = self.width.div_ceil(block_size);
+        let (bx0, by0) = (self.x0 as usize / block_size, self.y0 as usize / block_size);
+        (0..self.height.div_ceil(block_size)).map(move |row| {
+            let start = (by0 + row) * screen_blocks_x + bx0;
+            (
+                row * blocks_x..(row + 1) * blocks_x,
+                start..start + blocks_x,
+            )
+        })
+    }
+
+    // Copies the tile's part of the full screen buffers into it
+    pub fn load(&mut self, screen: &SampleTarget) {
+        for (tile, screen_range) in self.rows(screen) {
+            self.color[tile.clone()].copy_from_slice(&screen.color[screen_range.clone()]);
+            self.depth[tile.clone()].copy_from_slice(&screen.depth[screen_range.clone()]);
+            self.stencil[tile].copy_from_slice(&screen.stencil[screen_range]);
+        }
+        for (tile, screen_range) in self.block_rows(screen) {
+            self.hiz[tile].copy_from_slice(&screen.hiz[screen_range]);
+        }
+    }
+
+    // Copies the tile back into the full screen buffers
+    pub fn store(&self, screen: &mut SampleTarget) {
+        for (tile, screen_range) in self.rows(screen) {
+            screen.color[screen_range.clone()].copy_from_slice(&self.color[tile.clone()]);
+            screen.depth[screen_range.clone()].copy_from_slice(&self.depth[tile.clone()]);
+            screen.stencil[screen_range].copy_from_slice(&self.stencil[tile]);
+        }
+        for (tile, screen_range) in self.block_rows(screen) {
+            screen.hiz[screen_range].copy_from_slice(&self.hiz[tile]);
+        }
+    }
+}
+
+// The screen split up into tiles, each with a bin of the triangles overlapping it
+pub struct TileGrid {
+    pub tiles_x: usize,
+    pub tiles_y: usize,
+    pub tiles: Vec<Tile>,
+    // indices of triangles in submission order, which keeps depth ties and stencil updates the
+    // same as drawing them one after another
+    pub bins: Vec<Vec<u32>>,
+}
+
+impl TileGrid {
+    pub fn new(width: usize, height: usize, samples: usize) -> TileGrid {
+        let tile_size = TILE_SIZE as usize;
+        let tiles_x = width.div_ceil(tile_size);
+        let tiles_y = height.div_ceil(tile_size);
+        let mut tiles = Vec::with_capacity(tiles_x * tiles_y);
+        for ty in 0..tiles_y {
+            for tx in 0..tiles_x {
+                let (x0, y0) = (tx * tile_size, ty * tile_size);
+                tiles.push(Tile::new(
+                    x0 as i32,
+                    y0 as i32,
+                    tile_size.min(width - x0),
+                    tile_size.min(height - y0),
+                    samples,
+                ));
+            }
+        }
+        TileGrid {
+            tiles_x,
+            tiles_y,
+            bins: vec![Vec::new(); tiles.len()],
+            tiles,
+        }
+    }
+
+    pub fn clear_bins(&mut self) {
+        for bin in self.bins.iter_mut() {
+            bin.clear();
+        }
+    }
+
+    // Adds the triangle to the bin of every tile its bounding box overlaps
+    pub fn bin(&mut self, index: u32, setup: &TriangleSetup) {
+        let tile_x0 = (setup.min_x / TILE_SIZE) as usize;
+        let tile_y0 = (setup.min_y / TILE_SIZE) as usize;
+        let tile_x1 = ((setup.max_x / TILE_SIZE) as usize).min(self.tiles_x - 1);
+        let tile_y1 = ((setup.max_y / TILE_SIZE) as usize).min(self.tiles_y - 1);
+        for ty in tile_y0..=tile_y1 {
+            for tx in tile_x0..=tile_x1 {
+                self.bins[ty * self.tiles_x + tx].push(index);
+            }
+        }
+    }
+}
@@ -0,0 +1,322 @@
+use cgmath::Point3;
+
+// Screen space positions are snapped to a 24.8 fixed point grid before rasterising. Every edge
+// function is then evaluated exactly with integer maths, so two triangles sharing an edge always
+// agree on which side of it a pixel is.
+pub const SUBPIXEL_BITS: u32 = 8;
+pub const SUBPIXEL_SCALE: f32 = (1 << SUBPIXEL_BITS) as f32;
+const SUBPIXEL_HALF: i64 = 1 << (SUBPIXEL_BITS - 1);
+
+// Pixels are walked in aligned blocks of BLOCK_SIZE x BLOCK_SIZE so that blocks completely
+// inside or outside of the triangle can skip the per sample edge tests
+pub const BLOCK_SIZE: i32 = 8;
+
+// Vertices that project miles off screen (e.g. close to the camera plane) get clamped to this so
+// the edge function products can't overflow an i64
+const GUARD_BAND: i64 = 1 << 28;
+
+// Edge function of the directed edge a -> b, positive for points to the left of it
+#[derive(Clone, Copy, Debug)]
+pub struct EdgeFunction {
+    pub a_x: i64,
+    pub a_y: i64,
+    pub dx: i64,
+    pub dy: i64,
+    // 0 for top-left edges and -1 otherwise, so that points exactly on an edge are only owned by
+    // one of the triangles sharing it
+    pub bias: i64,
+}
+
+impl EdgeFunction {
+    fn new(a: (i64, i64), b: (i64, i64)) -> EdgeFunction {
+        let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+        // with counter-clockwise winding and y pointing up the inside of the triangle is on the
+        // left, so a top edge runs right to left and a left edge runs downwards
+        let is_top = dy == 0 && dx < 0;
+        let is_left = dy < 0;
+        EdgeFunction {
+            a_x: a.0,
+            a_y: a.1,
+            dx,
+            dy,
+            bias: if is_top || is_left { 0 } else { -1 },
+        }
+    }
+
+    // Unbiased value at the fixed point position (x, y)
+    #[inline(always)]
+    pub fn evaluate(&self, x: i64, y: i64) -> i64 {
+        self.dx * (y - self.a_y) - self.dy * (x - self.a_x)
+    }
+
+    // Change in value when moving one pixel right
+    #[inline(always)]
+    pub fn step_x(&self) -> i64 {
+        -self.dy << SUBPIXEL_BITS
+    }
+
+    // Change in value when moving one pixel up
+    #[inline(always)]
+    pub fn step_y(&self) -> i64 {
+        self.dx << SUBPIXEL_BITS
+    }
+
+    // Change in value between a pixel centre and a sample offset from it (in 1/16ths of a pixel)
+    #[inline(always)]
+    pub fn sample_delta(&self, offset: (i32, i32)) -> i64 {
+        let shift = SUBPIXEL_BITS - 4;
+        (self.dx * ((offset.1 as i64) << shift)) - (self.dy * ((offset.0 as i64) << shift))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockCoverage {
+    Outside,
+    Partial,
+    Inside,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TriangleSetup {
+    // edges[k] is the edge opposite vertex k, so its value divided by area is vertex k's
+    // barycentric weight
+    pub edges: [EdgeFunction; 3],
+    // twice the area in fixed point units, always positive
+    pub area: i64,
+    // positive for counter-clockwise triangles, used for culling
+    pub signed_area: f32,
+    // inclusive pixel bounds, clipped to the screen
+    pub min_x: i32,
+    pub min_y: i32,
+    pub max_x: i32,
+    pub max_y: i32,
+    // the triangle reaches past the edges of the screen, its bounds only cover the part on it
+    pub partly_offscreen: bool,
+}
+
+#[inline(always)]
+fn to_fixed(v: f32) -> i64 {
+    ((v * SUBPIXEL_SCALE).round() as i64).clamp(-GUARD_BAND, GUARD_BAND)
+}
+
+impl TriangleSetup {
+    // Returns None for degenerate triangles and ones that don't touch the screen. `pad` grows the
+    // pixel bounds for sample positions that aren't at the pixel centre.
+    pub fn new(
+        position: &[Point3<f32>; 3],
+        width: usize,
+        height: usize,
+        pad: i32,
+    ) -> Option<TriangleSetup> {
+        let v = position.map(|p| (to_fixed(p.x), to_fixed(p.y)));
+        let area = (v[1].0 - v[0].0) * (v[2].1 - v[0].1) - (v[1].1 - v[0].1) * (v[2].0 - v[0].0);
+        if area == 0 {
+            return None;
+        }
+
+        // clockwise triangles get two vertices swapped so the edges always wind counter-clockwise,
+        // the edge opposite each vertex stays the same
+        let (v1, v2) = if area > 0 { (1, 2) } else { (2, 1) };
+        let mut edges = [EdgeFunction::new(v[v1], v[v2]); 3];
+        edges[v1] = EdgeFunction::new(v[v2], v[0]);
+        edges[v2] = EdgeFunction::new(v[0], v[v1]);
+
+        // a pixel is sampled at its centre, x + 0.5
+        let to_pixel = |f: i64| ((f - SUBPIXEL_HALF) >> SUBPIXEL_BITS) as i32;
+        let min_x = to_pixel(v.iter().map(|p| p.0).min().unwrap());
+        let min_y = to_pixel(v.iter().map(|p| p.1).min().unwrap());
+        let max_x = to_pixel(v.iter().map(|p| p.0).max().unwrap());
+        let max_y = to_pixel(v.iter().map(|p| p.1).max().unwrap());
+        let setup = TriangleSetup {
+            edges,
+            area: area.abs(),
+            signed_area: area as f32,
+            min_x: (min_x - pad).max(0),
+            min_y: (min_y - pad).max(0),
+            max_x: (max_x + pad).min(width as i32 - 1),
+            max_y: (max_y + pad).min(height as i32 - 1),
+            partly_offscreen: min_x < 0
+                || min_y < 0
+                || max_x >= width as i32
+                || max_y >= height as i32,
+        };
+        if setup.min_x > setup.max_x || setup.min_y > setup.max_y {
+            return None;
+        }
+        Some(setup)
+    }
+
+    // The same triangle with its bounds limited to the inclusive pixel range, None if that doesn't
+    // overlap them
+    pub fn clip(&self, min_x: i32, min_y: i32, max_x: i32, max_y: i32) -> Option<TriangleSetup> {
+        let clipped = TriangleSetup {
+            min_x: self.min_x.max(min_x),
+            min_y: self.min_y.max(min_y),
+            max_x: self.max_x.min(max_x),
+            max_y: self.max_y.min(max_y),
+            ..*self
+        };
+        if clipped.min_x > clipped.max_x || clipped.min_y > clipped.max_y {
+            return None;
+        }
+        Some(clipped)
+    }
+
+    // Fixed point position of a sample, offset is in 1/16ths of a pixel from the pixel centre
+    #[inline(always)]
+    pub fn sample_position(x: i32, y: i32, offset: (i32, i32)) -> (i64, i64) {
+        let to_fixed = |p: i32, o: i32| {
+            ((p as i64) << SUBPIXEL_BITS) + SUBPIXEL_HALF + ((o as i64) << (SUBPIXEL_BITS - 4))
+        };
+        (to_fixed(x, offset.0), to_fixed(y, offset.1))
+    }
+
+    // Conservatively classifies the inclusive pixel range by testing the corners of its outer edge,
+    // which encloses every possible sample position. Edge functions are linear, so if all four
+    // corners are outside of one edge then so is everything between them.
+    pub fn classify_block(&self, x0: i32, y0: i32, x1: i32, y1: i32) -> BlockCoverage {
+        let (left, bottom) = ((x0 as i64) << SUBPIXEL_BITS, (y0 as i64) << SUBPIXEL_BITS);
+        let (right, top) = (
+            ((x1 as i64 + 1) << SUBPIXEL_BITS),
+            ((y1 as i64 + 1) << SUBPIXEL_BITS),
+        );
+        let mut inside = true;
+        for e in &self.edges {
+            let corners = [
+                e.evaluate(left, bottom),
+                e.evaluate(right, bottom),
+                e.evaluate(left, top),
+                e.evaluate(right, top),
+            ];
+            if corners.iter().all(|c| c + e.bias < 0) {
+                return BlockCoverage::Outside;
+            }
+            inside &= corners.iter().all(|c| c + e.bias >= 0);
+        }
+        if inside {
+            BlockCoverage::Inside
+        } else {
+            BlockCoverage::Partial
+        }
+    }
+
+    // Walks the blocks that aren't completely outside of the triangle, calling
+    // visit(ctx, x, y, len, w, inside) for each row of each block, where w holds the edge function
+    // values at the centre of pixel (x, y), the first of the len pixels in the row. inside is true
+    // if the whole block is known to be covered. Blocks where occluded(ctx, x, y) returns true for
+    // their first pixel are skipped. ctx is whatever both of them need mutable access to.
+    #[inline(always)]
+    pub fn rasterise_spans<C>(
+        &self,
+        ctx: &mut C,
+        mut occluded: impl FnMut(&mut C, i32, i32) -> bool,
+        mut visit: impl FnMut(&mut C, i32, i32, usize, [i64; 3], bool),
+    ) {
+        let step_y = self.edges.map(|e| e.step_y());
+        let block_x0 = self.min_x - self.min_x.rem_euclid(BLOCK_SIZE);
+        let block_y0 = self.min_y - self.min_y.rem_euclid(BLOCK_SIZE);
+        for by in (block_y0..=self.max_y).step_by(BLOCK_SIZE as usize) {
+            let (y0, y1) = (by.max(self.min_y), (by + BLOCK_SIZE - 1).min(self.max_y));
+            for bx in (block_x0..=self.max_x).step_by(BLOCK_SIZE as usize) {
+                let (x0, x1) = (bx.max(self.min_x), (bx + BLOCK_SIZE - 1).min(self.max_x));
+                if occluded(ctx, x0, y0) {
+                    continue;
+                }
+                let block = self.classify_block(x0, y0, x1, y1);
+                if block == BlockCoverage::Outside {
+                    continue;
+                }
+
+                let (px, py) = TriangleSetup::sample_position(x0, y0, (0, 0));
+                let mut row_start = self.edges.map(|e| e.evaluate(px, py));
+                for y in y0..=y1 {
+                    visit(
+                        ctx,
+                        x0,
+                        y,
+                        (x1 - x0 + 1) as usize,
+                        row_start,
+                        block == BlockCoverage::Inside,
+                    );
+                    row_start = [
+                        row_start[0] + step_y[0],
+                        row_start[1] + step_y[1],
+                        row_start[2] + step_y[2],
+                    ];
+                }
+            }
+        }
+    }
+
+    // Calls visit(ctx, x, y, coverage, weights) for every pixel with at least one covered sample,
+    // where bit s of coverage is set if sample s of the pattern is covered, and weights[s] holds
+    // the barycentrics of that sample. The edge functions are stepped incrementally rather than
+    // being evaluated from scratch at every sample. ctx and occluded work the same way as in
+    // rasterise_spans().
+    #[inline(always)]
+    pub fn rasterise<C>(
+        &self,
+        pattern: &[(i32, i32)],
+        ctx: &mut C,
+        occluded: impl FnMut(&mut C, i32, i32) -> bool,
+        mut visit: impl FnMut(&mut C, i32, i32, u32, &[[f32; 3]; 8]),
+    ) {
+        debug_assert!(pattern.len() <= 8);
+        let samples = pattern.len();
+        let full_coverage = (1u32 << samples) - 1;
+        let inv_area = 1. / self.area as f32;
+
+        let step_x = self.edges.map(|e| e.step_x());
+        let mut sample_deltas = [[0i64; 8]; 3];
+        for (k, e) in self.edges.iter().enumerate() {
+            for (s, &offset) in pattern.iter().enumerate() {
+                sample_deltas[k][s] = e.sample_delta(offset);
+            }
+        }
+        // a single centred sample means covered pixels in a row form one contiguous span, so the
+        // row can be abandoned as soon as we step out of it
+        let single_sample = samples == 1 && pattern[0] == (0, 0);
+
+        let mut weights = [[0f32; 3]; 8];
+        self.rasterise_spans(ctx, occluded, |ctx, x0, y, len, mut w, inside| {
+            let mut entered = false;
+            for x in x0..x0 + len as i32 {
+                let mut coverage = 0;
+                for s in 0..samples {
+                    let ws = [
+                        w[0] + sample_deltas[0][s],
+                        w[1] + sample_deltas[1][s],
+                        w[2] + sample_deltas[2][s],
+                    ];
+                    if inside
+                        || (ws[0] + self.edges[0].bias >= 0
+                            && ws[1] + self.edges[1].bias >= 0
+                            && ws[2] + self.edges[2].bias >= 0)
+                    {
+                        coverage |= 1 << s;
+                        weights[s] = ws.map(|w| w as f32 * inv_area);
+                    }
+                }
+                if coverage != 0 {
+                    entered = true;
+                    debug_assert!(coverage & !full_coverage == 0);
+                    visit(ctx, x, y, coverage, &weights);
+                } else if entered && single_sample {
+                    break;
+                }
+                w = [w[0] + step_x[0], w[1] + step_x[1], w[2] + step_x[2]];
+            }
+        });
+    }
+
+    // Barycentric weights of each vertex at a fixed point position, None if it isn't covered
+    #[inline(always)]
+    pub fn barycentrics(&self, x: i64, y: i64) -> Option<[f32; 3]> {
+        let w = self.edges.map(|e| e.evaluate(x, y));
+        if w.iter().zip(self.edges.iter()).any(|(w, e)| w + e.bias < 0) {
+            return None;
+        }
+        let area = self.area as f32;
+        Some(w.map(|w| w as f32 / area))
+    }
+}
@@ -0,0 +1,86 @@
+use base64::Engine;
+use gfxprogramming::*;
+use std::fs;
+
+// A triangle's positions followed by `normals` normals, as a .gltf with the buffer in a data URI.
+// nodes is the JSON of the nodes array, node 0 is the scene's root and has the mesh.
+fn gltf(normals: usize, nodes: &str) -> String {
+    let mut buffer = Vec::new();
+    for c in [0., 0., 0., 1., 0., 0., 0., 1., 0.] {
+        buffer.extend(f32::to_le_bytes(c));
+    }
+    for _ in 0..normals {
+        for c in [0., 0., 1.] {
+            buffer.extend(f32::to_le_bytes(c));
+        }
+    }
+    format!(
+        r#"{{
+            "asset": {{ "version": "2.0" }},
+            "scene": 0,
+            "scenes": [{{ "nodes": [0] }}],
+            "nodes": {nodes},
+            "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0, "NORMAL": 1 }} }}] }}],
+            "buffers": [{{
+                "byteLength": {length},
+                "uri": "data:application/octet-stream;base64,{data}"
+            }}],
+            "bufferViews": [
+                {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+                {{ "buffer": 0, "byteOffset": 36, "byteLength": {normals_length} }}
+            ],
+            "accessors": [
+                {{
+                    "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                    "min": [0, 0, 0], "max": [1, 1, 0]
+                }},
+                {{ "bufferView": 1, "componentType": 5126, "count": {normals}, "type": "VEC3" }}
+            ]
+        }}"#,
+        nodes = nodes,
+        length = buffer.len(),
+        data = base64::engine::general_purpose::STANDARD.encode(&buffer),
+        normals = normals,
+        normals_length = normals * 12,
+    )
+}
+
+fn load(name: &str, gltf: &str) -> Result<Scene, LoadError> {
+    let path = std::env::temp_dir().join(format!(
+        "gfxprogramming_{}_{}.gltf",
+        name,
+        std::process::id()
+    ));
+    fs::write(&path, gltf).unwrap();
+    let mut scene = Scene::new();
+    let loaded = load_gltf(&mut scene, path.to_str().unwrap(), None);
+    fs::remove_file(&path).unwrap();
+    loaded.map(|_| scene)
+}
+
+#[test]
+fn loads_a_triangle() {
+    let scene = load("triangle", &gltf(3, r#"[{ "mesh": 0 }]"#)).unwrap();
+    assert_eq!(scene.meshes.len(), 1);
+    assert_eq!(scene.meshes[0].len(), 1);
+    assert!(scene.meshes[0].tri_normals.is_some());
+}
+
+#[test]
+fn attribute_counts_have_to_match_the_positions() {
+    let error = load("normals", &gltf(1, r#"[{ "mesh": 0 }]"#))
+        .err()
+        .unwrap();
+    assert!(
+        error.to_string().contains("1 normals but 3 positions"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn cyclic_children_are_an_error() {
+    let nodes = r#"[{ "mesh": 0, "children": [1] }, { "children": [0] }]"#;
+    let error = load("cycle", &gltf(3, nodes)).err().unwrap();
+    assert!(error.to_string().contains("its own ancestor"), "{}", error);
+}
@@ -0,0 +1,92 @@
+use gfxprogramming::*;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+const SOURCE_OBJ: &str = "\
+mtllib source model.mtl
+v 0 0 0
+v 1 0 0
+v 0 1 0.5
+v 1.25 1 -0.1
+vt 0 0
+vt 1 0
+vt 0 1
+vt 0.75 0.125 0.5
+vn 0 0 1
+vn 0.6 0 0.8
+g front face
+usemtl warm
+f 1/1/1 2/2/1 3/3/2
+g back face
+usemtl cool
+f 2/2/2 4/4/2 3/3/1
+";
+
+const SOURCE_MTL: &str = "\
+newmtl warm
+Kd 1 0.5 0.25
+map_Kd -s 1 1 1 -clamp on check er.png
+newmtl cool
+Kd 0.1 0.75 1
+map_Kd -o 0.5 -mm 0 1 check er.png
+";
+
+// Loading a model, exporting it and loading the export gives back the same model, with spaces in
+// every file name along the way. The group names come back with underscores for their spaces.
+#[test]
+fn export_round_trips() {
+    let dir =
+        std::env::temp_dir().join(format!("gfxprogramming obj export {}", std::process::id()));
+    let source_dir = dir.join("source dir");
+    let export_dir = dir.join("export dir");
+    fs::create_dir_all(&source_dir).unwrap();
+    fs::create_dir_all(&export_dir).unwrap();
+
+    // a 2x2 checker, with alpha left at 0 like the pngs it's written to and read back from
+    let pixels = [[255, 0, 0], [0, 255, 0], [0, 0, 255], [255, 255, 255]]
+        .iter()
+        .flat_map(|&[r, g, b]| [r, g, b, 0])
+        .collect();
+    let texture: Image = (Arc::new(pixels), 2, 2);
+    save_png(&source_dir.join("check er.png"), &texture).unwrap();
+    fs::write(source_dir.join("source model.obj"), SOURCE_OBJ).unwrap();
+    fs::write(source_dir.join("source model.mtl"), SOURCE_MTL).unwrap();
+
+    let path = |dir: &Path, file: &str| dir.join(file).to_str().unwrap().to_string();
+    let source = ObjData::load(&path(&source_dir, "source model.obj")).unwrap();
+    export_obj(&source, &path(&export_dir, "exported model.obj")).unwrap();
+    let exported = ObjData::load(&path(&export_dir, "exported model.obj")).unwrap();
+
+    assert_eq!(exported.tri_positions, source.tri_positions);
+    assert_eq!(exported.tri_normals, source.tri_normals);
+    assert!(source.tri_texcoords.is_some());
+    assert_eq!(exported.tri_texcoords, source.tri_texcoords);
+    let colors = |obj: &ObjData| {
+        obj.submeshes
+            .iter()
+            .map(|submesh| {
+                (
+                    submesh.name.replace(' ', "_"),
+                    submesh.triangles.clone(),
+                    submesh.color.rgb(),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(colors(&exported), colors(&source));
+    let names: Vec<_> = exported.submeshes.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(names, ["front_face", "back_face"]);
+    let textures = |obj: &ObjData| {
+        obj.tri_textures
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|texture| (texture.texture.to_vec(), texture.width, texture.height))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(textures(&exported), textures(&source));
+    assert_eq!(textures(&source)[0].0, *texture.0);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,111 @@
+use gfxprogramming::*;
+use std::fs;
+
+// A convex pentagon with a color on each vertex, as one face
+const POSITIONS: [[f32; 3]; 5] = [
+    [0., 0., 0.],
+    [1., 0., 0.],
+    [1.5, 1., 0.25],
+    [0.5, 2., 0.5],
+    [-0.5, 1., 0.25],
+];
+const COLORS: [[u8; 3]; 5] = [
+    [255, 0, 0],
+    [0, 255, 0],
+    [0, 0, 255],
+    [255, 255, 0],
+    [51, 102, 204],
+];
+
+fn header(format: &str) -> String {
+    format!(
+        "ply\nformat {} 1.0\nelement vertex 5\nproperty float x\nproperty float y\n\
+         property float z\nproperty uchar red\nproperty uchar green\nproperty uchar blue\n\
+         element face 1\nproperty list uchar int vertex_indices\nend_header\n",
+        format
+    )
+}
+
+fn ascii() -> Vec<u8> {
+    let mut ply = header("ascii");
+    for ([x, y, z], [r, g, b]) in POSITIONS.iter().zip(COLORS) {
+        ply += &format!("{} {} {} {} {} {}\n", x, y, z, r, g, b);
+    }
+    ply += "5 0 1 2 3 4\n";
+    ply.into_bytes()
+}
+
+fn binary(format: &str, f32_bytes: fn(f32) -> [u8; 4], i32_bytes: fn(i32) -> [u8; 4]) -> Vec<u8> {
+    let mut ply = header(format).into_bytes();
+    for (position, color) in POSITIONS.iter().zip(COLORS) {
+        for &c in position {
+            ply.extend(f32_bytes(c));
+        }
+        ply.extend(color);
+    }
+    ply.push(5);
+    for i in 0..5 {
+        ply.extend(i32_bytes(i));
+    }
+    ply
+}
+
+fn load(name: &str, bytes: &[u8]) -> Result<ObjData, LoadError> {
+    let path = std::env::temp_dir().join(format!(
+        "gfxprogramming_{}_{}.ply",
+        name,
+        std::process::id()
+    ));
+    fs::write(&path, bytes).unwrap();
+    let obj = load_ply(path.to_str().unwrap());
+    fs::remove_file(&path).unwrap();
+    obj
+}
+
+fn colors(obj: &ObjData) -> Vec<[[f32; 3]; 3]> {
+    let tri_colors = obj.tri_colors.as_ref().unwrap();
+    tri_colors.iter().map(|t| t.map(|c| c.rgb())).collect()
+}
+
+#[test]
+fn faces_are_fanned() {
+    let obj = load("fan", &ascii()).unwrap();
+    let fan: Vec<_> = [[0, 1, 2], [0, 2, 3], [0, 3, 4]]
+        .iter()
+        .map(|t| t.map(|i| POSITIONS[i].into()))
+        .collect();
+    assert_eq!(obj.tri_positions, fan);
+    let color = |i: usize| COLORS[i].map(|c| c as f32 / 255.);
+    assert_eq!(
+        colors(&obj),
+        [[0, 1, 2], [0, 2, 3], [0, 3, 4]].map(|t| t.map(color))
+    );
+}
+
+#[test]
+fn binary_little_endian_matches_ascii() {
+    let ascii = load("ascii_le", &ascii()).unwrap();
+    let little = binary("binary_little_endian", f32::to_le_bytes, i32::to_le_bytes);
+    let little = load("little", &little).unwrap();
+    assert_eq!(little.tri_positions, ascii.tri_positions);
+    assert_eq!(colors(&little), colors(&ascii));
+}
+
+#[test]
+fn binary_big_endian_matches_ascii() {
+    let ascii = load("ascii_be", &ascii()).unwrap();
+    let big = binary("binary_big_endian", f32::to_be_bytes, i32::to_be_bytes);
+    let big = load("big", &big).unwrap();
+    assert_eq!(big.tri_positions, ascii.tri_positions);
+    assert_eq!(colors(&big), colors(&ascii));
+}
+
+#[test]
+fn nan_colors_are_an_error() {
+    let ply = "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\n\
+               property float z\nproperty float red\nproperty float green\nproperty float blue\n\
+               element face 1\nproperty list uchar int vertex_indices\nend_header\n\
+               0 0 0 nan 0 0\n1 0 0 0 1 0\n0 1 0 0 0 1\n3 0 1 2\n";
+    let error = load("nan", ply.as_bytes()).err().unwrap();
+    assert!(error.to_string().contains("bad color"), "{}", error);
+}
@@ -0,0 +1,198 @@
+use cgmath::point3;
+use cgmath::vec3;
+use cgmath::Point3;
+use gfxprogramming::*;
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+const WIDTH: usize = 150;
+const HEIGHT: usize = 110;
+
+// The point at z = -1 the default camera projects to the screen position (x, y)
+fn unproject(x: f32, y: f32) -> Point3<f32> {
+    let scale = HEIGHT as f32 / 2.;
+    point3(
+        (x - WIDTH as f32 / 2.) / scale,
+        (y - HEIGHT as f32 / 2.) / scale,
+        -1.,
+    )
+}
+
+// A fan of triangles around a pixel centre reaching well past the edges of the screen. Every
+// edge between them runs through pixel centres, which only the top-left rule can settle.
+fn fan() -> ObjData {
+    let directions = [
+        (1., 0.),
+        (2., 1.),
+        (1., 1.),
+        (1., 2.),
+        (0., 1.),
+        (-1., 2.),
+        (-1., 1.),
+        (-2., 1.),
+        (-1., 0.),
+        (-2., -1.),
+        (-1., -1.),
+        (-1., -2.),
+        (0., -1.),
+        (1., -2.),
+        (1., -1.),
+        (2., -1.),
+    ];
+    let (x, y) = (75.5, 54.5);
+    let ring: Vec<_> = directions
+        .iter()
+        .map(|(dx, dy)| unproject(x + dx * 100., y + dy * 100.))
+        .collect();
+    let tri_positions: Vec<_> = (0..ring.len())
+        .map(|i| [unproject(x, y), ring[i], ring[(i + 1) % ring.len()]])
+        .collect();
+    let submeshes = vec![Submesh::new("fan", 0..tri_positions.len(), &tri_positions)];
+    ObjData::from_triangles(tri_positions, None, None, Vec::new(), submeshes)
+}
+
+#[test]
+fn shared_edges_are_covered_exactly_once() {
+    for threads in [1, 4] {
+        for sample_count in [SampleCount::X1, SampleCount::X4] {
+            let mut r = Rasteriser::new(WIDTH, HEIGHT);
+            r.set_threads(threads);
+            r.set_sample_count(sample_count);
+            r.render_state.cull_mode = CullMode::None;
+            r.render_state.depth_func = CompareFunc::Always;
+            // counts the fragments in the stencil buffer
+            r.debug_view = DebugView::Overdraw;
+            r.scene.add_obj(fan(), "fan", None);
+            r.render_frame();
+
+            let samples = sample_count.samples();
+            let counts = r.stencil_buffer();
+            assert_eq!(counts.len(), WIDTH * HEIGHT * samples);
+            if let Some(i) = counts.iter().position(|&count| count != 1) {
+                let pixel = i / samples;
+                panic!(
+                    "sample {} of pixel ({}, {}) was covered {} times with {} threads",
+                    i % samples,
+                    pixel % WIDTH,
+                    pixel / WIDTH,
+                    counts[i],
+                    threads
+                );
+            }
+        }
+    }
+}
+
+// A textured, smooth shaded sphere
+fn sphere(centre: Point3<f32>, radius: f32, color: Color) -> ObjData {
+    let (stacks, slices) = (12, 16);
+    let vertex = |stack: usize, slice: usize| {
+        let (u, v) = (slice as f32 / slices as f32, stack as f32 / stacks as f32);
+        let (theta, phi) = (u * 2. * PI, v * PI);
+        let normal = vec3(phi.sin() * theta.cos(), phi.cos(), phi.sin() * theta.sin());
+        (centre + normal * radius, normal, point3(u, v, 0.))
+    };
+    let mut tri_positions = Vec::new();
+    let mut tri_normals = Vec::new();
+    let mut tri_texcoords = Vec::new();
+    for stack in 0..stacks {
+        for slice in 0..slices {
+            let quad = [
+                vertex(stack, slice),
+                vertex(stack + 1, slice),
+                vertex(stack + 1, slice + 1),
+                vertex(stack, slice + 1),
+            ];
+            for [a, b, c] in [[0, 1, 2], [0, 2, 3]] {
+                tri_positions.push([quad[a].0, quad[b].0, quad[c].0]);
+                tri_normals.push([quad[a].1, quad[b].1, quad[c].1]);
+                tri_texcoords.push([quad[a].2, quad[b].2, quad[c].2]);
+            }
+        }
+    }
+    // a 4x4 checker
+    let texture: Arc<Vec<u8>> = Arc::new(
+        (0..16)
+            .flat_map(|i| match (i % 4 + i / 4) % 2 {
+                0 => [255, 255, 255, 0],
+                _ => [40, 80, 160, 0],
+            })
+            .collect(),
+    );
+    let tri_textures = tri_texcoords
+        .into_iter()
+        .map(|points| TextureData::new(texture.clone(), 4, 4, points))
+        .collect();
+    let submeshes = vec![Submesh {
+        color,
+        ..Submesh::new("sphere", 0..tri_positions.len(), &tri_positions)
+    }];
+    ObjData::from_triangles(
+        tri_positions,
+        Some(tri_textures),
+        Some(tri_normals),
+        vec![texture],
+        submeshes,
+    )
+}
+
+// Overlapping spheres, one of them hanging off the bottom left of the screen
+fn spheres(threads: usize, sample_count: SampleCount) -> Rasteriser {
+    let mut r = Rasteriser::new(WIDTH, HEIGHT);
+    r.set_threads(threads);
+    r.set_sample_count(sample_count);
+    r.shading = TriangleShading::Gouraud;
+    for (centre, radius, color) in [
+        (point3(0., 0., -3.), 1., Color::new(1., 1., 1.)),
+        (point3(0.8, 0.3, -2.5), 0.6, Color::new(1., 0.5, 0.25)),
+        (point3(-1.6, -0.8, -2.), 0.7, Color::new(0.25, 1., 0.5)),
+    ] {
+        r.scene
+            .add_obj(sphere(centre, radius, color), "sphere", None);
+    }
+    r
+}
+
+#[test]
+fn threads_render_the_same_image() {
+    for sample_count in [SampleCount::X1, SampleCount::X4] {
+        let mut single = spheres(1, sample_count);
+        single.render_frame();
+        assert!(single.buffer.iter().any(|&pixel| pixel != 0));
+        for threads in [2, 3, 8] {
+            let mut tiled = spheres(threads, sample_count);
+            tiled.render_frame();
+            assert!(
+                single.buffer == tiled.buffer,
+                "{} threads with {:?} differ from 1",
+                threads,
+                sample_count
+            );
+        }
+    }
+}
+
+#[test]
+fn simd_levels_render_the_same_image() {
+    let levels = match SimdLevel::detect() {
+        SimdLevel::Scalar => vec![],
+        SimdLevel::Sse2 => vec![SimdLevel::Sse2],
+        SimdLevel::Avx2 => vec![SimdLevel::Sse2, SimdLevel::Avx2],
+    };
+    for sample_count in [SampleCount::X1, SampleCount::X4] {
+        let mut scalar = spheres(1, sample_count);
+        scalar.set_simd_level(SimdLevel::Scalar);
+        scalar.render_frame();
+        for &level in &levels {
+            let mut simd = spheres(1, sample_count);
+            simd.set_simd_level(level);
+            simd.render_frame();
+            assert!(
+                scalar.buffer == simd.buffer,
+                "{:?} with {:?} differs from scalar",
+                level,
+                sample_count
+            );
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use gfxprogramming::*;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+// A triangle with texture coordinates and no .mtl, so no texture of its own
+const TRIANGLE_OBJ: &str = "\
+v -1 -1 -3
+v 1 -1 -3
+v 0 1 -3
+vt 0 0
+vt 1 0
+vt 0.5 1
+f 1/1 2/2 3/3
+";
+
+// A directory of its own for each test, removed again when it's dropped
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> TempDir {
+        let dir = std::env::temp_dir().join(format!(
+            "gfxprogramming_scene_{}_{}",
+            name,
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+
+    fn write(&self, file: &str, contents: &str) -> String {
+        let path = self.0.join(file);
+        fs::write(&path, contents).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+// (line, column, message) of the error loading the scene
+fn error(dir: &TempDir, scene: &str) -> (usize, usize, String) {
+    let path = dir.write("scene.toml", scene);
+    match load_scene_file(&path).err().unwrap() {
+        SceneFileError::Invalid {
+            line,
+            column,
+            message,
+            ..
+        } => (line, column, message),
+        error => panic!("{}", error),
+    }
+}
+
+#[test]
+fn errors_point_at_the_value() {
+    let dir = TempDir::new("errors");
+    dir.write("triangle.obj", "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n");
+
+    let (line, column, message) = error(&dir, "[render]\nwidth = 64\nheight = ]\n");
+    assert_eq!((line, column), (3, 10), "{}", message);
+
+    let (line, column, message) = error(&dir, "[render]\nwidth = 0\n");
+    assert_eq!((line, column), (2, 9), "{}", message);
+
+    let (line, column, message) = error(&dir, "[[model]]\n  path = \"missing.obj\"\n");
+    assert_eq!((line, column), (2, 10), "{}", message);
+    assert!(message.contains("doesn't exist"), "{}", message);
+
+    // the triangle has no texture coordinates
+    let scene = "[[model]]\npath = \"triangle.obj\"\nmaterial = { texture = \"red.png\" }\n";
+    let (line, column, message) = error(&dir, scene);
+    assert_eq!((line, column), (3, 24), "{}", message);
+}
+
+#[test]
+fn textures_go_on_copies_of_the_meshes() {
+    let dir = TempDir::new("textures");
+    dir.write("triangle.obj", TRIANGLE_OBJ);
+    for (file, [r, g, b]) in [("red.png", [255, 0, 0]), ("blue.png", [0, 0, 255])] {
+        let image: Image = (Arc::new([r, g, b, 0].repeat(4)), 2, 2);
+        save_png(&dir.0.join(file), &image).unwrap();
+    }
+    let path = dir.write(
+        "scene.toml",
+        r#"
+            [[model]]
+            path = "triangle.obj"
+            name = "red"
+            material = { texture = "red.png" }
+
+            [[model]]
+            path = "triangle.obj"
+            name = "blue"
+            material = { texture = "blue.png" }
+
+            [[model]]
+            path = "triangle.obj"
+            name = "plain"
+        "#,
+    );
+    let r = load_scene_file(&path).unwrap_or_else(|e| panic!("{}", e));
+
+    // the mesh each model's nodes draw
+    let mesh = |name: &str| {
+        let meshes: Vec<usize> = r
+            .scene
+            .descendants(r.scene.find(name).unwrap())
+            .into_iter()
+            .filter_map(|id| r.scene.nodes[id].mesh.as_ref().map(|mesh| mesh.mesh))
+            .collect();
+        assert_eq!(meshes.len(), 1);
+        &r.scene.meshes[meshes[0]]
+    };
+    let texel = |name: &str| {
+        let tri_textures = mesh(name).tri_textures.as_ref().unwrap();
+        tri_textures[0].texture[..3].to_vec()
+    };
+    assert_eq!(texel("red"), [255, 0, 0]);
+    assert_eq!(texel("blue"), [0, 0, 255]);
+    assert!(mesh("plain").tri_textures.is_none());
+    assert!(!std::ptr::eq(mesh("red"), mesh("blue")));
+}